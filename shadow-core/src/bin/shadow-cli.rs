@@ -0,0 +1,158 @@
+//! Headless shadow analysis: runs the desktop app's pipeline from a config file and
+//! writes the masked results without opening a window.
+//!
+//! Usage: `shadow-cli <config.json|config.toml> [--format geotiff|csv] [--output PATH]`
+
+use shadow_core::pipeline::{self, PreparedEngine};
+use shadow_core::raster_io::RasterIO;
+use shadow_core::types::{CancellationToken, Config, ShadowError};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str =
+    "Usage: shadow-cli <config.json|config.toml> [--format geotiff|csv] [--output PATH]";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    GeoTiff,
+    Csv,
+}
+
+impl OutputFormat {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "geotiff" | "tif" | "tiff" => Some(Self::GeoTiff),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::GeoTiff => "tif",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+struct Args {
+    config_path: PathBuf,
+    format: Option<OutputFormat>,
+    output: Option<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut config_path = None;
+    let mut format = None;
+    let mut output = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            "-f" | "--format" => {
+                let name = args.next().ok_or("--format needs a value")?;
+                format = Some(
+                    OutputFormat::from_name(&name)
+                        .ok_or_else(|| format!("Unsupported format '{}'", name))?,
+                );
+            }
+            "-o" | "--output" => {
+                output = Some(PathBuf::from(args.next().ok_or("--output needs a path")?));
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown option '{}'", arg)),
+            _ if config_path.is_none() => config_path = Some(PathBuf::from(arg)),
+            _ => return Err(format!("Unexpected argument '{}'", arg)),
+        }
+    }
+
+    Ok(Args {
+        config_path: config_path.ok_or_else(|| USAGE.to_string())?,
+        format,
+        output,
+    })
+}
+
+/// Read a config as TOML when the file ends in `.toml`, as JSON otherwise
+fn read_config(path: &Path) -> Result<Config, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read config {:?}: {}", path, e))?;
+    let is_toml = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("toml"));
+    if is_toml {
+        toml::from_str(&text).map_err(|e| format!("Invalid TOML config: {}", e))
+    } else {
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON config: {}", e))
+    }
+}
+
+fn run(args: Args) -> Result<PathBuf, String> {
+    let config = read_config(&args.config_path)?;
+
+    // An explicit format wins; otherwise the output extension decides, then GeoTIFF
+    let format = args
+        .format
+        .or_else(|| {
+            args.output
+                .as_ref()
+                .and_then(|path| path.extension())
+                .and_then(|ext| OutputFormat::from_name(&ext.to_string_lossy()))
+        })
+        .unwrap_or(OutputFormat::GeoTiff);
+    let output = args.output.unwrap_or_else(|| {
+        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+        PathBuf::from(format!("shadows_{}.{}", timestamp, format.extension()))
+    });
+
+    println!("Starting shadow calculation with config: {:?}", config);
+    let PreparedEngine {
+        engine,
+        transform,
+        projection,
+        ..
+    } = pipeline::prepare_engine(&config, None, CancellationToken::new())?;
+    let mut results = engine.calculate_shadows().map_err(|e| match e {
+        ShadowError::Cancelled => "Shadow calculation cancelled".to_string(),
+        e => format!("Shadow calculation failed: {}", e),
+    })?;
+    pipeline::mask_results_to_aoi(&mut results, &config, &transform)?;
+
+    println!("Exporting to: {:?}", output);
+    match format {
+        OutputFormat::GeoTiff => {
+            pipeline::write_results_geotiff(&output, &results, &transform, &projection)
+                .map_err(|e| format!("Failed to write GeoTIFF: {}", e))?
+        }
+        OutputFormat::Csv => RasterIO::write_csv(
+            &output,
+            &results.shadow_fraction,
+            &results.timestamps,
+            &transform,
+        )
+        .map_err(|e| format!("Failed to write CSV: {}", e))?,
+    }
+
+    Ok(output)
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(args) {
+        Ok(output) => {
+            println!("Results written to: {}", output.display());
+            ExitCode::SUCCESS
+        }
+        Err(message) => {
+            eprintln!("Error: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
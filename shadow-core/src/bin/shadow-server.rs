@@ -0,0 +1,564 @@
+//! Local REST API over the shadow engine, for web tools and notebooks on the same
+//! machine. Calculations run as background jobs whose progress can be polled.
+//!
+//! Usage: `shadow-server [--port PORT] [--allow-origin ORIGIN]... [--keep-jobs N]`
+//! (listens on 127.0.0.1, default port 7878)
+
+use axum::extract::{Path as UrlPath, State};
+use axum::http::{header, HeaderValue, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use shadow_core::pipeline::{self, PreparedEngine};
+use shadow_core::progress::{ProgressSink, ProgressUpdate};
+use shadow_core::raster_io::RasterIO;
+use shadow_core::types::{CancellationToken, Config, ShadowError, ShadowResult};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+const DEFAULT_PORT: u16 = 7878;
+
+/// Finished jobs kept for their results before the oldest are dropped
+const DEFAULT_KEEP_JOBS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum JobStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// Masked results of a finished job, on the clipped grid
+struct JobOutput {
+    results: ShadowResult,
+    transform: [f64; 6],
+    projection: String,
+}
+
+struct Job {
+    status: JobStatus,
+    /// Latest update the engine reported, shared with the job's progress sink
+    progress: Arc<Mutex<Option<ProgressUpdate>>>,
+    cancel_token: CancellationToken,
+    error: Option<String>,
+    output: Option<Arc<JobOutput>>,
+}
+
+#[derive(Default)]
+struct ServerState {
+    jobs: Mutex<BTreeMap<u64, Job>>,
+    next_id: AtomicU64,
+    /// Numbers export files so concurrent exports of one job don't share a path
+    next_export: AtomicU64,
+    keep_jobs: usize,
+}
+
+impl ServerState {
+    fn new(keep_jobs: usize) -> Self {
+        Self {
+            keep_jobs,
+            ..Default::default()
+        }
+    }
+}
+
+/// Drop the oldest finished jobs, and with them their results, beyond the
+/// retention limit. Running jobs are never dropped.
+fn prune_finished_jobs(jobs: &mut BTreeMap<u64, Job>, keep_jobs: usize) {
+    let finished: Vec<u64> = jobs
+        .iter()
+        .filter(|(_, job)| job.status != JobStatus::Running)
+        .map(|(&id, _)| id)
+        .collect();
+    for id in finished
+        .iter()
+        .take(finished.len().saturating_sub(keep_jobs))
+    {
+        println!("Job {}: dropping results (retention limit)", id);
+        jobs.remove(id);
+    }
+}
+
+type SharedState = Arc<ServerState>;
+
+/// Keeps the most recent progress update of a job for polling
+struct JobProgress(Arc<Mutex<Option<ProgressUpdate>>>);
+
+impl ProgressSink for JobProgress {
+    fn report(&self, update: &ProgressUpdate) {
+        *self.0.lock().unwrap() = Some(update.clone());
+    }
+}
+
+/// Error response with a JSON `{ "error": ... }` body
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = Json(serde_json::json!({ "error": self.1 }));
+        (self.0, body).into_response()
+    }
+}
+
+fn not_found(message: impl Into<String>) -> ApiError {
+    ApiError(StatusCode::NOT_FOUND, message.into())
+}
+
+fn bad_request(message: impl Into<String>) -> ApiError {
+    ApiError(StatusCode::BAD_REQUEST, message.into())
+}
+
+fn internal(message: impl Into<String>) -> ApiError {
+    ApiError(StatusCode::INTERNAL_SERVER_ERROR, message.into())
+}
+
+#[derive(Debug, Deserialize)]
+struct LoadRastersRequest {
+    dtm_path: String,
+    dsm_path: String,
+    point_cloud_resolution: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+struct RasterBounds {
+    min_lon: f64,
+    max_lon: f64,
+    min_lat: f64,
+    max_lat: f64,
+}
+
+impl RasterBounds {
+    fn from_transform(transform: &[f64; 6], (n_rows, n_cols): (usize, usize)) -> Self {
+        let min_lat = transform[3] + (n_rows as f64 * transform[5]); // transform[5] is negative
+        let max_lat = transform[3];
+        Self {
+            min_lon: transform[0],
+            max_lon: transform[0] + (n_cols as f64 * transform[1]),
+            min_lat: min_lat.min(max_lat),
+            max_lat: max_lat.max(min_lat),
+        }
+    }
+}
+
+/// Check a DTM/DSM pair and return its bounds, for choosing an AOI
+async fn load_rasters(
+    Json(request): Json<LoadRastersRequest>,
+) -> Result<Json<RasterBounds>, ApiError> {
+    let bounds = tokio::task::spawn_blocking(move || {
+        let resolution = request.point_cloud_resolution.unwrap_or(1.0).max(0.01);
+        let dtm = RasterIO::read_dtm(Path::new(&request.dtm_path), resolution)
+            .map_err(|e| bad_request(format!("Failed to load DTM: {}", e)))?;
+        let dsm = RasterIO::read_dsm(Path::new(&request.dsm_path), resolution)
+            .map_err(|e| bad_request(format!("Failed to load DSM: {}", e)))?;
+        if dtm.data.shape() != dsm.data.shape() {
+            return Err(bad_request("DTM and DSM must have the same dimensions"));
+        }
+        let (_, n_rows, n_cols) = dtm.data.dim();
+        Ok(RasterBounds::from_transform(
+            &dtm.transform,
+            (n_rows, n_cols),
+        ))
+    })
+    .await
+    .map_err(|e| internal(e.to_string()))??;
+    Ok(Json(bounds))
+}
+
+#[derive(Debug, Serialize)]
+struct JobCreated {
+    id: u64,
+}
+
+/// Start a calculation in the background; poll `GET /jobs/{id}` for its progress
+async fn submit_job(
+    State(state): State<SharedState>,
+    Json(config): Json<Config>,
+) -> (StatusCode, Json<JobCreated>) {
+    let id = state.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+    let progress = Arc::new(Mutex::new(None));
+    let cancel_token = CancellationToken::new();
+    state.jobs.lock().unwrap().insert(
+        id,
+        Job {
+            status: JobStatus::Running,
+            progress: progress.clone(),
+            cancel_token: cancel_token.clone(),
+            error: None,
+            output: None,
+        },
+    );
+    println!("Job {}: starting shadow calculation", id);
+
+    let worker_state = state.clone();
+    tokio::task::spawn_blocking(move || {
+        let outcome = run_job(&config, progress, cancel_token);
+        let mut jobs = worker_state.jobs.lock().unwrap();
+        let Some(job) = jobs.get_mut(&id) else {
+            return;
+        };
+        match outcome {
+            Ok(output) => {
+                println!("Job {}: completed", id);
+                job.status = JobStatus::Completed;
+                job.output = Some(Arc::new(output));
+            }
+            Err(ShadowError::Cancelled) => {
+                println!("Job {}: cancelled", id);
+                job.status = JobStatus::Cancelled;
+            }
+            Err(e) => {
+                eprintln!("Job {}: failed: {}", id, e);
+                job.status = JobStatus::Failed;
+                job.error = Some(e.to_string());
+            }
+        }
+        prune_finished_jobs(&mut jobs, worker_state.keep_jobs);
+    });
+
+    (StatusCode::ACCEPTED, Json(JobCreated { id }))
+}
+
+/// The same load, buffer, clip, calculate and mask steps as the desktop app
+fn run_job(
+    config: &Config,
+    progress: Arc<Mutex<Option<ProgressUpdate>>>,
+    cancel_token: CancellationToken,
+) -> Result<JobOutput, ShadowError> {
+    let PreparedEngine {
+        engine,
+        transform,
+        projection,
+        ..
+    } = pipeline::prepare_engine(config, Some(Arc::new(JobProgress(progress))), cancel_token)
+        .map_err(ShadowError::General)?;
+    let mut results = engine.calculate_shadows()?;
+    pipeline::mask_results_to_aoi(&mut results, config, &transform)
+        .map_err(ShadowError::General)?;
+    Ok(JobOutput {
+        results,
+        transform,
+        projection,
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct JobInfo {
+    id: u64,
+    status: JobStatus,
+    progress: Option<ProgressUpdate>,
+    error: Option<String>,
+    /// (rows, cols) of the result grid once completed
+    dimensions: Option<(usize, usize)>,
+    timestamps: Option<usize>,
+    bounds: Option<RasterBounds>,
+}
+
+fn job_info(id: u64, job: &Job) -> JobInfo {
+    let output = job.output.as_ref();
+    let dimensions = output.map(|output| {
+        let (_, n_rows, n_cols) = output.results.shadow_fraction.dim();
+        (n_rows, n_cols)
+    });
+    JobInfo {
+        id,
+        status: job.status,
+        progress: job.progress.lock().unwrap().clone(),
+        error: job.error.clone(),
+        dimensions,
+        timestamps: output.map(|output| output.results.timestamps.len()),
+        bounds: output.zip(dimensions).map(|(output, dimensions)| {
+            RasterBounds::from_transform(&output.transform, dimensions)
+        }),
+    }
+}
+
+async fn list_jobs(State(state): State<SharedState>) -> Json<Vec<JobInfo>> {
+    let jobs = state.jobs.lock().unwrap();
+    Json(jobs.iter().map(|(&id, job)| job_info(id, job)).collect())
+}
+
+async fn get_job(
+    State(state): State<SharedState>,
+    UrlPath(id): UrlPath<u64>,
+) -> Result<Json<JobInfo>, ApiError> {
+    let jobs = state.jobs.lock().unwrap();
+    let job = jobs
+        .get(&id)
+        .ok_or_else(|| not_found(format!("No job {}", id)))?;
+    Ok(Json(job_info(id, job)))
+}
+
+async fn cancel_job(
+    State(state): State<SharedState>,
+    UrlPath(id): UrlPath<u64>,
+) -> Result<StatusCode, ApiError> {
+    let jobs = state.jobs.lock().unwrap();
+    let job = jobs
+        .get(&id)
+        .ok_or_else(|| not_found(format!("No job {}", id)))?;
+    job.cancel_token.cancel();
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Cancel a job if it is still running and forget it, freeing its results
+async fn delete_job(
+    State(state): State<SharedState>,
+    UrlPath(id): UrlPath<u64>,
+) -> Result<StatusCode, ApiError> {
+    let job = state
+        .jobs
+        .lock()
+        .unwrap()
+        .remove(&id)
+        .ok_or_else(|| not_found(format!("No job {}", id)))?;
+    job.cancel_token.cancel();
+    println!("Job {}: deleted", id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Results of a completed job
+fn job_output(state: &ServerState, id: u64) -> Result<Arc<JobOutput>, ApiError> {
+    let jobs = state.jobs.lock().unwrap();
+    let job = jobs
+        .get(&id)
+        .ok_or_else(|| not_found(format!("No job {}", id)))?;
+    job.output.clone().ok_or_else(|| {
+        ApiError(
+            StatusCode::CONFLICT,
+            format!("Job {} has no results (status {:?})", id, job.status),
+        )
+    })
+}
+
+/// One map on the result grid; cells outside the AOI are null
+#[derive(Debug, Serialize)]
+struct Layer {
+    name: String,
+    data: Vec<Vec<f32>>,
+    transform: [f64; 6],
+    bounds: RasterBounds,
+}
+
+impl Layer {
+    fn new(name: String, data: ndarray::ArrayView2<f32>, output: &JobOutput) -> Self {
+        Self {
+            name,
+            data: data.outer_iter().map(|row| row.to_vec()).collect(),
+            transform: output.transform,
+            bounds: RasterBounds::from_transform(&output.transform, data.dim()),
+        }
+    }
+}
+
+async fn get_timestamps(
+    State(state): State<SharedState>,
+    UrlPath(id): UrlPath<u64>,
+) -> Result<Json<Vec<String>>, ApiError> {
+    let output = job_output(&state, id)?;
+    Ok(Json(
+        output
+            .results
+            .timestamps
+            .iter()
+            .map(|timestamp| timestamp.to_rfc3339())
+            .collect(),
+    ))
+}
+
+/// A summary layer by field name, e.g. `total_shadow_hours`
+async fn get_summary_layer(
+    State(state): State<SharedState>,
+    UrlPath((id, name)): UrlPath<(u64, String)>,
+) -> Result<Json<Layer>, ApiError> {
+    let output = job_output(&state, id)?;
+    let layer =
+        output.results.summary_stats.layer(&name).ok_or_else(|| {
+            not_found(format!("No summary layer named '{}' in the results", name))
+        })?;
+    Ok(Json(Layer::new(
+        name,
+        layer.index_axis(ndarray::Axis(0), 0),
+        &output,
+    )))
+}
+
+/// Shadow fraction at one timestamp
+async fn get_time_slice(
+    State(state): State<SharedState>,
+    UrlPath((id, index)): UrlPath<(u64, usize)>,
+) -> Result<Json<Layer>, ApiError> {
+    let output = job_output(&state, id)?;
+    let timestamp = output.results.timestamps.get(index).ok_or_else(|| {
+        not_found(format!(
+            "Time index {} is out of range (0-{})",
+            index,
+            output.results.timestamps.len().saturating_sub(1)
+        ))
+    })?;
+    let name = timestamp.to_rfc3339();
+    let layer = tokio::task::spawn_blocking(move || {
+        let slice = output
+            .results
+            .shadow_fraction
+            .read_slice(index)
+            .map_err(|e| internal(format!("Failed to read shadow map: {}", e)))?;
+        Ok::<_, ApiError>(Layer::new(name, slice.view(), &output))
+    })
+    .await
+    .map_err(|e| internal(e.to_string()))??;
+    Ok(Json(layer))
+}
+
+/// Results as a GeoTIFF (summary layers, then one band per timestamp) or CSV file
+async fn export_job(
+    State(state): State<SharedState>,
+    UrlPath((id, format)): UrlPath<(u64, String)>,
+) -> Result<Response, ApiError> {
+    let output = job_output(&state, id)?;
+    let (extension, content_type) = match format.as_str() {
+        "geotiff" => ("tif", "image/tiff"),
+        "csv" => ("csv", "text/csv"),
+        _ => return Err(bad_request(format!("Unsupported format '{}'", format))),
+    };
+    let filename = format!("shadows_job{}.{}", id, extension);
+    let export = state.next_export.fetch_add(1, Ordering::Relaxed);
+    let path = std::env::temp_dir().join(format!(
+        "shadow_server_{}_{}_{}",
+        std::process::id(),
+        export,
+        filename
+    ));
+
+    let bytes = tokio::task::spawn_blocking(move || {
+        let written = match extension {
+            "tif" => pipeline::write_results_geotiff(
+                &path,
+                &output.results,
+                &output.transform,
+                &output.projection,
+            ),
+            _ => RasterIO::write_csv(
+                &path,
+                &output.results.shadow_fraction,
+                &output.results.timestamps,
+                &output.transform,
+            ),
+        };
+        let bytes = written.and_then(|()| Ok(std::fs::read(&path)?));
+        let _ = std::fs::remove_file(&path);
+        bytes.map_err(|e| internal(format!("Failed to export results: {}", e)))
+    })
+    .await
+    .map_err(|e| internal(e.to_string()))??;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        bytes,
+    )
+        .into_response())
+}
+
+/// Browsers may only call the API from the listed origins; other local tools are
+/// not subject to CORS
+fn cors_layer(allowed_origins: Vec<HeaderValue>) -> CorsLayer {
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(allowed_origins))
+        .allow_methods([Method::GET, Method::POST, Method::DELETE])
+        .allow_headers([header::CONTENT_TYPE])
+}
+
+fn router(state: SharedState, allowed_origins: Vec<HeaderValue>) -> Router {
+    Router::new()
+        .route("/rasters", post(load_rasters))
+        .route("/jobs", get(list_jobs).post(submit_job))
+        .route("/jobs/:id", get(get_job).delete(delete_job))
+        .route("/jobs/:id/cancel", post(cancel_job))
+        .route("/jobs/:id/timestamps", get(get_timestamps))
+        .route("/jobs/:id/summary/:layer", get(get_summary_layer))
+        .route("/jobs/:id/slices/:index", get(get_time_slice))
+        .route("/jobs/:id/export/:format", get(export_job))
+        .layer(cors_layer(allowed_origins))
+        .with_state(state)
+}
+
+struct ServerOptions {
+    port: u16,
+    allowed_origins: Vec<HeaderValue>,
+    keep_jobs: usize,
+}
+
+fn parse_args() -> Result<ServerOptions, String> {
+    let mut options = ServerOptions {
+        port: DEFAULT_PORT,
+        allowed_origins: Vec::new(),
+        keep_jobs: DEFAULT_KEEP_JOBS,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-p" | "--port" => {
+                let value = args.next().ok_or("--port needs a value")?;
+                options.port = value
+                    .parse()
+                    .map_err(|_| format!("Invalid port '{}'", value))?;
+            }
+            "--allow-origin" => {
+                let value = args.next().ok_or("--allow-origin needs a value")?;
+                let origin = HeaderValue::from_str(value.trim_end_matches('/'))
+                    .map_err(|_| format!("Invalid origin '{}'", value))?;
+                options.allowed_origins.push(origin);
+            }
+            "--keep-jobs" => {
+                let value = args.next().ok_or("--keep-jobs needs a value")?;
+                options.keep_jobs = value
+                    .parse()
+                    .map_err(|_| format!("Invalid job count '{}'", value))?;
+            }
+            _ => {
+                return Err(
+                    "Usage: shadow-server [--port PORT] [--allow-origin ORIGIN]... [--keep-jobs N]"
+                        .to_string(),
+                )
+            }
+        }
+    }
+    Ok(options)
+}
+
+#[tokio::main]
+async fn main() {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+    };
+
+    // Local tools only: the API reads and writes files with the server's permissions
+    let address = std::net::SocketAddr::from(([127, 0, 0, 1], options.port));
+    let listener = tokio::net::TcpListener::bind(address)
+        .await
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to listen on {}: {}", address, e);
+            std::process::exit(1);
+        });
+    println!("Shadow server listening on http://{}", address);
+
+    let state = Arc::new(ServerState::new(options.keep_jobs));
+    axum::serve(listener, router(state, options.allowed_origins))
+        .await
+        .expect("error while running shadow server");
+}
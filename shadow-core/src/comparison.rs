@@ -0,0 +1,302 @@
+use crate::types::{ShadowError, SummaryStats};
+use ndarray::{Array2, Array3, ArrayView2, Axis, Zip};
+use serde::Serialize;
+
+/// Mean daily sun hours a cell needs to pass when no threshold is given
+pub const DEFAULT_SUN_HOURS_THRESHOLD: f64 = 2.0;
+
+/// GeoTIFF band descriptions of the delta layers, in export order
+pub const DELTA_BANDS: [&str; 5] = [
+    "Added_Shadow_Hours_(scenario_minus_baseline)",
+    "Lost_Solar_Efficiency_(baseline_minus_scenario_0-1)",
+    "Newly_Below_Sun_Hour_Threshold_(1=yes)",
+    "Baseline_Daily_Sun_Hours",
+    "Scenario_Daily_Sun_Hours",
+];
+
+/// Per-cell differences between a baseline and a scenario on the same grid. Cells
+/// outside the AOI in either result are NaN.
+#[derive(Debug, Clone)]
+pub struct DeltaLayers {
+    pub added_shadow_hours: Array2<f32>,
+    pub lost_solar_efficiency: Array2<f32>,
+    /// 1 where the baseline meets the sun-hour threshold and the scenario does not
+    pub newly_failing: Array2<f32>,
+    pub baseline_sun_hours: Array2<f32>,
+    pub scenario_sun_hours: Array2<f32>,
+}
+
+impl DeltaLayers {
+    /// Layers in the order of `DELTA_BANDS`
+    pub fn bands(&self) -> [&Array2<f32>; 5] {
+        [
+            &self.added_shadow_hours,
+            &self.lost_solar_efficiency,
+            &self.newly_failing,
+            &self.baseline_sun_hours,
+            &self.scenario_sun_hours,
+        ]
+    }
+}
+
+/// Impact of a scenario over the AOI
+#[derive(Debug, Clone, Serialize)]
+pub struct ComparisonSummary {
+    /// Mean daily sun hours a cell needs to pass
+    pub sun_hours_threshold: f64,
+    pub cells: usize,
+    /// Area of the compared cells, m²
+    pub area: f64,
+    pub mean_added_shadow_hours: f64,
+    pub max_added_shadow_hours: f64,
+    /// Cells getting at least a tenth of an hour more shadow
+    pub cells_more_shadow: usize,
+    pub cells_less_shadow: usize,
+    pub mean_lost_solar_efficiency: f64,
+    pub baseline_failing_cells: usize,
+    pub scenario_failing_cells: usize,
+    pub newly_failing_cells: usize,
+    /// Area of the newly failing cells, m²
+    pub newly_failing_area: f64,
+    pub newly_passing_cells: usize,
+}
+
+/// Shadow hours below this change count as unchanged in the summary
+const SHADOW_HOURS_TOLERANCE: f32 = 0.1;
+
+/// Mean daily sun hours per cell: the sunlit share of the available solar hours
+/// times the available solar hours per day
+fn daily_sun_hours(stats: &SummaryStats) -> Array2<f32> {
+    &first_band(&stats.solar_efficiency_percentage) * &first_band(&stats.daily_solar_hours)
+}
+
+fn first_band(layer: &Array3<f32>) -> ArrayView2<'_, f32> {
+    layer.index_axis(Axis(0), 0)
+}
+
+/// Difference a scenario against its baseline. Both must be on the same grid.
+pub fn compare(
+    baseline: &SummaryStats,
+    scenario: &SummaryStats,
+    sun_hours_threshold: f64,
+    cell_area: f64,
+) -> Result<(DeltaLayers, ComparisonSummary), ShadowError> {
+    let baseline_dim = baseline.total_shadow_hours.dim();
+    let scenario_dim = scenario.total_shadow_hours.dim();
+    if baseline_dim.1 != scenario_dim.1 || baseline_dim.2 != scenario_dim.2 {
+        return Err(ShadowError::Config(format!(
+            "Baseline grid {}x{} does not match scenario grid {}x{}",
+            baseline_dim.1, baseline_dim.2, scenario_dim.1, scenario_dim.2
+        )));
+    }
+
+    let added_shadow_hours =
+        &first_band(&scenario.total_shadow_hours) - &first_band(&baseline.total_shadow_hours);
+    let lost_solar_efficiency = &first_band(&baseline.solar_efficiency_percentage)
+        - &first_band(&scenario.solar_efficiency_percentage);
+    let baseline_sun_hours = daily_sun_hours(baseline);
+    let scenario_sun_hours = daily_sun_hours(scenario);
+
+    let threshold = sun_hours_threshold as f32;
+    let mut newly_failing = Array2::<f32>::from_elem(added_shadow_hours.dim(), f32::NAN);
+    let mut summary = ComparisonSummary {
+        sun_hours_threshold,
+        cells: 0,
+        area: 0.0,
+        mean_added_shadow_hours: 0.0,
+        max_added_shadow_hours: f64::NEG_INFINITY,
+        cells_more_shadow: 0,
+        cells_less_shadow: 0,
+        mean_lost_solar_efficiency: 0.0,
+        baseline_failing_cells: 0,
+        scenario_failing_cells: 0,
+        newly_failing_cells: 0,
+        newly_failing_area: 0.0,
+        newly_passing_cells: 0,
+    };
+    let mut added_sum = 0.0f64;
+    let mut lost_sum = 0.0f64;
+
+    Zip::from(&mut newly_failing)
+        .and(&added_shadow_hours)
+        .and(&lost_solar_efficiency)
+        .and(&baseline_sun_hours)
+        .and(&scenario_sun_hours)
+        .for_each(|failing, &added, &lost, &before, &after| {
+            if !added.is_finite() || !lost.is_finite() || !before.is_finite() || !after.is_finite()
+            {
+                return;
+            }
+            let failed_before = before < threshold;
+            let fails_after = after < threshold;
+            *failing = (!failed_before && fails_after) as u8 as f32;
+
+            summary.cells += 1;
+            added_sum += added as f64;
+            lost_sum += lost as f64;
+            summary.max_added_shadow_hours = summary.max_added_shadow_hours.max(added as f64);
+            if added >= SHADOW_HOURS_TOLERANCE {
+                summary.cells_more_shadow += 1;
+            } else if added <= -SHADOW_HOURS_TOLERANCE {
+                summary.cells_less_shadow += 1;
+            }
+            summary.baseline_failing_cells += failed_before as usize;
+            summary.scenario_failing_cells += fails_after as usize;
+            match (failed_before, fails_after) {
+                (false, true) => summary.newly_failing_cells += 1,
+                (true, false) => summary.newly_passing_cells += 1,
+                _ => {}
+            }
+        });
+
+    if summary.cells > 0 {
+        summary.mean_added_shadow_hours = added_sum / summary.cells as f64;
+        summary.mean_lost_solar_efficiency = lost_sum / summary.cells as f64;
+    } else {
+        summary.max_added_shadow_hours = 0.0;
+    }
+    summary.area = summary.cells as f64 * cell_area;
+    summary.newly_failing_area = summary.newly_failing_cells as f64 * cell_area;
+
+    println!(
+        "Scenario comparison over {} cells: {:.2} h mean added shadow, {} cells newly below {:.1} daily sun hours",
+        summary.cells, summary.mean_added_shadow_hours, summary.newly_failing_cells, sun_hours_threshold
+    );
+
+    Ok((
+        DeltaLayers {
+            added_shadow_hours,
+            lost_solar_efficiency,
+            newly_failing,
+            baseline_sun_hours,
+            scenario_sun_hours,
+        },
+        summary,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One row of cells with the given total shadow hours and sunlit shares of 8
+    /// available solar hours a day
+    fn stats(shadow_hours: &[f32], efficiency: &[f32]) -> SummaryStats {
+        let layer =
+            |values: &[f32]| Array3::from_shape_vec((1, 1, values.len()), values.to_vec()).unwrap();
+        let zeros = Array3::zeros((1, 1, shadow_hours.len()));
+        SummaryStats {
+            total_shadow_hours: layer(shadow_hours),
+            avg_shadow_percentage: zeros.clone(),
+            max_consecutive_shadow: zeros.clone(),
+            morning_shadow_hours: zeros.clone(),
+            noon_shadow_hours: zeros.clone(),
+            afternoon_shadow_hours: zeros,
+            solar_efficiency_percentage: layer(efficiency),
+            daily_solar_hours: Array3::from_elem((1, 1, shadow_hours.len()), 8.0),
+            total_available_solar_hours: Array3::from_elem((1, 1, shadow_hours.len()), 8.0),
+            sky_view_factor: None,
+            direct_irradiation: None,
+            diffuse_irradiation: None,
+            global_irradiation: None,
+            slope: None,
+            aspect: None,
+            self_shaded_hours: None,
+            effective_sun_hours: None,
+        }
+    }
+
+    #[test]
+    fn sun_hour_threshold_is_met_at_the_threshold() {
+        // Daily sun hours before -> after: 4 -> 2 stays passing, 4 -> 1.5 newly fails,
+        // 1 -> 3 newly passes, 1 -> 0.5 keeps failing
+        let baseline = stats(&[0.0; 4], &[0.5, 0.5, 0.125, 0.125]);
+        let scenario = stats(&[0.0; 4], &[0.25, 0.1875, 0.375, 0.0625]);
+        let (layers, summary) = compare(&baseline, &scenario, 2.0, 1.0).unwrap();
+
+        assert_eq!(
+            layers.newly_failing.row(0).to_vec(),
+            vec![0.0, 1.0, 0.0, 0.0]
+        );
+        assert_eq!(
+            layers.baseline_sun_hours.row(0).to_vec(),
+            vec![4.0, 4.0, 1.0, 1.0]
+        );
+        assert_eq!(
+            layers.scenario_sun_hours.row(0).to_vec(),
+            vec![2.0, 1.5, 3.0, 0.5]
+        );
+        assert_eq!(summary.baseline_failing_cells, 2);
+        assert_eq!(summary.scenario_failing_cells, 2);
+        assert_eq!(summary.newly_failing_cells, 1);
+        assert_eq!(summary.newly_passing_cells, 1);
+
+        // A higher threshold fails the cell left at exactly 2 hours too
+        let (_, summary) = compare(&baseline, &scenario, 2.5, 1.0).unwrap();
+        assert_eq!(summary.newly_failing_cells, 2);
+    }
+
+    #[test]
+    fn small_shadow_changes_count_as_unchanged() {
+        let baseline = stats(&[1.0; 5], &[0.5; 5]);
+        let scenario = stats(&[1.125, 1.0625, 1.0, 0.9375, 0.875], &[0.5; 5]);
+        let (layers, summary) = compare(&baseline, &scenario, 2.0, 4.0).unwrap();
+
+        assert_eq!(
+            layers.added_shadow_hours.row(0).to_vec(),
+            vec![0.125, 0.0625, 0.0, -0.0625, -0.125]
+        );
+        assert_eq!(summary.cells_more_shadow, 1);
+        assert_eq!(summary.cells_less_shadow, 1);
+        assert_eq!(summary.max_added_shadow_hours, 0.125);
+        assert_eq!(summary.mean_added_shadow_hours, 0.0);
+        assert_eq!(summary.area, 20.0);
+
+        // A change of exactly the tolerance counts
+        let baseline = stats(&[0.0, 0.0], &[0.5; 2]);
+        let scenario = stats(
+            &[SHADOW_HOURS_TOLERANCE, -SHADOW_HOURS_TOLERANCE],
+            &[0.5; 2],
+        );
+        let (_, summary) = compare(&baseline, &scenario, 2.0, 4.0).unwrap();
+        assert_eq!(
+            (summary.cells_more_shadow, summary.cells_less_shadow),
+            (1, 1)
+        );
+    }
+
+    #[test]
+    fn cells_outside_either_aoi_are_left_out() {
+        let baseline = stats(&[1.0, f32::NAN, 1.0], &[0.5, 0.5, 0.5]);
+        let scenario = stats(&[2.0, 2.0, 2.0], &[0.125, 0.125, f32::NAN]);
+        let (layers, summary) = compare(&baseline, &scenario, 2.0, 1.0).unwrap();
+
+        assert_eq!(summary.cells, 1);
+        assert_eq!(summary.newly_failing_cells, 1);
+        assert_eq!(summary.newly_failing_area, 1.0);
+        assert_eq!(summary.mean_lost_solar_efficiency, 0.375);
+        assert_eq!(layers.newly_failing[[0, 0]], 1.0);
+        assert!(layers.newly_failing[[0, 1]].is_nan());
+        assert!(layers.newly_failing[[0, 2]].is_nan());
+    }
+
+    #[test]
+    fn empty_comparison_reports_no_added_shadow() {
+        let baseline = stats(&[f32::NAN; 2], &[f32::NAN; 2]);
+        let (_, summary) = compare(&baseline, &baseline, 2.0, 1.0).unwrap();
+        assert_eq!(summary.cells, 0);
+        assert_eq!(summary.max_added_shadow_hours, 0.0);
+        assert_eq!(summary.mean_added_shadow_hours, 0.0);
+    }
+
+    #[test]
+    fn mismatched_grids_are_rejected() {
+        let result = compare(
+            &stats(&[0.0; 3], &[0.5; 3]),
+            &stats(&[0.0; 4], &[0.5; 4]),
+            2.0,
+            1.0,
+        );
+        assert!(matches!(result, Err(ShadowError::Config(_))));
+    }
+}
@@ -0,0 +1,525 @@
+use crate::types::ShadowError;
+use ndarray::Array2;
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+/// Heights along a run of edges may differ by this much and still form one segment
+const SEGMENT_HEIGHT_TOLERANCE: f32 = 1.0;
+
+/// Facings of runs meeting end to end may differ by this much (degrees) and still be
+/// merged into one diagonal segment
+const STAIRCASE_ORIENTATION_TOLERANCE: f64 = 30.0;
+
+/// A run of DSM cell edges where the surface drops by at least the minimum wall height.
+///
+/// Pixel coordinates follow the engine convention: cell (row, col) is centred on
+/// (col, row) and its edges lie half a pixel away.
+#[derive(Debug, Clone)]
+pub struct WallSegment {
+    /// Endpoints as (col, row) pixel coordinates
+    pub start: (f64, f64),
+    pub end: (f64, f64),
+    /// Azimuth the wall faces (outward normal), degrees clockwise from north
+    pub orientation: f64,
+    /// Highest ground in front of the wall and lowest roof behind it, so every
+    /// sample height between them is on the wall for the whole segment
+    pub base_height: f32,
+    pub top_height: f32,
+    /// For each edge: the midpoint of the edge and the low cell in front of it
+    pub edges: Vec<WallEdge>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WallEdge {
+    pub midpoint: (f64, f64),
+    pub front_cell: (usize, usize),
+}
+
+/// Sun hours per sample height for one wall segment
+#[derive(Debug, Clone)]
+pub struct FacadeSegmentStats {
+    pub segment: WallSegment,
+    pub level_heights: Vec<f32>,
+    pub sun_hours: Vec<f32>,
+}
+
+/// Cell-edge directions as (row offset, col offset, normal azimuth)
+const EDGE_DIRECTIONS: [(isize, isize, f64); 4] = [
+    (-1, 0, 0.0),   // North face
+    (0, 1, 90.0),   // East face
+    (1, 0, 180.0),  // South face
+    (0, -1, 270.0), // West face
+];
+
+/// Find wall segments from height jumps between neighbouring DSM cells.
+///
+/// Each edge between a high cell and a neighbour at least `min_wall_height` lower is
+/// a piece of wall facing the neighbour. Consecutive edges facing the same way with
+/// similar base and top heights are merged into one run. Run orientation comes from
+/// the DSM gradient rather than the edge direction, so the N and E runs of a diagonal
+/// wall drawn as a pixel staircase share its true facing, and runs meeting end to end
+/// with a consistent facing are then joined into one segment along the diagonal.
+/// `include` filters on the front cell, e.g. to keep walls facing into the AOI.
+pub fn extract_wall_segments<F>(
+    dsm: &Array2<f32>,
+    min_wall_height: f32,
+    include: F,
+) -> Vec<WallSegment>
+where
+    F: Fn(usize, usize) -> bool,
+{
+    let (n_rows, n_cols) = dsm.dim();
+    let mut segments = Vec::new();
+
+    for &(d_row, d_col, edge_azimuth) in &EDGE_DIRECTIONS {
+        let along_rows = d_col != 0; // E/W faces run down the rows
+
+        // Walk each line of edges parallel to the face direction
+        let (n_lines, line_len) = if along_rows {
+            (n_cols, n_rows)
+        } else {
+            (n_rows, n_cols)
+        };
+
+        for line in 0..n_lines {
+            let mut run: Vec<(usize, usize, f32, f32)> = Vec::new(); // (row, col, base, top)
+
+            for pos in 0..=line_len {
+                let edge = if pos < line_len {
+                    let (row, col) = if along_rows { (pos, line) } else { (line, pos) };
+                    wall_edge(dsm, row, col, d_row, d_col, min_wall_height)
+                        .filter(|_| {
+                            let front = (
+                                (row as isize + d_row) as usize,
+                                (col as isize + d_col) as usize,
+                            );
+                            include(front.0, front.1)
+                        })
+                        .map(|(base, top)| (row, col, base, top))
+                } else {
+                    None
+                };
+
+                // Close the current run when the edge breaks it
+                let continues = match (edge, run.first()) {
+                    (Some((_, _, base, top)), Some(&(_, _, run_base, run_top))) => {
+                        (base - run_base).abs() <= SEGMENT_HEIGHT_TOLERANCE
+                            && (top - run_top).abs() <= SEGMENT_HEIGHT_TOLERANCE
+                    }
+                    _ => false,
+                };
+                if !continues && !run.is_empty() {
+                    segments.push(build_segment(dsm, &run, d_row, d_col, edge_azimuth));
+                    run.clear();
+                }
+                if let Some(edge) = edge {
+                    run.push(edge);
+                }
+            }
+        }
+    }
+
+    merge_staircases(segments)
+}
+
+/// Join runs that meet end to end into segments whose facing and heights stay within
+/// the tolerances of the segment's mean, taking the runs in extraction order
+fn merge_staircases(runs: Vec<WallSegment>) -> Vec<WallSegment> {
+    // Runs ending at each corner, keyed by doubled (half-pixel) coordinates
+    let corner = |(x, y): (f64, f64)| ((x * 2.0).round() as i64, (y * 2.0).round() as i64);
+    let mut runs_at: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+    for (idx, run) in runs.iter().enumerate() {
+        for end in [run.start, run.end] {
+            runs_at.entry(corner(end)).or_default().push(idx);
+        }
+    }
+
+    // Union-find over runs, each root holding its group's facing vector sum (edge
+    // weighted) and height range
+    let mut parent: Vec<usize> = (0..runs.len()).collect();
+    let mut facing: Vec<(f64, f64)> = runs
+        .iter()
+        .map(|run| {
+            let weight = run.edges.len() as f64;
+            let azimuth = run.orientation.to_radians();
+            (azimuth.sin() * weight, azimuth.cos() * weight)
+        })
+        .collect();
+    let mut heights: Vec<(f32, f32)> = runs
+        .iter()
+        .map(|run| (run.base_height, run.top_height))
+        .collect();
+
+    fn root(parent: &mut [usize], mut idx: usize) -> usize {
+        while parent[idx] != idx {
+            parent[idx] = parent[parent[idx]];
+            idx = parent[idx];
+        }
+        idx
+    }
+
+    for (idx, run) in runs.iter().enumerate() {
+        for end in [run.start, run.end] {
+            for &other in &runs_at[&corner(end)] {
+                let (a, b) = (root(&mut parent, idx), root(&mut parent, other));
+                if a == b {
+                    continue;
+                }
+                let angle = (facing[a].0.atan2(facing[a].1) - facing[b].0.atan2(facing[b].1))
+                    .to_degrees()
+                    .rem_euclid(360.0);
+                let consistent = angle.min(360.0 - angle) <= STAIRCASE_ORIENTATION_TOLERANCE
+                    && (heights[a].0 - heights[b].0).abs() <= SEGMENT_HEIGHT_TOLERANCE
+                    && (heights[a].1 - heights[b].1).abs() <= SEGMENT_HEIGHT_TOLERANCE;
+                if consistent {
+                    parent[b] = a;
+                    facing[a] = (facing[a].0 + facing[b].0, facing[a].1 + facing[b].1);
+                    heights[a] = (
+                        heights[a].0.max(heights[b].0),
+                        heights[a].1.min(heights[b].1),
+                    );
+                }
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<WallSegment>> = Vec::new();
+    let mut group_of_root: HashMap<usize, usize> = HashMap::new();
+    let roots: Vec<usize> = (0..runs.len()).map(|idx| root(&mut parent, idx)).collect();
+    for (run, root) in runs.into_iter().zip(roots) {
+        let group = *group_of_root.entry(root).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[group].push(run);
+    }
+
+    groups
+        .into_iter()
+        .map(|mut group| {
+            if group.len() == 1 {
+                group.remove(0)
+            } else {
+                join_runs(group)
+            }
+        })
+        .collect()
+}
+
+/// One segment from runs forming a staircase, with edges ordered along the wall and
+/// the outermost run ends as its endpoints
+fn join_runs(runs: Vec<WallSegment>) -> WallSegment {
+    let (east, north) = runs.iter().fold((0.0, 0.0), |(east, north), run| {
+        let weight = run.edges.len() as f64;
+        let azimuth = run.orientation.to_radians();
+        (
+            east + azimuth.sin() * weight,
+            north + azimuth.cos() * weight,
+        )
+    });
+    let orientation = east.atan2(north).to_degrees().rem_euclid(360.0);
+
+    // Along the wall in (col, row) pixel space, a quarter turn from the facing
+    let along = |(x, y): (f64, f64)| {
+        let azimuth = orientation.to_radians();
+        x * azimuth.cos() + y * azimuth.sin()
+    };
+    let by_along = |a: &(f64, f64), b: &(f64, f64)| along(*a).total_cmp(&along(*b));
+    let mut ends: Vec<(f64, f64)> = runs.iter().flat_map(|run| [run.start, run.end]).collect();
+    ends.sort_by(by_along);
+    let (start, end) = (ends[0], ends[ends.len() - 1]);
+
+    let mut edges: Vec<WallEdge> = runs.iter().flat_map(|run| run.edges.clone()).collect();
+    edges.sort_by(|a, b| by_along(&a.midpoint, &b.midpoint));
+
+    WallSegment {
+        start,
+        end,
+        orientation,
+        base_height: runs
+            .iter()
+            .map(|run| run.base_height)
+            .fold(f32::NEG_INFINITY, f32::max),
+        top_height: runs
+            .iter()
+            .map(|run| run.top_height)
+            .fold(f32::INFINITY, f32::min),
+        edges,
+    }
+}
+
+/// (base, top) heights if the edge from `(row, col)` toward its neighbour is a wall
+fn wall_edge(
+    dsm: &Array2<f32>,
+    row: usize,
+    col: usize,
+    d_row: isize,
+    d_col: isize,
+    min_wall_height: f32,
+) -> Option<(f32, f32)> {
+    let (n_rows, n_cols) = dsm.dim();
+    let n_row = row as isize + d_row;
+    let n_col = col as isize + d_col;
+    if n_row < 0 || n_col < 0 || n_row >= n_rows as isize || n_col >= n_cols as isize {
+        return None;
+    }
+
+    let top = dsm[[row, col]];
+    let base = dsm[[n_row as usize, n_col as usize]];
+    if top.is_finite() && base.is_finite() && top - base >= min_wall_height {
+        Some((base, top))
+    } else {
+        None
+    }
+}
+
+fn build_segment(
+    dsm: &Array2<f32>,
+    run: &[(usize, usize, f32, f32)],
+    d_row: isize,
+    d_col: isize,
+    edge_azimuth: f64,
+) -> WallSegment {
+    // Edge midpoints sit half a pixel from the high cell toward the front cell
+    let edges: Vec<WallEdge> = run
+        .iter()
+        .map(|&(row, col, _, _)| WallEdge {
+            midpoint: (
+                col as f64 + 0.5 * d_col as f64,
+                row as f64 + 0.5 * d_row as f64,
+            ),
+            front_cell: (
+                (row as isize + d_row) as usize,
+                (col as isize + d_col) as usize,
+            ),
+        })
+        .collect();
+
+    // Segment endpoints extend half a pixel along the edge line past the end midpoints
+    let (first, last) = (edges[0].midpoint, edges[edges.len() - 1].midpoint);
+    let (along_x, along_y) = if d_col != 0 { (0.0, 0.5) } else { (0.5, 0.0) };
+    let start = (first.0 - along_x, first.1 - along_y);
+    let end = (last.0 + along_x, last.1 + along_y);
+
+    // Mean downhill direction across the run, as a vector sum
+    let (mut east, mut north) = (0.0f64, 0.0f64);
+    for &(row, col, _, _) in run {
+        if let Some((gx, gy)) = sobel_gradient(dsm, row, col) {
+            // Downhill in (east, south) pixel space is (-gx, -gy); north = -south
+            east += -gx;
+            north += gy;
+        }
+    }
+    let orientation = downhill_orientation(east, north, edge_azimuth);
+
+    WallSegment {
+        start,
+        end,
+        orientation,
+        base_height: run.iter().map(|e| e.2).fold(f32::NEG_INFINITY, f32::max),
+        top_height: run.iter().map(|e| e.3).fold(f32::INFINITY, f32::min),
+        edges,
+    }
+}
+
+/// Use the gradient direction when it agrees with the edge facing (within 90°),
+/// otherwise fall back to the edge normal
+fn downhill_orientation(east: f64, north: f64, edge_azimuth: f64) -> f64 {
+    if east.hypot(north) < 1e-6 {
+        return edge_azimuth;
+    }
+    let azimuth = east.atan2(north).to_degrees().rem_euclid(360.0);
+    let difference = (azimuth - edge_azimuth + 180.0).rem_euclid(360.0) - 180.0;
+    if difference.abs() < 90.0 {
+        azimuth
+    } else {
+        edge_azimuth
+    }
+}
+
+/// Sobel gradient (d/dcol, d/drow) at an interior cell
+fn sobel_gradient(dsm: &Array2<f32>, row: usize, col: usize) -> Option<(f64, f64)> {
+    let (n_rows, n_cols) = dsm.dim();
+    if row == 0 || col == 0 || row + 1 >= n_rows || col + 1 >= n_cols {
+        return None;
+    }
+    let z = |r: usize, c: usize| dsm[[r, c]] as f64;
+    let gx = (z(row - 1, col + 1) + 2.0 * z(row, col + 1) + z(row + 1, col + 1)
+        - z(row - 1, col - 1)
+        - 2.0 * z(row, col - 1)
+        - z(row + 1, col - 1))
+        / 8.0;
+    let gy = (z(row + 1, col - 1) + 2.0 * z(row + 1, col) + z(row + 1, col + 1)
+        - z(row - 1, col - 1)
+        - 2.0 * z(row - 1, col)
+        - z(row - 1, col + 1))
+        / 8.0;
+    if gx.is_finite() && gy.is_finite() {
+        Some((gx, gy))
+    } else {
+        None
+    }
+}
+
+/// Compass label for a facing azimuth
+fn facing_label(orientation: f64) -> &'static str {
+    const LABELS: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+    LABELS[(((orientation + 22.5).rem_euclid(360.0)) / 45.0) as usize % 8]
+}
+
+/// Write facade statistics as a GeoJSON FeatureCollection of LineStrings in the
+/// raster's coordinate system
+pub fn write_geojson(
+    path: &Path,
+    facades: &[FacadeSegmentStats],
+    transform: &[f64; 6],
+    resolution: f64,
+) -> Result<(), ShadowError> {
+    let to_world = |(x, y): (f64, f64)| -> [f64; 2] {
+        [
+            transform[0] + x * transform[1] + y * transform[2],
+            transform[3] + x * transform[4] + y * transform[5],
+        ]
+    };
+
+    let features: Vec<serde_json::Value> = facades
+        .iter()
+        .map(|facade| {
+            let segment = &facade.segment;
+            let n_levels = facade.sun_hours.len().max(1) as f32;
+            let mean_sun_hours = facade.sun_hours.iter().sum::<f32>() / n_levels;
+            json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "LineString",
+                    "coordinates": [to_world(segment.start), to_world(segment.end)],
+                },
+                "properties": {
+                    "orientation_deg": (segment.orientation * 10.0).round() / 10.0,
+                    "facing": facing_label(segment.orientation),
+                    "length_m": segment.edges.len() as f64 * resolution,
+                    "base_height_m": segment.base_height,
+                    "top_height_m": segment.top_height,
+                    "level_heights_m": facade.level_heights,
+                    "sun_hours": facade.sun_hours,
+                    "mean_sun_hours": mean_sun_hours,
+                    "min_sun_hours": facade.sun_hours.iter().cloned().fold(f32::INFINITY, f32::min),
+                    "max_sun_hours": facade.sun_hours.iter().cloned().fold(0.0, f32::max),
+                },
+            })
+        })
+        .collect();
+
+    let collection = json!({
+        "type": "FeatureCollection",
+        "features": features,
+    });
+
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    serde_json::to_writer(&mut file, &collection)
+        .map_err(|e| ShadowError::General(format!("Failed to write GeoJSON: {}", e)))?;
+    file.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Flat ground with 10 m high cells where `high` holds
+    fn raised(n: usize, high: impl Fn(usize, usize) -> bool) -> Array2<f32> {
+        Array2::from_shape_fn((n, n), |(row, col)| if high(row, col) { 10.0 } else { 0.0 })
+    }
+
+    fn angle_between(a: f64, b: f64) -> f64 {
+        let difference = (a - b).rem_euclid(360.0);
+        difference.min(360.0 - difference)
+    }
+
+    #[test]
+    fn block_has_one_segment_per_side_facing_out() {
+        // Rows 5..9 and cols 4..10: 4 cells tall, 6 wide
+        let dsm = raised(15, |row, col| {
+            (5..9).contains(&row) && (4..10).contains(&col)
+        });
+        let mut segments = extract_wall_segments(&dsm, 2.0, |_, _| true);
+        segments.sort_by(|a, b| a.orientation.total_cmp(&b.orientation));
+
+        let sides: Vec<(f64, usize)> = segments
+            .iter()
+            .map(|segment| (segment.orientation, segment.edges.len()))
+            .collect();
+        assert_eq!(sides, vec![(0.0, 6), (90.0, 4), (180.0, 6), (270.0, 4)]);
+
+        let north = &segments[0];
+        assert_eq!((north.start, north.end), ((3.5, 4.5), (9.5, 4.5)));
+        assert_eq!((north.base_height, north.top_height), (0.0, 10.0));
+        assert!(north.edges.iter().all(|edge| edge.front_cell.0 == 4));
+    }
+
+    #[test]
+    fn staircase_diagonal_becomes_one_segment() {
+        // High ground south-west of a 45° diagonal, so the wall faces north-east
+        let dsm = raised(30, |row, col| row > col + 5 && row < 28 && col > 1);
+        let segments = extract_wall_segments(&dsm, 2.0, |_, _| true);
+
+        let diagonal = segments
+            .iter()
+            .max_by_key(|segment| segment.edges.len())
+            .unwrap();
+        assert!(angle_between(diagonal.orientation, 45.0) < 1.0);
+        // Both the north and east faces of every step belong to it
+        assert!(diagonal.edges.len() >= 34, "{} edges", diagonal.edges.len());
+        let (dx, dy) = (
+            diagonal.end.0 - diagonal.start.0,
+            diagonal.end.1 - diagonal.start.1,
+        );
+        assert!((dx.abs() - dy.abs()).abs() < 1e-9 && dx.abs() >= 17.0);
+
+        // Edges run in order from one end to the other
+        let along: Vec<f64> = diagonal
+            .edges
+            .iter()
+            .map(|edge| edge.midpoint.0 + edge.midpoint.1)
+            .collect();
+        assert!(
+            along.windows(2).all(|pair| pair[0] <= pair[1])
+                || along.windows(2).all(|pair| pair[0] >= pair[1])
+        );
+    }
+
+    #[test]
+    fn shallow_staircase_faces_square_to_the_wall() {
+        // Wall line col = 3 row - 5, high ground to the south: facing atan(1/3) east of north
+        let dsm = raised(30, |row, col| 3 * row > col + 5 && row < 28 && col > 1);
+        let segments = extract_wall_segments(&dsm, 2.0, |_, _| true);
+
+        let expected = (1.0f64).atan2(3.0).to_degrees();
+        let wall = segments
+            .iter()
+            .max_by_key(|segment| segment.edges.len())
+            .unwrap();
+        assert!(
+            angle_between(wall.orientation, expected) < 5.0,
+            "facing {:.1}°, expected {:.1}°",
+            wall.orientation,
+            expected
+        );
+        assert!(wall.edges.len() >= 35, "{} edges", wall.edges.len());
+    }
+
+    #[test]
+    fn front_cell_filter_drops_walls() {
+        let dsm = raised(15, |row, col| {
+            (5..9).contains(&row) && (4..10).contains(&col)
+        });
+        // Keep only walls whose front cell is north of the block
+        let segments = extract_wall_segments(&dsm, 2.0, |row, _| row < 5);
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].orientation, 0.0);
+        // Walls lower than the minimum height are not walls
+        assert!(extract_wall_segments(&dsm, 12.0, |_, _| true).is_empty());
+    }
+}
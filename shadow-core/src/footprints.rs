@@ -0,0 +1,468 @@
+use crate::raster_io::RasterIO;
+use crate::types::{BuildingFootprint, RoofShape, ShadowError};
+use gdal::spatial_ref::{CoordTransform, SpatialRef};
+use gdal::vector::LayerAccess;
+use gdal::Dataset;
+use geo::algorithm::contains::Contains;
+use geo_types::{Coord, Geometry, LineString, Polygon};
+use ndarray::Array2;
+use std::path::Path;
+
+/// Attribute names holding each footprint's height and roof
+#[derive(Debug, Clone)]
+pub struct FootprintFields {
+    pub height: String,
+    pub roof_shape: Option<String>,
+    pub roof_height: Option<String>,
+}
+
+/// Read building footprints from any OGR vector source (GeoJSON, Shapefile,
+/// GeoPackage, ...), reprojected to `target_projection` when both have a CRS.
+///
+/// Every polygon of a multipolygon becomes its own building, keeping its holes.
+/// Roof shapes are read case-insensitively as `flat`, `gable` or `hip`. Features
+/// without a height are skipped.
+pub fn read_footprints(
+    path: &Path,
+    fields: &FootprintFields,
+    target_projection: &str,
+) -> Result<Vec<BuildingFootprint>, ShadowError> {
+    let dataset = Dataset::open(path)?;
+    let mut layer = dataset.layer(0)?;
+
+    let transform = match (layer.spatial_ref(), target_projection.is_empty()) {
+        (Some(source), false) => {
+            let target = SpatialRef::from_wkt(target_projection)?;
+            source.set_axis_mapping_strategy(
+                gdal_sys::OSRAxisMappingStrategy::OAMS_TRADITIONAL_GIS_ORDER,
+            );
+            target.set_axis_mapping_strategy(
+                gdal_sys::OSRAxisMappingStrategy::OAMS_TRADITIONAL_GIS_ORDER,
+            );
+            Some(CoordTransform::new(&source, &target)?)
+        }
+        _ => None,
+    };
+
+    let mut footprints = Vec::new();
+    let mut skipped = 0;
+    for feature in layer.features() {
+        let height = match feature.field_as_double_by_name(&fields.height)? {
+            Some(height) if height > 0.0 => height,
+            _ => {
+                skipped += 1;
+                continue;
+            }
+        };
+        let roof_shape = match &fields.roof_shape {
+            Some(name) => match feature.field_as_string_by_name(name)? {
+                Some(value) => Some(parse_roof_shape(&value)?),
+                None => None,
+            },
+            None => None,
+        };
+        let roof_height = match &fields.roof_height {
+            Some(name) => feature.field_as_double_by_name(name)?,
+            None => None,
+        };
+
+        let geometry = match feature.geometry() {
+            Some(geometry) => match &transform {
+                Some(transform) => geometry.transform(transform)?,
+                None => geometry.clone(),
+            },
+            None => {
+                skipped += 1;
+                continue;
+            }
+        };
+        let polygons = match geometry.to_geo()? {
+            Geometry::Polygon(polygon) => vec![polygon],
+            Geometry::MultiPolygon(multi) => multi.0,
+            _ => Vec::new(),
+        };
+
+        for polygon in polygons {
+            let holes: Vec<Vec<Vec<f64>>> = polygon.interiors().iter().map(ring_points).collect();
+            footprints.push(BuildingFootprint {
+                polygon: ring_points(polygon.exterior()),
+                holes: (!holes.is_empty()).then_some(holes),
+                height,
+                roof_shape,
+                roof_height,
+            });
+        }
+    }
+
+    println!(
+        "Read {} building footprints from {:?} ({} features skipped)",
+        footprints.len(),
+        path,
+        skipped
+    );
+    Ok(footprints)
+}
+
+fn parse_roof_shape(value: &str) -> Result<RoofShape, ShadowError> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "" | "flat" => Ok(RoofShape::Flat),
+        "gable" | "gabled" => Ok(RoofShape::Gable),
+        "hip" | "hipped" => Ok(RoofShape::Hip),
+        other => Err(ShadowError::Config(format!(
+            "Unknown roof shape '{}' (expected flat, gable or hip)",
+            other
+        ))),
+    }
+}
+
+/// Replace the DSM inside each footprint, outside its holes, with the building's roof
+/// surface. The building stands on the mean terrain height under its footprint;
+/// footprints with no terrain under them are skipped. Returns the cells that were
+/// overwritten, so other inputs on the grid can be cleared there.
+pub fn burn_footprints(
+    dsm: &mut Array2<f32>,
+    dtm: &Array2<f32>,
+    transform: &[f64; 6],
+    footprints: &[BuildingFootprint],
+) -> Result<Array2<bool>, ShadowError> {
+    let (n_rows, n_cols) = dsm.dim();
+    let mut burned = Array2::<bool>::from_elem((n_rows, n_cols), false);
+
+    let mut n_burned = 0;
+    for (index, footprint) in footprints.iter().enumerate() {
+        let invalid_ring = |ring: &[Vec<f64>]| ring.len() < 3 || ring.iter().any(|c| c.len() < 2);
+        let holes = footprint.holes.as_deref().unwrap_or_default();
+        if invalid_ring(&footprint.polygon) || holes.iter().any(|hole| invalid_ring(hole)) {
+            return Err(ShadowError::Config(format!(
+                "Building footprint {} needs at least three [x, y] points in each ring",
+                index + 1
+            )));
+        }
+        let polygon = Polygon::new(
+            points_ring(&footprint.polygon),
+            holes.iter().map(|hole| points_ring(hole)).collect(),
+        );
+
+        let cells: Vec<(usize, usize)> = footprint_pixel_window(&polygon, transform, dsm.dim())
+            .filter(|&(row, col)| {
+                let (x, y) = RasterIO::pixel_to_world(col, row, transform);
+                polygon.contains(&Coord { x, y })
+            })
+            .collect();
+        let terrain: Vec<f32> = cells
+            .iter()
+            .map(|&cell| dtm[cell])
+            .filter(|value| value.is_finite())
+            .collect();
+        if terrain.is_empty() {
+            continue;
+        }
+        let base = terrain.iter().sum::<f32>() / terrain.len() as f32;
+
+        let roof = RoofFrame::new(&polygon);
+        let eaves = base + footprint.height as f32;
+        let rise = footprint.get_roof_height() as f32;
+        let shape = footprint.get_roof_shape();
+        for &(row, col) in &cells {
+            let (x, y) = RasterIO::pixel_to_world(col, row, transform);
+            dsm[[row, col]] = eaves + rise * roof.rise_fraction(shape, x, y);
+            burned[[row, col]] = true;
+        }
+        n_burned += 1;
+    }
+
+    println!(
+        "Burned {} of {} building footprints into {} DSM cells",
+        n_burned,
+        footprints.len(),
+        burned.iter().filter(|&&cell| cell).count()
+    );
+    if n_burned < footprints.len() {
+        println!(
+            "Skipped {} footprints with no terrain under them",
+            footprints.len() - n_burned
+        );
+    }
+    Ok(burned)
+}
+
+/// Ring as [x, y] points, the form footprints take in the config
+fn ring_points(ring: &LineString<f64>) -> Vec<Vec<f64>> {
+    ring.coords().map(|coord| vec![coord.x, coord.y]).collect()
+}
+
+fn points_ring(points: &[Vec<f64>]) -> LineString<f64> {
+    LineString::from(points.iter().map(|c| (c[0], c[1])).collect::<Vec<_>>())
+}
+
+/// Cells inside the footprint's bounding box, assuming a north-up grid
+fn footprint_pixel_window(
+    polygon: &Polygon<f64>,
+    transform: &[f64; 6],
+    (n_rows, n_cols): (usize, usize),
+) -> impl Iterator<Item = (usize, usize)> {
+    let (mut min_col, mut max_col) = (f64::INFINITY, f64::NEG_INFINITY);
+    let (mut min_row, mut max_row) = (f64::INFINITY, f64::NEG_INFINITY);
+    for coord in polygon.exterior().coords() {
+        let col = (coord.x - transform[0]) / transform[1];
+        let row = (coord.y - transform[3]) / transform[5];
+        min_col = min_col.min(col);
+        max_col = max_col.max(col);
+        min_row = min_row.min(row);
+        max_row = max_row.max(row);
+    }
+
+    let clamp = |value: f64, size: usize| (value.max(0.0) as usize).min(size);
+    let (row_start, row_end) = (
+        clamp(min_row.floor(), n_rows),
+        clamp(max_row.ceil() + 1.0, n_rows),
+    );
+    let (col_start, col_end) = (
+        clamp(min_col.floor(), n_cols),
+        clamp(max_col.ceil() + 1.0, n_cols),
+    );
+    (row_start..row_end).flat_map(move |row| (col_start..col_end).map(move |col| (row, col)))
+}
+
+/// Footprint axes for pitched roofs: the ridge runs along the longest edge, through
+/// the middle of the footprint's extent across it. Footprints are in geographic
+/// coordinates, so longitudes are scaled by cos(latitude) at the footprint's centre
+/// to make both axes the same length on the ground.
+struct RoofFrame {
+    x_scale: f64,
+    along: (f64, f64),
+    across: (f64, f64),
+    along_range: (f64, f64),
+    across_centre: f64,
+    half_width: f64,
+}
+
+impl RoofFrame {
+    fn new(polygon: &Polygon<f64>) -> Self {
+        let exterior = polygon.exterior();
+        // Rings are closed, so the first vertex is repeated at the end
+        let vertices = exterior.0.iter().skip(1);
+        let centre_lat = vertices.clone().map(|c| c.y).sum::<f64>() / vertices.count() as f64;
+        let x_scale = centre_lat.to_radians().cos();
+        let coords: Vec<Coord<f64>> = exterior
+            .coords()
+            .map(|c| Coord {
+                x: c.x * x_scale,
+                y: c.y,
+            })
+            .collect();
+        let (dx, dy) = coords
+            .windows(2)
+            .map(|edge| (edge[1].x - edge[0].x, edge[1].y - edge[0].y))
+            .max_by(|a, b| a.0.hypot(a.1).total_cmp(&b.0.hypot(b.1)))
+            .unwrap_or((1.0, 0.0));
+        let length = dx.hypot(dy).max(f64::EPSILON);
+        let along = (dx / length, dy / length);
+        let across = (-along.1, along.0);
+
+        let project = |axis: (f64, f64)| {
+            coords
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), c| {
+                    let value = c.x * axis.0 + c.y * axis.1;
+                    (min.min(value), max.max(value))
+                })
+        };
+        let along_range = project(along);
+        let (across_min, across_max) = project(across);
+
+        Self {
+            x_scale,
+            along,
+            across,
+            along_range,
+            across_centre: (across_min + across_max) / 2.0,
+            half_width: ((across_max - across_min) / 2.0).max(f64::EPSILON),
+        }
+    }
+
+    /// Share of the ridge rise reached at a point: 1 on the ridge, 0 at the eaves
+    fn rise_fraction(&self, shape: RoofShape, x: f64, y: f64) -> f32 {
+        let x = x * self.x_scale;
+        let u = x * self.along.0 + y * self.along.1;
+        let v = x * self.across.0 + y * self.across.1;
+        let to_sides = 1.0 - (v - self.across_centre).abs() / self.half_width;
+        let fraction = match shape {
+            RoofShape::Flat => 0.0,
+            RoofShape::Gable => to_sides,
+            RoofShape::Hip => {
+                let to_ends =
+                    (u - self.along_range.0).min(self.along_range.1 - u) / self.half_width;
+                to_sides.min(to_ends)
+            }
+        };
+        fraction.clamp(0.0, 1.0) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2e-5 degrees of longitude by 1e-5 of latitude, near square on the ground at 60°N
+    const TRANSFORM: [f64; 6] = [10.0, 2e-5, 0.0, 60.0, 0.0, -1e-5];
+
+    /// Closed ring around the corners of cells (row0, col0) to (row1, col1)
+    fn cell_rectangle((row0, col0): (usize, usize), (row1, col1): (usize, usize)) -> Vec<Vec<f64>> {
+        let corner = |row, col| {
+            let (x, y) = RasterIO::pixel_to_world(col, row, &TRANSFORM);
+            vec![x, y]
+        };
+        vec![
+            corner(row0, col0),
+            corner(row0, col1),
+            corner(row1, col1),
+            corner(row1, col0),
+            corner(row0, col0),
+        ]
+    }
+
+    /// 8 m eaves and a 4 m rise over a footprint 20 cells east-west by 10 north-south
+    fn building(roof_shape: RoofShape) -> BuildingFootprint {
+        BuildingFootprint {
+            polygon: cell_rectangle((1, 1), (11, 21)),
+            holes: None,
+            height: 8.0,
+            roof_shape: Some(roof_shape),
+            roof_height: Some(4.0),
+        }
+    }
+
+    fn burn(footprints: &[BuildingFootprint]) -> (Array2<f32>, Array2<bool>) {
+        let mut dsm = Array2::from_elem((13, 23), 100.0f32);
+        let dtm = Array2::from_elem((13, 23), 100.0f32);
+        let burned = burn_footprints(&mut dsm, &dtm, &TRANSFORM, footprints).unwrap();
+        (dsm, burned)
+    }
+
+    fn roof_frame(footprint: &BuildingFootprint) -> RoofFrame {
+        RoofFrame::new(&Polygon::new(points_ring(&footprint.polygon), Vec::new()))
+    }
+
+    fn at(row: usize, col: usize) -> (f64, f64) {
+        RasterIO::pixel_to_world(col, row, &TRANSFORM)
+    }
+
+    #[test]
+    fn gable_rises_from_the_long_sides_to_the_ridge() {
+        let frame = roof_frame(&building(RoofShape::Gable));
+        let fraction = |(x, y): (f64, f64)| frame.rise_fraction(RoofShape::Gable, x, y);
+
+        // The ridge runs east-west along row 6, the whole length of the building
+        assert!((fraction(at(6, 2)) - 1.0).abs() < 1e-3);
+        assert!((fraction(at(6, 11)) - 1.0).abs() < 1e-3);
+        assert!((fraction(at(4, 11)) - 0.6).abs() < 1e-3);
+        assert!((fraction(at(8, 11)) - 0.6).abs() < 1e-3);
+        assert!(fraction(at(1, 11)).abs() < 1e-3);
+        assert_eq!(
+            frame.rise_fraction(RoofShape::Flat, at(6, 11).0, at(6, 11).1),
+            0.0
+        );
+    }
+
+    #[test]
+    fn hip_slopes_down_to_the_short_ends() {
+        let frame = roof_frame(&building(RoofShape::Hip));
+        let fraction = |(x, y): (f64, f64)| frame.rise_fraction(RoofShape::Hip, x, y);
+
+        // The ridge is shortened by the half width of 5 rows at each end
+        assert!(fraction(at(6, 1)).abs() < 1e-3);
+        assert!((fraction(at(6, 3)) - 0.4).abs() < 1e-3);
+        assert!((fraction(at(6, 6)) - 1.0).abs() < 1e-3);
+        assert!((fraction(at(6, 16)) - 1.0).abs() < 1e-3);
+        assert!((fraction(at(6, 19)) - 0.4).abs() < 1e-3);
+        // Near a corner the nearer of the side and end slopes wins
+        assert!((fraction(at(3, 3)) - 0.4).abs() < 1e-3);
+    }
+
+    #[test]
+    fn ridge_follows_the_longest_edge_on_the_ground() {
+        // 10 cells east-west by 12 north-south is longer east-west in degrees but
+        // longer north-south on the ground, so the ridge runs north-south along column 6
+        let footprint = BuildingFootprint {
+            polygon: cell_rectangle((0, 1), (12, 11)),
+            ..building(RoofShape::Gable)
+        };
+        let frame = roof_frame(&footprint);
+        let fraction = |(x, y): (f64, f64)| frame.rise_fraction(RoofShape::Gable, x, y);
+        assert!((fraction(at(2, 6)) - 1.0).abs() < 1e-3);
+        assert!((fraction(at(10, 6)) - 1.0).abs() < 1e-3);
+        assert!(fraction(at(6, 1)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn burned_gable_heights() {
+        let (dsm, burned) = burn(&[building(RoofShape::Gable)]);
+
+        // Cells on the footprint's outline are outside it
+        assert!(!burned[[1, 11]] && !burned[[6, 1]] && !burned[[11, 11]]);
+        assert_eq!(dsm[[1, 11]], 100.0);
+        assert_eq!(burned.iter().filter(|&&cell| cell).count(), 9 * 19);
+
+        assert!((dsm[[6, 2]] - 112.0).abs() < 1e-2);
+        assert!((dsm[[6, 11]] - 112.0).abs() < 1e-2);
+        assert!((dsm[[2, 11]] - 108.8).abs() < 1e-2);
+        assert!((dsm[[10, 11]] - 108.8).abs() < 1e-2);
+    }
+
+    #[test]
+    fn burned_hip_heights() {
+        let (dsm, _) = burn(&[building(RoofShape::Hip)]);
+        assert!((dsm[[6, 2]] - 108.8).abs() < 1e-2);
+        assert!((dsm[[6, 11]] - 112.0).abs() < 1e-2);
+        assert!((dsm[[6, 20]] - 108.8).abs() < 1e-2);
+        assert!((dsm[[2, 11]] - 108.8).abs() < 1e-2);
+    }
+
+    #[test]
+    fn flat_roof_stands_on_the_mean_terrain() {
+        let mut dsm = Array2::from_elem((13, 23), 0.0f32);
+        let mut dtm = Array2::from_shape_fn((13, 23), |(row, _)| 100.0 + row as f32);
+        // Missing terrain is left out of the mean, which stays at the middle row's
+        dtm[[2, 2]] = f32::NAN;
+        dtm[[10, 2]] = f32::NAN;
+        let footprint = BuildingFootprint {
+            polygon: cell_rectangle((1, 1), (11, 21)),
+            holes: Some(vec![cell_rectangle((4, 8), (8, 14))]),
+            height: 8.0,
+            roof_shape: None,
+            roof_height: None,
+        };
+        let burned = burn_footprints(&mut dsm, &dtm, &TRANSFORM, &[footprint]).unwrap();
+
+        assert!((dsm[[3, 11]] - 114.0).abs() < 1e-3);
+        assert!((dsm[[9, 11]] - 114.0).abs() < 1e-3);
+        // The courtyard keeps the DSM it had
+        assert!(!burned[[6, 11]]);
+        assert_eq!(dsm[[6, 11]], 0.0);
+    }
+
+    #[test]
+    fn footprints_off_the_terrain_are_skipped() {
+        let mut dsm = Array2::from_elem((13, 23), 100.0f32);
+        let dtm = Array2::from_elem((13, 23), f32::NAN);
+        let burned =
+            burn_footprints(&mut dsm, &dtm, &TRANSFORM, &[building(RoofShape::Hip)]).unwrap();
+        assert!(burned.iter().all(|&cell| !cell));
+        assert!(dsm.iter().all(|&value| value == 100.0));
+    }
+
+    #[test]
+    fn rings_with_too_few_points_are_rejected() {
+        let footprint = BuildingFootprint {
+            polygon: vec![vec![10.0, 60.0], vec![10.1, 60.0]],
+            ..building(RoofShape::Flat)
+        };
+        let mut dsm = Array2::from_elem((13, 23), 100.0f32);
+        let dtm = dsm.clone();
+        assert!(matches!(
+            burn_footprints(&mut dsm, &dtm, &TRANSFORM, &[footprint]),
+            Err(ShadowError::Config(_))
+        ));
+    }
+}
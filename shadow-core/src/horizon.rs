@@ -0,0 +1,55 @@
+use ndarray::Array3;
+
+/// Distance in pixels past the shadow rays' reach that profile rays are traced. A ray
+/// through a cell may enter it up to a diagonal later than a neighbouring ray does.
+pub const HORIZON_RAY_OVERREACH: f64 = 2.0;
+
+/// Per-cell horizon profiles over a fixed number of azimuth sectors.
+///
+/// Each entry stores an upper bound on the tangent of the highest horizon the shadow
+/// ray kernel in `ShadowEngine::calculate_cell_shadow` could meet from a cell anywhere
+/// in one sector, in its height/distance convention (metres of rise per pixel of
+/// horizontal travel). A sun at or above that bound is clear of everything the ray
+/// could hit, so the cell is lit without tracing; only the remaining cells need a ray.
+pub struct HorizonProfile {
+    sectors: usize,
+    horizon_tan: Array3<f32>, // (rows, cols, sectors)
+}
+
+impl HorizonProfile {
+    pub fn new(sectors: usize, horizon_tan: Array3<f32>) -> Self {
+        Self {
+            sectors,
+            horizon_tan,
+        }
+    }
+
+    /// Azimuth (degrees clockwise from north) at the start of a sector
+    pub fn sector_azimuth(sector: usize, sectors: usize) -> f64 {
+        sector as f64 * 360.0 / sectors as f64
+    }
+
+    /// Rays traced per sector for shadow rays reaching `max_distance` pixels. Ray `k` of
+    /// sector `s` points at `sector_azimuth(s) + k * width / rays`; each sector also takes
+    /// the first ray of the next one, so both of its edges are covered. Neighbouring
+    /// rays stay less than a pixel apart out to the traced distance, so a one-pixel
+    /// cell crossed by any direction in the sector is crossed by one of its rays.
+    pub fn rays_per_sector(sectors: usize, max_distance: f64) -> usize {
+        let sector_width = std::f64::consts::TAU / sectors as f64;
+        (sector_width * (max_distance + HORIZON_RAY_OVERREACH))
+            .ceil()
+            .max(1.0) as usize
+    }
+
+    /// Highest horizon tangent in the sector containing an azimuth
+    pub fn horizon_tan_at(&self, row: usize, col: usize, azimuth: f64) -> f32 {
+        let sector_width = 360.0 / self.sectors as f64;
+        let sector = (azimuth.rem_euclid(360.0) / sector_width) as usize % self.sectors;
+        self.horizon_tan[[row, col, sector]]
+    }
+
+    /// Whether the sun clears the highest horizon in its sector, so the cell is lit
+    pub fn sun_clears_horizon(&self, row: usize, col: usize, azimuth: f64, elevation: f64) -> bool {
+        self.horizon_tan_at(row, col, azimuth) as f64 <= elevation.to_radians().tan()
+    }
+}
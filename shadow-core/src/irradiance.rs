@@ -0,0 +1,115 @@
+use chrono::{DateTime, Datelike, Utc};
+
+/// ASHRAE clear-sky coefficients for the 21st of each month: apparent extraterrestrial
+/// irradiance A (W/m²), atmospheric extinction B and diffuse ratio C
+const ASHRAE_COEFFICIENTS: [(f64, f64, f64); 12] = [
+    (1230.0, 0.142, 0.058), // January
+    (1215.0, 0.144, 0.060), // February
+    (1186.0, 0.156, 0.071), // March
+    (1136.0, 0.180, 0.097), // April
+    (1104.0, 0.196, 0.121), // May
+    (1088.0, 0.205, 0.134), // June
+    (1085.0, 0.207, 0.136), // July
+    (1107.0, 0.201, 0.122), // August
+    (1151.0, 0.177, 0.092), // September
+    (1192.0, 0.160, 0.073), // October
+    (1221.0, 0.149, 0.063), // November
+    (1233.0, 0.142, 0.057), // December
+];
+
+/// Clear-sky irradiance for one sun position, in W/m²
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClearSkyIrradiance {
+    /// Beam irradiance on a surface facing the sun
+    pub direct_normal: f64,
+    /// Diffuse irradiance on a horizontal surface under an unobstructed sky
+    pub diffuse_horizontal: f64,
+}
+
+/// ASHRAE clear-sky model: DNI = A·exp(−B / sin(elevation)) and diffuse = C·DNI,
+/// with the monthly coefficients interpolated linearly between the 21st of each
+/// month. Returns zero irradiance while the sun is below the horizon.
+pub fn clear_sky(timestamp: &DateTime<Utc>, elevation: f64) -> ClearSkyIrradiance {
+    if elevation <= 0.0 {
+        return ClearSkyIrradiance::default();
+    }
+
+    let (a, b, c) = monthly_coefficients(timestamp);
+    let direct_normal = a * (-b / elevation.to_radians().sin()).exp();
+    ClearSkyIrradiance {
+        direct_normal,
+        diffuse_horizontal: c * direct_normal,
+    }
+}
+
+/// Coefficients for a date, interpolated between the tabulated 21st-of-month values
+fn monthly_coefficients(timestamp: &DateTime<Utc>) -> (f64, f64, f64) {
+    let month = timestamp.month0() as usize;
+    let day = timestamp.day() as f64;
+    let days_in_month = 30.44;
+
+    // Position relative to the 21st of this month, in months
+    let offset = (day - 21.0) / days_in_month;
+    let (from, to, weight) = if offset >= 0.0 {
+        (month, (month + 1) % 12, offset)
+    } else {
+        ((month + 11) % 12, month, 1.0 + offset)
+    };
+
+    let (a0, b0, c0) = ASHRAE_COEFFICIENTS[from];
+    let (a1, b1, c1) = ASHRAE_COEFFICIENTS[to];
+    (
+        a0 + (a1 - a0) * weight,
+        b0 + (b1 - b0) * weight,
+        c0 + (c1 - c0) * weight,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(month: u32, day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, month, day, 12, 0, 0).unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn tabulated_day_uses_its_month_coefficients() {
+        // 21 June: A = 1088, B = 0.205, C = 0.134; air mass 2 at 30° elevation
+        let overhead = clear_sky(&at(6, 21), 90.0);
+        assert_close(overhead.direct_normal, 1088.0 * (-0.205f64).exp());
+        assert_close(overhead.diffuse_horizontal, 0.134 * overhead.direct_normal);
+
+        let low = clear_sky(&at(6, 21), 30.0);
+        assert_close(low.direct_normal, 1088.0 * (-0.41f64).exp());
+        assert!(low.direct_normal < overhead.direct_normal);
+    }
+
+    #[test]
+    fn coefficients_interpolate_across_new_year() {
+        // 5 January lies 15 days after 21 December and 16 before 21 January
+        let weight = 1.0 - 16.0 / 30.44;
+        let (a, b, c) = monthly_coefficients(&at(1, 5));
+        assert_close(a, 1233.0 + (1230.0 - 1233.0) * weight);
+        assert_close(b, 0.142);
+        assert_close(c, 0.057 + (0.058 - 0.057) * weight);
+    }
+
+    #[test]
+    fn sun_below_horizon_gives_no_irradiance() {
+        for elevation in [0.0, -5.0] {
+            let sky = clear_sky(&at(3, 1), elevation);
+            assert_eq!((sky.direct_normal, sky.diffuse_horizontal), (0.0, 0.0));
+        }
+    }
+}
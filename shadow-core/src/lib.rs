@@ -0,0 +1,28 @@
+//! Shadow analysis engine for DSM/DTM rasters, independent of any GUI.
+//!
+//! The desktop app, the `shadow-cli` binary and other Rust services embed it the same
+//! way: build a [`shadow_engine::ShadowEngine`] (or use [`pipeline::prepare_engine`]
+//! for the app's load-clip-buffer steps) and pass a [`progress::ProgressSink`] to
+//! receive progress updates.
+
+pub mod comparison;
+pub mod facade;
+pub mod footprints;
+pub mod horizon;
+pub mod irradiance;
+pub mod pipeline;
+pub mod point_cloud;
+pub mod polygonize;
+pub mod progress;
+pub mod pv;
+pub mod raster_io;
+pub mod roof;
+pub mod shadow_engine;
+pub mod shadow_store;
+pub mod sun_position;
+pub mod surface;
+pub mod sweep_line;
+pub mod timelapse;
+pub mod types;
+pub mod vectorize;
+pub mod weather;
@@ -0,0 +1,314 @@
+use crate::footprints;
+use crate::progress::ProgressSink;
+use crate::raster_io::RasterIO;
+use crate::shadow_engine::ShadowEngine;
+use crate::types::*;
+use crate::weather;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Shadow engine for a config, with rasters loaded and clipped to the AOI plus buffer
+pub struct PreparedEngine {
+    pub engine: ShadowEngine,
+    pub transform: [f64; 6],
+    /// Cell size in metres at the AOI's centre latitude
+    pub resolution: f64,
+    pub projection: String,
+}
+
+/// Load the rasters of a config, clip them to the AOI plus the automatic buffer and
+/// set up the engine on the clipped grid. Progress goes to the sink when one is given;
+/// the terminal progress bars are always shown.
+pub fn prepare_engine(
+    config: &Config,
+    progress_sink: Option<Arc<dyn ProgressSink>>,
+    cancel_token: CancellationToken,
+) -> Result<PreparedEngine, String> {
+    // Load rasters, gridding point clouds at the configured resolution
+    let dtm_data = RasterIO::read_dtm(
+        Path::new(&config.dtm_path),
+        config.get_point_cloud_resolution(),
+    )
+    .map_err(|e| format!("Failed to load DTM: {}", e))?;
+    let dsm_data = RasterIO::read_dsm(
+        Path::new(&config.dsm_path),
+        config.get_point_cloud_resolution(),
+    )
+    .map_err(|e| format!("Failed to load DSM: {}", e))?;
+
+    // Convert AOI to polygon
+    let polygon = config
+        .to_polygon()
+        .map_err(|e| format!("Failed to parse AOI: {}", e))?;
+
+    // Calculate automatic buffer based on terrain and solar geometry
+    let auto_buffer_meters = RasterIO::calculate_automatic_buffer(
+        &dtm_data,
+        &dsm_data,
+        &polygon,
+        &config.start_date,
+        &config.end_date,
+    )
+    .map_err(|e| format!("Failed to calculate automatic buffer: {}", e))?;
+
+    // Get the center latitude for conversion
+    let center_lat = polygon.exterior().coords().map(|c| c.y).sum::<f64>()
+        / polygon.exterior().coords().count() as f64;
+
+    let buffer_degrees = meters_to_degrees(auto_buffer_meters, center_lat);
+    println!(
+        "Automatic buffer: {:.1}m = {:.6}° at latitude {:.3}°",
+        auto_buffer_meters, buffer_degrees, center_lat
+    );
+
+    // Clip to AOI with buffer (now in degrees)
+    let dtm_clipped = RasterIO::clip_to_aoi(&dtm_data, &polygon, buffer_degrees)
+        .map_err(|e| format!("Failed to clip DTM: {}", e))?;
+    let dsm_clipped = RasterIO::clip_to_aoi(&dsm_data, &polygon, buffer_degrees)
+        .map_err(|e| format!("Failed to clip DSM: {}", e))?;
+
+    // Extract 2D arrays
+    let dtm_2d = dtm_clipped.data.slice(ndarray::s![0, .., ..]).to_owned();
+    let mut dsm_2d = dsm_clipped.data.slice(ndarray::s![0, .., ..]).to_owned();
+
+    // Proposed buildings replace whatever the surveyed DSM has inside their footprints
+    let burned = match config.proposed_buildings.as_deref() {
+        Some(buildings) if !buildings.is_empty() => Some(
+            footprints::burn_footprints(&mut dsm_2d, &dtm_2d, &dsm_clipped.transform, buildings)
+                .map_err(|e| format!("Failed to add proposed buildings: {}", e))?,
+        ),
+        _ => None,
+    };
+
+    // Optional canopy/classification raster, clipped the same way as the DSM
+    let vegetation_2d = match &config.vegetation_path {
+        Some(path) => {
+            let vegetation_data = RasterIO::read_raster(Path::new(path))
+                .map_err(|e| format!("Failed to load vegetation raster: {}", e))?;
+            let vegetation_clipped =
+                RasterIO::clip_to_aoi(&vegetation_data, &polygon, buffer_degrees)
+                    .map_err(|e| format!("Failed to clip vegetation raster: {}", e))?;
+            let mut vegetation_2d = vegetation_clipped
+                .data
+                .slice(ndarray::s![0, .., ..])
+                .to_owned();
+            if vegetation_2d.dim() != dsm_2d.dim() {
+                return Err(format!(
+                    "Vegetation raster grid {:?} does not match DSM grid {:?}",
+                    vegetation_2d.dim(),
+                    dsm_2d.dim()
+                ));
+            }
+            // Trees on a proposed building's site are gone once it is built
+            if let Some(burned) = &burned {
+                ndarray::Zip::from(&mut vegetation_2d)
+                    .and(burned)
+                    .for_each(|value, &burned| {
+                        if burned {
+                            *value = f32::NAN;
+                        }
+                    });
+            }
+            Some(vegetation_2d)
+        }
+        None => None,
+    };
+
+    // Calculate pixel resolution in meters
+    let resolution = degrees_to_meters(dtm_clipped.transform[1].abs(), center_lat);
+    println!(
+        "Pixel resolution: {:.6}° = {:.2}m at latitude {:.3}°",
+        dtm_clipped.transform[1].abs(),
+        resolution,
+        center_lat
+    );
+
+    // Create shadow engine with automatic buffer in meters
+    let mut config_with_meter_buffer = config.clone();
+    config_with_meter_buffer.buffer_meters = Some(auto_buffer_meters); // Use automatic buffer
+
+    let engine = ShadowEngine::new(
+        dtm_2d,
+        dsm_2d,
+        resolution,
+        dtm_clipped.transform,
+        config_with_meter_buffer,
+    )
+    .with_cancel_token(cancel_token);
+    let engine = match progress_sink {
+        Some(sink) => engine.with_progress_sink(sink),
+        None => engine,
+    };
+    let engine = match &vegetation_2d {
+        Some(canopy) => engine.with_vegetation(canopy),
+        None => engine,
+    };
+    let engine = match &config.weather_path {
+        Some(path) => engine.with_weather(
+            weather::WeatherData::from_path(Path::new(path))
+                .map_err(|e| format!("Failed to load weather file: {}", e))?,
+        ),
+        None => engine,
+    };
+
+    Ok(PreparedEngine {
+        engine,
+        transform: dtm_clipped.transform,
+        resolution,
+        projection: dtm_clipped.projection,
+    })
+}
+
+/// Set every cell outside the AOI to NaN, in the time series and all summary layers
+pub fn mask_results_to_aoi(
+    results: &mut ShadowResult,
+    config: &Config,
+    transform: &[f64; 6],
+) -> Result<(), String> {
+    let polygon = config
+        .to_polygon()
+        .map_err(|e| format!("Failed to parse AOI for masking: {}", e))?;
+
+    // Mask the shadow fraction data (time series)
+    RasterIO::mask_shadow_store_to_aoi(&mut results.shadow_fraction, &polygon, transform, f32::NAN)
+        .map_err(|e| format!("Failed to mask shadow fraction results to AOI: {}", e))?;
+
+    // Mask all summary stats layers
+    let summary_stats = &mut results.summary_stats;
+    let fixed_layers = [
+        ("total shadow hours", &mut summary_stats.total_shadow_hours),
+        (
+            "avg shadow percentage",
+            &mut summary_stats.avg_shadow_percentage,
+        ),
+        (
+            "max consecutive shadow",
+            &mut summary_stats.max_consecutive_shadow,
+        ),
+        (
+            "morning shadow hours",
+            &mut summary_stats.morning_shadow_hours,
+        ),
+        ("noon shadow hours", &mut summary_stats.noon_shadow_hours),
+        (
+            "afternoon shadow hours",
+            &mut summary_stats.afternoon_shadow_hours,
+        ),
+        (
+            "solar efficiency percentage",
+            &mut summary_stats.solar_efficiency_percentage,
+        ),
+        ("daily solar hours", &mut summary_stats.daily_solar_hours),
+        (
+            "total available solar hours",
+            &mut summary_stats.total_available_solar_hours,
+        ),
+    ];
+    for (description, layer) in fixed_layers {
+        RasterIO::mask_results_to_aoi(layer, &polygon, transform, f32::NAN)
+            .map_err(|e| format!("Failed to mask {} to AOI: {}", description, e))?;
+    }
+
+    for (description, layer) in results.summary_stats.optional_layers_mut() {
+        RasterIO::mask_results_to_aoi(layer, &polygon, transform, f32::NAN)
+            .map_err(|e| format!("Failed to mask {} to AOI: {}", description, e))?;
+    }
+
+    println!("Results masked to AOI boundaries for visualization and analysis");
+    Ok(())
+}
+
+/// Write the summary layers followed by one band per timestamp, each band named by
+/// its description so results files can be loaded again
+pub fn write_results_geotiff(
+    path: &Path,
+    results: &ShadowResult,
+    transform: &[f64; 6],
+    projection: &str,
+) -> Result<(), ShadowError> {
+    // Collect summary stats; the time series is streamed band by band below
+    let summary_stats = &results.summary_stats;
+    let optional_layers = summary_stats.optional_layers();
+    let mut summary_layers = vec![
+        &summary_stats.total_shadow_hours,
+        &summary_stats.avg_shadow_percentage,
+        &summary_stats.max_consecutive_shadow,
+        &summary_stats.morning_shadow_hours,
+        &summary_stats.noon_shadow_hours,
+        &summary_stats.afternoon_shadow_hours,
+        &summary_stats.solar_efficiency_percentage,
+        &summary_stats.daily_solar_hours,
+        &summary_stats.total_available_solar_hours,
+    ];
+    summary_layers.extend(optional_layers.iter().map(|(_, layer)| *layer));
+    let n_summary = summary_layers.len();
+    let (n_times, n_rows, n_cols) = results.shadow_fraction.dim();
+
+    // Create band descriptions for better identification
+    let mut band_descriptions = vec![
+        "Total_Shadow_Hours".to_string(),
+        "Average_Shadow_Fraction_(0-1)".to_string(),
+        "Max_Consecutive_Shadow_Hours".to_string(),
+        "Morning_Shadow_Hours_(before_solar_noon_minus_2h)".to_string(),
+        "Noon_Shadow_Hours_(solar_noon_±2h)".to_string(),
+        "Afternoon_Shadow_Hours_(after_solar_noon_plus_2h)".to_string(),
+        "Solar_Efficiency_Fraction_(0-1)".to_string(),
+        "Average_Daily_Solar_Hours".to_string(),
+        "Total_Available_Solar_Hours".to_string(),
+    ];
+    for (description, _) in &optional_layers {
+        band_descriptions.push(description.to_string());
+    }
+
+    // Add timestamp descriptions for each time layer
+    for timestamp in &results.timestamps {
+        band_descriptions.push(timestamp.format("%Y-%m-%d_%H:%M_UTC").to_string());
+    }
+
+    // Write GeoTIFF with band descriptions, summary layers first then time series
+    RasterIO::write_geotiff_bands(
+        path,
+        n_summary + n_times,
+        (n_rows, n_cols),
+        transform,
+        projection,
+        &band_descriptions,
+        |band_idx| {
+            if band_idx < n_summary {
+                Ok(summary_layers[band_idx]
+                    .slice(ndarray::s![0, .., ..])
+                    .to_owned())
+            } else {
+                results.shadow_fraction.read_slice(band_idx - n_summary)
+            }
+        },
+    )
+}
+
+// Helper function to convert meters to degrees
+pub fn meters_to_degrees(meters: f64, latitude: f64) -> f64 {
+    // At the equator: 1 degree ≈ 111,320 meters
+    // At latitude φ: 1 degree longitude ≈ 111,320 * cos(φ) meters
+    // 1 degree latitude ≈ 111,320 meters (approximately constant)
+
+    let lat_rad = latitude.to_radians();
+
+    // For longitude: meters / (111320 * cos(latitude))
+    // For latitude: meters / 111320
+    // We'll use an average for a rough square buffer
+
+    let lon_meters_per_degree = 111320.0 * lat_rad.cos();
+    let lat_meters_per_degree = 111320.0;
+
+    // Use the average for a roughly square buffer
+    let avg_meters_per_degree = (lon_meters_per_degree + lat_meters_per_degree) / 2.0;
+
+    meters / avg_meters_per_degree
+}
+
+// Helper function to convert degrees to meters
+pub fn degrees_to_meters(degrees: f64, latitude: f64) -> f64 {
+    let lat_rad = latitude.to_radians();
+    let lon_meters_per_degree = 111320.0 * lat_rad.cos();
+    degrees * lon_meters_per_degree
+}
@@ -0,0 +1,449 @@
+use crate::pipeline;
+use crate::types::{RasterData, ShadowError};
+use gdal::spatial_ref::{CoordTransform, SpatialRef};
+use las::Read;
+use ndarray::Array2;
+use std::path::Path;
+
+/// ASPRS class codes used when gridding
+const CLASS_GROUND: u8 = 2;
+const CLASS_LOW_NOISE: u8 = 7;
+const CLASS_HIGH_NOISE: u8 = 18;
+
+/// Length of one degree of latitude, as used by `pipeline::degrees_to_meters`
+const METRES_PER_DEGREE_LAT: f64 = 111_320.0;
+
+/// Points reprojected per call into GDAL
+const REPROJECT_BATCH: usize = 65_536;
+
+/// Points traced along each edge of the header bounds when reprojecting them
+const BOUNDS_EDGE_POINTS: usize = 21;
+
+/// LASF_Projection record ids of the OGC WKT and GeoTIFF key directory records
+const WKT_RECORD_ID: u16 = 2112;
+const GEO_KEY_DIRECTORY_RECORD_ID: u16 = 34735;
+
+/// GeoTIFF keys naming the EPSG code of a projected or geographic CRS
+const PROJECTED_CS_TYPE_GEO_KEY: u16 = 3072;
+const GEOGRAPHIC_TYPE_GEO_KEY: u16 = 2048;
+
+/// GeoTIFF value for a CRS that is not in the EPSG registry
+const GEO_KEY_USER_DEFINED: u16 = 32767;
+
+/// Which surface to grid from a point cloud
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointCloudSurface {
+    /// Highest first return per cell (DSM)
+    FirstReturnMax,
+    /// Mean of ground-classified points per cell (DTM)
+    GroundMean,
+}
+
+/// Whether a path is a LAS or LAZ point cloud, judged by its extension
+pub fn is_point_cloud(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("las") || ext.eq_ignore_ascii_case("laz"))
+}
+
+/// Coordinate system of the rasters gridded from a LAS/LAZ file as WKT. Points are
+/// reprojected to WGS84, so this only checks that the header has a coordinate system.
+pub fn read_projection(path: &Path) -> Result<String, ShadowError> {
+    let reader = las::Reader::from_path(path)
+        .map_err(|e| ShadowError::General(format!("Failed to open point cloud: {}", e)))?;
+    source_spatial_ref(reader.header())?;
+    Ok(SpatialRef::from_epsg(4326)?.to_wkt()?)
+}
+
+/// Grid a LAS/LAZ file into a single-band WGS84 raster whose cells are `resolution`
+/// metres on a side, north up.
+///
+/// Points are reprojected from the file's coordinate system, since the analysis
+/// works in geographic coordinates. Cells are square in metres at the centre
+/// latitude, so the longitude step is wider than the latitude step by 1/cos(lat).
+/// The grid is snapped to multiples of the cell size around the reprojected bounds,
+/// so the DSM and DTM gridded from the same file line up cell for cell. Noise points
+/// are skipped. Cells without points are filled from their neighbours, which also
+/// fills the ground under buildings in the DTM.
+pub fn grid(
+    path: &Path,
+    surface: PointCloudSurface,
+    resolution: f64,
+) -> Result<RasterData, ShadowError> {
+    let mut reader = las::Reader::from_path(path)
+        .map_err(|e| ShadowError::General(format!("Failed to open point cloud: {}", e)))?;
+    let header = reader.header().clone();
+
+    let source = source_spatial_ref(&header)?;
+    let target = SpatialRef::from_epsg(4326)?;
+    for spatial_ref in [&source, &target] {
+        spatial_ref.set_axis_mapping_strategy(
+            gdal_sys::OSRAxisMappingStrategy::OAMS_TRADITIONAL_GIS_ORDER,
+        );
+    }
+    let to_geographic = CoordTransform::new(&source, &target)?;
+
+    let (lon_min, lat_min, lon_max, lat_max) = geographic_bounds(&header, &to_geographic)?;
+    let center_lat = (lat_min + lat_max) / 2.0;
+    let lon_step = resolution / pipeline::degrees_to_meters(1.0, center_lat);
+    let lat_step = resolution / METRES_PER_DEGREE_LAT;
+
+    let (min_x, max_y, n_rows, n_cols) =
+        snap_grid((lon_min, lat_min, lon_max, lat_max), lon_step, lat_step);
+
+    println!(
+        "Gridding {} points from {:?} at {} m into {}x{} WGS84 cells ({:?})",
+        header.number_of_points(),
+        path,
+        resolution,
+        n_rows,
+        n_cols,
+        surface
+    );
+
+    let mut sum = Array2::<f64>::zeros((n_rows, n_cols));
+    let mut count = Array2::<u32>::zeros((n_rows, n_cols));
+    let mut highest = Array2::<f64>::from_elem((n_rows, n_cols), f64::NEG_INFINITY);
+
+    // Points are reprojected in batches, which is far cheaper than one at a time
+    let mut xs = Vec::with_capacity(REPROJECT_BATCH);
+    let mut ys = Vec::with_capacity(REPROJECT_BATCH);
+    let mut zs = Vec::with_capacity(REPROJECT_BATCH);
+    let mut points = reader.points();
+    loop {
+        xs.clear();
+        ys.clear();
+        zs.clear();
+        for point in points.by_ref() {
+            let point =
+                point.map_err(|e| ShadowError::General(format!("Failed to read point: {}", e)))?;
+            let class = u8::from(point.classification);
+            if class == CLASS_LOW_NOISE || class == CLASS_HIGH_NOISE {
+                continue;
+            }
+            let keep = match surface {
+                PointCloudSurface::FirstReturnMax => point.return_number <= 1,
+                PointCloudSurface::GroundMean => class == CLASS_GROUND,
+            };
+            if !keep {
+                continue;
+            }
+
+            xs.push(point.x);
+            ys.push(point.y);
+            zs.push(point.z);
+            if xs.len() == REPROJECT_BATCH {
+                break;
+            }
+        }
+        if xs.is_empty() {
+            break;
+        }
+
+        // Heights stay in the file's vertical units, like those of raster inputs
+        to_geographic.transform_coords(&mut xs, &mut ys, &mut [])?;
+        for ((&x, &y), &z) in xs.iter().zip(&ys).zip(&zs) {
+            let col = ((x - min_x) / lon_step).floor();
+            let row = ((max_y - y) / lat_step).floor();
+            if col < 0.0 || row < 0.0 || col >= n_cols as f64 || row >= n_rows as f64 {
+                continue;
+            }
+            let cell = (row as usize, col as usize);
+            sum[cell] += z;
+            count[cell] += 1;
+            highest[cell] = highest[cell].max(z);
+        }
+    }
+
+    if surface == PointCloudSurface::GroundMean && count.iter().all(|&n| n == 0) {
+        return Err(ShadowError::Config(
+            "point cloud has no ground-classified points".to_string(),
+        ));
+    }
+
+    let mut elevation = Array2::<f32>::from_elem((n_rows, n_cols), f32::NAN);
+    for ((cell, &n), value) in count.indexed_iter().zip(elevation.iter_mut()) {
+        if n > 0 {
+            *value = match surface {
+                PointCloudSurface::FirstReturnMax => highest[cell] as f32,
+                PointCloudSurface::GroundMean => (sum[cell] / n as f64) as f32,
+            };
+        }
+    }
+
+    let filled = fill_holes(&mut elevation);
+    if filled > 0 {
+        println!("Filled {} empty cells from their neighbours", filled);
+    }
+
+    Ok(RasterData {
+        data: elevation.insert_axis(ndarray::Axis(0)),
+        transform: [min_x, lon_step, 0.0, max_y, 0.0, -lat_step],
+        projection: target.to_wkt()?,
+        no_data_value: None,
+    })
+}
+
+/// (min_x, max_y, n_rows, n_cols) of a grid with the given steps whose origin is
+/// snapped to whole cells and which covers (lon_min, lat_min, lon_max, lat_max)
+fn snap_grid(
+    (lon_min, lat_min, lon_max, lat_max): (f64, f64, f64, f64),
+    lon_step: f64,
+    lat_step: f64,
+) -> (f64, f64, usize, usize) {
+    let min_x = (lon_min / lon_step).floor() * lon_step;
+    let max_y = (lat_max / lat_step).ceil() * lat_step;
+    let n_cols = (((lon_max - min_x) / lon_step).floor() as usize + 1).max(1);
+    let n_rows = (((max_y - lat_min) / lat_step).floor() as usize + 1).max(1);
+    (min_x, max_y, n_rows, n_cols)
+}
+
+/// (lon_min, lat_min, lon_max, lat_max) of the header bounds, tracing each edge so
+/// that curved edges of a projected box are covered
+fn geographic_bounds(
+    header: &las::Header,
+    to_geographic: &CoordTransform,
+) -> Result<(f64, f64, f64, f64), ShadowError> {
+    let bounds = header.bounds();
+    let (width, height) = (bounds.max.x - bounds.min.x, bounds.max.y - bounds.min.y);
+    let mut xs = Vec::with_capacity(4 * BOUNDS_EDGE_POINTS);
+    let mut ys = Vec::with_capacity(4 * BOUNDS_EDGE_POINTS);
+    for i in 0..BOUNDS_EDGE_POINTS {
+        let t = i as f64 / (BOUNDS_EDGE_POINTS - 1) as f64;
+        for (x, y) in [
+            (bounds.min.x + t * width, bounds.min.y),
+            (bounds.min.x + t * width, bounds.max.y),
+            (bounds.min.x, bounds.min.y + t * height),
+            (bounds.max.x, bounds.min.y + t * height),
+        ] {
+            xs.push(x);
+            ys.push(y);
+        }
+    }
+    to_geographic.transform_coords(&mut xs, &mut ys, &mut [])?;
+
+    let fold = |values: &[f64]| {
+        values
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &v| {
+                (min.min(v), max.max(v))
+            })
+    };
+    let (lon_min, lon_max) = fold(&xs);
+    let (lat_min, lat_max) = fold(&ys);
+    Ok((lon_min, lat_min, lon_max, lat_max))
+}
+
+/// Fill NaN cells with the mean of their filled 8-neighbours, growing inward from
+/// the edges of each hole one ring per pass. Returns the number of cells filled.
+fn fill_holes(elevation: &mut Array2<f32>) -> usize {
+    let (n_rows, n_cols) = elevation.dim();
+    let mut holes: Vec<(usize, usize)> = elevation
+        .indexed_iter()
+        .filter(|(_, value)| value.is_nan())
+        .map(|(cell, _)| cell)
+        .collect();
+    let total = holes.len();
+    if total == n_rows * n_cols {
+        // Nothing to grow from; leave the grid empty rather than invent heights
+        return 0;
+    }
+
+    while !holes.is_empty() {
+        let updates: Vec<((usize, usize), f32)> = holes
+            .iter()
+            .filter_map(|&(row, col)| {
+                let mut sum = 0.0f32;
+                let mut n = 0;
+                for n_row in row.saturating_sub(1)..=(row + 1).min(n_rows - 1) {
+                    for n_col in col.saturating_sub(1)..=(col + 1).min(n_cols - 1) {
+                        let value = elevation[[n_row, n_col]];
+                        if !value.is_nan() {
+                            sum += value;
+                            n += 1;
+                        }
+                    }
+                }
+                (n > 0).then(|| ((row, col), sum / n as f32))
+            })
+            .collect();
+
+        for &(cell, value) in &updates {
+            elevation[cell] = value;
+        }
+        holes.retain(|&cell| elevation[cell].is_nan());
+    }
+
+    total
+}
+
+/// Coordinate system from the header's OGC WKT record, or failing that the EPSG
+/// code in its GeoTIFF key directory. Files with neither cannot be placed on the map.
+fn source_spatial_ref(header: &las::Header) -> Result<SpatialRef, ShadowError> {
+    let projection_record = |record_id: u16| {
+        header
+            .vlrs()
+            .iter()
+            .chain(header.evlrs())
+            .find(|vlr| vlr.user_id == "LASF_Projection" && vlr.record_id == record_id)
+    };
+
+    let wkt = projection_record(WKT_RECORD_ID)
+        .map(|vlr| {
+            String::from_utf8_lossy(&vlr.data)
+                .trim_end_matches('\0')
+                .to_string()
+        })
+        .filter(|wkt| !wkt.trim().is_empty());
+    if let Some(wkt) = wkt {
+        return Ok(SpatialRef::from_wkt(&wkt)?);
+    }
+
+    match projection_record(GEO_KEY_DIRECTORY_RECORD_ID).and_then(|vlr| geo_key_epsg(&vlr.data)) {
+        Some(epsg) => Ok(SpatialRef::from_epsg(epsg)?),
+        None => Err(ShadowError::Config(
+            "point cloud has no WKT or EPSG coordinate system".to_string(),
+        )),
+    }
+}
+
+/// EPSG code of the projected CRS, or else the geographic CRS, in a GeoTIFF key
+/// directory: a header of four u16s followed by (key, location, count, value)
+/// entries. Only codes stored inline (location 0) name an EPSG CRS.
+fn geo_key_epsg(data: &[u8]) -> Option<u32> {
+    let words: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    let n_keys = *words.get(3)? as usize;
+    let keys: Vec<&[u16]> = words[4..].chunks_exact(4).take(n_keys).collect();
+
+    // A projected CRS key takes precedence even when it is not an EPSG code, since
+    // the geographic key then only names the datum beneath it
+    let value = |key: u16| {
+        keys.iter()
+            .find(|entry| entry[0] == key)
+            .map(|entry| (entry[1] == 0).then_some(entry[3]))
+    };
+    value(PROJECTED_CS_TYPE_GEO_KEY)
+        .or_else(|| value(GEOGRAPHIC_TYPE_GEO_KEY))
+        .flatten()
+        .filter(|&code| code != 0 && code != GEO_KEY_USER_DEFINED)
+        .map(u32::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// GeoTIFF key directory bytes holding the given (key, location, value) entries
+    fn geo_keys(entries: &[(u16, u16, u16)]) -> Vec<u8> {
+        let mut words = vec![1, 1, 0, entries.len() as u16];
+        for &(key, location, value) in entries {
+            words.extend([key, location, 1, value]);
+        }
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn geo_keys_prefer_the_projected_crs() {
+        let keys = geo_keys(&[(1024, 0, 1), (2048, 0, 4269), (3072, 0, 26918)]);
+        assert_eq!(geo_key_epsg(&keys), Some(26918));
+    }
+
+    #[test]
+    fn geo_keys_fall_back_to_the_geographic_crs() {
+        let keys = geo_keys(&[(1024, 0, 2), (2048, 0, 4326)]);
+        assert_eq!(geo_key_epsg(&keys), Some(4326));
+    }
+
+    #[test]
+    fn geo_keys_without_an_epsg_code_are_rejected() {
+        // User-defined projection over a known datum
+        let keys = geo_keys(&[(2048, 0, 4326), (3072, 0, GEO_KEY_USER_DEFINED)]);
+        assert_eq!(geo_key_epsg(&keys), None);
+        // Code stored in another tag rather than inline
+        assert_eq!(geo_key_epsg(&geo_keys(&[(3072, 34736, 0)])), None);
+        assert_eq!(geo_key_epsg(&geo_keys(&[])), None);
+        assert_eq!(geo_key_epsg(&[1, 0]), None);
+    }
+
+    #[test]
+    fn snapped_grids_line_up_cell_for_cell() {
+        let (lon_step, lat_step) = (0.25, 0.125);
+        let (min_x, max_y, n_rows, n_cols) =
+            snap_grid((10.1, 50.05, 10.9, 50.3), lon_step, lat_step);
+        assert_eq!((min_x, max_y), (10.0, 50.375));
+        assert_eq!((n_rows, n_cols), (3, 4));
+
+        // A grid over different bounds with the same steps is offset by whole cells
+        let (other_x, other_y, _, _) = snap_grid((10.6, 49.9, 11.2, 50.2), lon_step, lat_step);
+        let col_offset = (other_x - min_x) / lon_step;
+        let row_offset = (max_y - other_y) / lat_step;
+        assert!((col_offset - col_offset.round()).abs() < 1e-9);
+        assert!((row_offset - row_offset.round()).abs() < 1e-9);
+        assert_eq!((col_offset.round(), row_offset.round()), (2.0, 1.0));
+    }
+
+    #[test]
+    fn snapped_grid_covers_the_bounds() {
+        let bounds = (-0.37, 51.49, -0.3, 51.52);
+        let (lon_step, lat_step) = (1.0 / 7000.0, 1.0 / 11000.0);
+        let (min_x, max_y, n_rows, n_cols) = snap_grid(bounds, lon_step, lat_step);
+        assert!(min_x <= bounds.0 && min_x > bounds.0 - lon_step);
+        assert!(max_y >= bounds.3 && max_y < bounds.3 + lat_step);
+        assert!(min_x + n_cols as f64 * lon_step > bounds.2);
+        assert!(max_y - n_rows as f64 * lat_step < bounds.1);
+    }
+
+    #[test]
+    fn fill_holes_averages_filled_neighbours() {
+        let mut elevation = Array2::from_shape_vec(
+            (3, 3),
+            vec![1.0, 2.0, 3.0, 4.0, f32::NAN, 6.0, 7.0, 8.0, 9.0],
+        )
+        .unwrap();
+        assert_eq!(fill_holes(&mut elevation), 1);
+        assert_eq!(elevation[[1, 1]], 5.0);
+    }
+
+    #[test]
+    fn fill_holes_grows_inward_ring_by_ring() {
+        // A 5x5 hole inside a flat 10 m border, with one 20 m cell on the border
+        let mut elevation = Array2::from_shape_fn((7, 7), |(row, col)| {
+            if row == 0 || col == 0 || row == 6 || col == 6 {
+                10.0
+            } else {
+                f32::NAN
+            }
+        });
+        elevation[[0, 3]] = 20.0;
+        assert_eq!(fill_holes(&mut elevation), 25);
+        assert!(elevation.iter().all(|value| !value.is_nan()));
+
+        // The first ring takes only border cells; the centre is filled last from it
+        assert!((elevation[[1, 3]] - 40.0 / 3.0).abs() < 1e-5);
+        assert_eq!(elevation[[5, 5]], 10.0);
+        let ring: f32 = [
+            (2, 2),
+            (2, 3),
+            (2, 4),
+            (3, 2),
+            (3, 4),
+            (4, 2),
+            (4, 3),
+            (4, 4),
+        ]
+        .iter()
+        .map(|&cell| elevation[cell])
+        .sum();
+        assert!((elevation[[3, 3]] - ring / 8.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn fill_holes_leaves_an_empty_grid_empty() {
+        let mut elevation = Array2::from_elem((4, 4), f32::NAN);
+        assert_eq!(fill_holes(&mut elevation), 0);
+        assert!(elevation.iter().all(|value| value.is_nan()));
+    }
+}
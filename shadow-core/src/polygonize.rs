@@ -0,0 +1,245 @@
+use geo::algorithm::area::Area;
+use geo::algorithm::contains::Contains;
+use geo_types::{Coord, LineString, Polygon};
+use std::collections::{HashMap, HashSet};
+
+/// Outline a set of raster cells as polygons in (col, row) pixel coordinates.
+///
+/// Cell (row, col) is the square centred on (col, row), as elsewhere in the engine.
+/// Cells that touch only at a corner end up in separate polygons, and enclosed gaps
+/// become holes. Runs of collinear cell edges are merged into single segments.
+pub fn cell_polygons(cells: &[(usize, usize)]) -> Vec<Polygon<f64>> {
+    let members: HashSet<(usize, usize)> = cells.iter().cloned().collect();
+    let contains = |row: isize, col: isize| -> bool {
+        row >= 0 && col >= 0 && members.contains(&(row as usize, col as usize))
+    };
+
+    // Boundary edges between lattice corners (x, y), directed so the region is on the
+    // right when walking in pixel space (y down)
+    let mut outgoing: HashMap<(isize, isize), Vec<(isize, isize)>> = HashMap::new();
+    for &(row, col) in cells {
+        let (r, c) = (row as isize, col as isize);
+        let sides = [
+            (r - 1, c, (c, r), (c + 1, r)),         // Top
+            (r, c + 1, (c + 1, r), (c + 1, r + 1)), // Right
+            (r + 1, c, (c + 1, r + 1), (c, r + 1)), // Bottom
+            (r, c - 1, (c, r + 1), (c, r)),         // Left
+        ];
+        for (n_row, n_col, from, to) in sides {
+            if !contains(n_row, n_col) {
+                outgoing.entry(from).or_default().push(to);
+            }
+        }
+    }
+
+    // Chain edges into closed rings
+    let mut rings: Vec<Vec<(isize, isize)>> = Vec::new();
+    let mut starts: Vec<(isize, isize)> = outgoing.keys().cloned().collect();
+    starts.sort_unstable();
+    for start in starts {
+        while let Some(first) = outgoing.get_mut(&start).and_then(|edges| edges.pop()) {
+            let mut ring = vec![start];
+            let (mut previous, mut current) = (start, first);
+            while current != start {
+                ring.push(current);
+                // Every corner has as many edges out as in, so this only fails if the
+                // edges above are not a closed boundary; drop the open chain then
+                let Some(next) = take_next_edge(&mut outgoing, previous, current) else {
+                    break;
+                };
+                previous = current;
+                current = next;
+            }
+            if current == start {
+                rings.push(simplify_ring(ring));
+            }
+        }
+    }
+
+    // Positive shoelace area in pixel space (y down) is an outer boundary
+    let (exteriors, holes): (Vec<_>, Vec<_>) =
+        rings.into_iter().partition(|ring| signed_area(ring) > 0.0);
+    let mut polygons: Vec<(Polygon<f64>, Vec<LineString<f64>>)> = exteriors
+        .into_iter()
+        .map(|ring| (Polygon::new(to_line_string(&ring), vec![]), Vec::new()))
+        .collect();
+
+    for hole in holes {
+        let hole = to_line_string(&hole);
+        // Hole vertices can lie on other rings, so test a point just inside the hole.
+        // The innermost exterior around it is the one the hole belongs to.
+        let probe = hole_probe(&hole);
+        if let Some((_, interiors)) = polygons
+            .iter_mut()
+            .filter(|(exterior, _)| exterior.contains(&probe))
+            .min_by(|(a, _), (b, _)| a.unsigned_area().total_cmp(&b.unsigned_area()))
+        {
+            interiors.push(hole);
+        }
+    }
+
+    polygons
+        .into_iter()
+        .map(|(polygon, interiors)| Polygon::new(polygon.exterior().clone(), interiors))
+        .collect()
+}
+
+/// Remove an outgoing edge at `current`, or None if it has none left. Where two rings
+/// meet at a corner, take the right turn so cells touching only diagonally are kept
+/// apart.
+fn take_next_edge(
+    outgoing: &mut HashMap<(isize, isize), Vec<(isize, isize)>>,
+    previous: (isize, isize),
+    current: (isize, isize),
+) -> Option<(isize, isize)> {
+    let edges = outgoing
+        .get_mut(&current)
+        .filter(|edges| !edges.is_empty())?;
+    let index = if edges.len() > 1 {
+        let (dx, dy) = (current.0 - previous.0, current.1 - previous.1);
+        // Right turn in pixel space (y down): (dx, dy) -> (-dy, dx)
+        let right = (current.0 - dy, current.1 + dx);
+        edges.iter().position(|&to| to == right).unwrap_or(0)
+    } else {
+        0
+    };
+    Some(edges.swap_remove(index))
+}
+
+/// Drop vertices in the middle of straight runs
+fn simplify_ring(ring: Vec<(isize, isize)>) -> Vec<(isize, isize)> {
+    let n = ring.len();
+    (0..n)
+        .filter(|&i| {
+            let (a, b, c) = (ring[(i + n - 1) % n], ring[i], ring[(i + 1) % n]);
+            (b.0 - a.0) * (c.1 - b.1) != (b.1 - a.1) * (c.0 - b.0)
+        })
+        .map(|i| ring[i])
+        .collect()
+}
+
+fn signed_area(ring: &[(isize, isize)]) -> f64 {
+    let n = ring.len();
+    (0..n)
+        .map(|i| {
+            let (a, b) = (ring[i], ring[(i + 1) % n]);
+            (a.0 * b.1 - b.0 * a.1) as f64
+        })
+        .sum::<f64>()
+        / 2.0
+}
+
+/// Lattice corners sit half a pixel from cell centres
+fn to_line_string(ring: &[(isize, isize)]) -> LineString<f64> {
+    let mut coords: Vec<Coord<f64>> = ring
+        .iter()
+        .map(|&(x, y)| Coord {
+            x: x as f64 - 0.5,
+            y: y as f64 - 0.5,
+        })
+        .collect();
+    coords.push(coords[0]);
+    LineString::new(coords)
+}
+
+/// A point just inside a hole ring, next to the middle of its first edge
+fn hole_probe(hole: &LineString<f64>) -> Coord<f64> {
+    let (a, b) = (hole.0[0], hole.0[1]);
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length = dx.hypot(dy);
+    // Holes run the opposite way to exteriors, so the hole interior (not part of the
+    // region) is on the left of each edge: (dy, -dx) in pixel space
+    Coord {
+        x: (a.x + b.x) / 2.0 + 0.25 * dy / length,
+        y: (a.y + b.y) / 2.0 - 0.25 * dx / length,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cells marked '#' in `rows`
+    fn cells(rows: &[&str]) -> Vec<(usize, usize)> {
+        rows.iter()
+            .enumerate()
+            .flat_map(|(row, line)| {
+                line.chars()
+                    .enumerate()
+                    .filter(|&(_, c)| c == '#')
+                    .map(move |(col, _)| (row, col))
+            })
+            .collect()
+    }
+
+    /// Polygons sorted largest first
+    fn outlines(rows: &[&str]) -> Vec<Polygon<f64>> {
+        let mut polygons = cell_polygons(&cells(rows));
+        polygons.sort_by(|a, b| b.unsigned_area().total_cmp(&a.unsigned_area()));
+        polygons
+    }
+
+    #[test]
+    fn single_cell_is_centred_on_its_pixel() {
+        let polygons = outlines(&["", "..", "..#"]);
+        assert_eq!(polygons.len(), 1);
+        let xs: Vec<f64> = polygons[0].exterior().coords().map(|c| c.x).collect();
+        let ys: Vec<f64> = polygons[0].exterior().coords().map(|c| c.y).collect();
+        assert_eq!(xs.iter().cloned().fold(f64::INFINITY, f64::min), 1.5);
+        assert_eq!(xs.iter().cloned().fold(f64::NEG_INFINITY, f64::max), 2.5);
+        assert_eq!(ys.iter().cloned().fold(f64::INFINITY, f64::min), 1.5);
+        assert_eq!(ys.iter().cloned().fold(f64::NEG_INFINITY, f64::max), 2.5);
+        assert_eq!(polygons[0].unsigned_area(), 1.0);
+    }
+
+    #[test]
+    fn checkerboard_cells_stay_separate() {
+        let polygons = outlines(&["#.#.", ".#.#", "#.#.", ".#.#"]);
+        assert_eq!(polygons.len(), 8);
+        for polygon in &polygons {
+            assert_eq!(polygon.unsigned_area(), 1.0);
+            // Four corners plus the closing point
+            assert_eq!(polygon.exterior().0.len(), 5);
+            assert!(polygon.interiors().is_empty());
+        }
+    }
+
+    #[test]
+    fn ring_has_a_hole() {
+        let polygons = outlines(&["####", "#..#", "#..#", "####"]);
+        assert_eq!(polygons.len(), 1);
+        assert_eq!(polygons[0].exterior().0.len(), 5);
+        assert_eq!(polygons[0].interiors().len(), 1);
+        assert_eq!(polygons[0].interiors()[0].0.len(), 5);
+        assert_eq!(polygons[0].unsigned_area(), 12.0);
+    }
+
+    #[test]
+    fn island_in_a_hole_is_its_own_polygon() {
+        let polygons = outlines(&["#####", "#...#", "#.#.#", "#...#", "#####"]);
+        assert_eq!(polygons.len(), 2);
+        assert_eq!(polygons[0].interiors().len(), 1);
+        assert_eq!(polygons[0].unsigned_area(), 16.0);
+        assert!(polygons[1].interiors().is_empty());
+        assert_eq!(polygons[1].unsigned_area(), 1.0);
+        assert!(polygons[1]
+            .exterior()
+            .coords()
+            .all(|c| (1.5..=2.5).contains(&c.x)));
+    }
+
+    #[test]
+    fn gap_open_at_a_corner_is_not_a_hole() {
+        // The gap at (1, 1) meets the outside diagonally at the bottom right, so like
+        // cells touching at a corner it stays connected to the outside
+        let polygons = outlines(&["###.", "#.#.", "##..", "...."]);
+        assert_eq!(polygons.len(), 1);
+        assert!(polygons[0].interiors().is_empty());
+        assert_eq!(polygons[0].unsigned_area(), 7.0);
+    }
+
+    #[test]
+    fn no_cells_no_polygons() {
+        assert!(cell_polygons(&[]).is_empty());
+    }
+}
@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProgressUpdate {
+    pub progress: f64,
+    pub current_step: String,
+    pub total_steps: Option<usize>,
+    pub current_step_number: Option<usize>,
+}
+
+/// Receiver for progress of long-running calculations, e.g. a GUI event channel.
+/// The engine always draws its terminal progress bars; a sink adds a second
+/// destination. Updates arrive from worker threads.
+pub trait ProgressSink: Send + Sync {
+    fn report(&self, update: &ProgressUpdate);
+}
@@ -0,0 +1,342 @@
+use crate::irradiance::ClearSkyIrradiance;
+use crate::types::{PvArray, ShadowError};
+use chrono::{DateTime, Datelike, Utc};
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+
+/// Irradiance at standard test conditions, W/m²
+const STC_IRRADIANCE: f64 = 1000.0;
+
+/// Cell temperature at standard test conditions, °C
+const STC_TEMPERATURE: f64 = 25.0;
+
+/// Sun position and shading of the array at one analysis timestamp
+#[derive(Debug, Clone)]
+pub struct PvSample {
+    pub timestamp: DateTime<Utc>,
+    pub azimuth: f64,
+    pub elevation: f64,
+    /// Irradiance reaching the site, from the weather data or the clear-sky model
+    pub sky: ClearSkyIrradiance,
+    /// Mean `shadow_fraction` over the array cells (0 = fully lit)
+    pub shaded_fraction: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PvHourlyYield {
+    pub timestamp: DateTime<Utc>,
+    pub sun_azimuth: f64,
+    pub sun_elevation: f64,
+    pub shaded_fraction: f64,
+    /// Plane-of-array irradiance after shading, W/m²
+    pub plane_irradiance: f64,
+    pub cell_temperature: f64,
+    /// AC energy over the timestamp's interval, kWh
+    pub ac_energy: f64,
+    /// AC energy the same array would produce with no shading, kWh
+    pub unshaded_ac_energy: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PvMonthlyYield {
+    pub year: i32,
+    pub month: u32,
+    pub ac_energy: f64,
+    pub unshaded_ac_energy: f64,
+    pub shading_loss_percent: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PvYield {
+    /// Module area in the tilted plane, m²
+    pub module_area: f64,
+    /// DC power at standard test conditions, kWp
+    pub peak_power: f64,
+    pub total_ac_energy: f64,
+    pub unshaded_ac_energy: f64,
+    /// Share of the unshaded yield lost to shading
+    pub shading_loss_percent: f64,
+    /// AC energy per installed kWp over the analysis period, kWh/kWp
+    pub specific_yield: f64,
+    pub monthly: Vec<PvMonthlyYield>,
+    /// Written to CSV rather than sent to the frontend
+    #[serde(skip)]
+    pub hourly: Vec<PvHourlyYield>,
+}
+
+/// Estimate the AC yield of a PV array from its shading at each analysis timestamp.
+///
+/// Plane-of-array irradiance comes from each sample's sky irradiance (measured or
+/// clear-sky): beam on the tilted plane is scaled by the unshaded share of the array,
+/// while sky diffuse (isotropic) and ground-reflected light are left unshaded. Cell
+/// temperature follows the NOCT model and DC power is derated by the module
+/// temperature coefficient before inverter losses. `footprint_area` is the horizontal
+/// area covered by the array, in m².
+pub fn estimate_yield(
+    array: &PvArray,
+    footprint_area: f64,
+    samples: &[PvSample],
+    hour_interval: f64,
+) -> PvYield {
+    let tilt = array.tilt.clamp(0.0, 90.0).to_radians();
+    // Modules cover more than their footprint once tilted; stop at near-vertical arrays
+    let module_area = footprint_area / tilt.cos().max(0.1);
+    let peak_power = module_area * array.module_efficiency * STC_IRRADIANCE / 1000.0;
+
+    let hourly: Vec<PvHourlyYield> = samples
+        .iter()
+        .map(|sample| {
+            let (beam, diffuse) = plane_of_array(array, tilt, sample);
+            let unshaded = beam + diffuse;
+            let shaded = beam * (1.0 - sample.shaded_fraction.clamp(0.0, 1.0)) + diffuse;
+            let (ac_energy, cell_temperature) =
+                interval_ac_energy(array, module_area, shaded, hour_interval);
+            let (unshaded_ac_energy, _) =
+                interval_ac_energy(array, module_area, unshaded, hour_interval);
+
+            PvHourlyYield {
+                timestamp: sample.timestamp,
+                sun_azimuth: sample.azimuth,
+                sun_elevation: sample.elevation,
+                shaded_fraction: sample.shaded_fraction,
+                plane_irradiance: shaded,
+                cell_temperature,
+                ac_energy,
+                unshaded_ac_energy,
+            }
+        })
+        .collect();
+
+    let mut monthly: Vec<PvMonthlyYield> = Vec::new();
+    for hour in &hourly {
+        let (year, month) = (hour.timestamp.year(), hour.timestamp.month());
+        match monthly.last_mut() {
+            Some(entry) if entry.year == year && entry.month == month => {
+                entry.ac_energy += hour.ac_energy;
+                entry.unshaded_ac_energy += hour.unshaded_ac_energy;
+            }
+            _ => monthly.push(PvMonthlyYield {
+                year,
+                month,
+                ac_energy: hour.ac_energy,
+                unshaded_ac_energy: hour.unshaded_ac_energy,
+                shading_loss_percent: 0.0,
+            }),
+        }
+    }
+    for entry in &mut monthly {
+        entry.shading_loss_percent = loss_percent(entry.ac_energy, entry.unshaded_ac_energy);
+    }
+
+    let total_ac_energy: f64 = hourly.iter().map(|h| h.ac_energy).sum();
+    let unshaded_ac_energy: f64 = hourly.iter().map(|h| h.unshaded_ac_energy).sum();
+
+    PvYield {
+        module_area,
+        peak_power,
+        total_ac_energy,
+        unshaded_ac_energy,
+        shading_loss_percent: loss_percent(total_ac_energy, unshaded_ac_energy),
+        specific_yield: if peak_power > 0.0 {
+            total_ac_energy / peak_power
+        } else {
+            0.0
+        },
+        monthly,
+        hourly,
+    }
+}
+
+/// (beam, diffuse + reflected) irradiance on the module plane, W/m²
+fn plane_of_array(array: &PvArray, tilt: f64, sample: &PvSample) -> (f64, f64) {
+    if sample.elevation <= 0.0 {
+        return (0.0, 0.0);
+    }
+    let elevation = sample.elevation.to_radians();
+    let cos_incidence = tilt.cos() * elevation.sin()
+        + tilt.sin() * elevation.cos() * (sample.azimuth - array.azimuth).to_radians().cos();
+
+    let sky = sample.sky;
+    let beam = sky.direct_normal * cos_incidence.max(0.0);
+    let global_horizontal = sky.direct_normal * elevation.sin() + sky.diffuse_horizontal;
+    let sky_diffuse = sky.diffuse_horizontal * (1.0 + tilt.cos()) / 2.0;
+    let reflected = global_horizontal * array.get_albedo() * (1.0 - tilt.cos()) / 2.0;
+    (beam, sky_diffuse + reflected)
+}
+
+/// AC energy in kWh over one interval, and the cell temperature it was derated for
+fn interval_ac_energy(
+    array: &PvArray,
+    module_area: f64,
+    plane_irradiance: f64,
+    hour_interval: f64,
+) -> (f64, f64) {
+    let cell_temperature =
+        array.get_ambient_temperature() + (array.get_noct() - 20.0) / 800.0 * plane_irradiance;
+    let temperature_factor = (1.0
+        + array.temperature_coefficient / 100.0 * (cell_temperature - STC_TEMPERATURE))
+        .max(0.0);
+    let dc_power = module_area * array.module_efficiency * plane_irradiance * temperature_factor;
+    let ac_power = dc_power * (1.0 - array.inverter_losses.clamp(0.0, 1.0));
+    (ac_power * hour_interval / 1000.0, cell_temperature)
+}
+
+fn loss_percent(energy: f64, unshaded_energy: f64) -> f64 {
+    if unshaded_energy > 0.0 {
+        (1.0 - energy / unshaded_energy) * 100.0
+    } else {
+        0.0
+    }
+}
+
+/// Write the monthly yield table as CSV
+pub fn write_monthly_csv(path: &Path, pv_yield: &PvYield) -> Result<(), ShadowError> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+
+    writeln!(
+        file,
+        "year,month,ac_energy_kwh,unshaded_ac_energy_kwh,shading_loss_percent"
+    )?;
+    for entry in &pv_yield.monthly {
+        writeln!(
+            file,
+            "{},{},{:.3},{:.3},{:.2}",
+            entry.year,
+            entry.month,
+            entry.ac_energy,
+            entry.unshaded_ac_energy,
+            entry.shading_loss_percent
+        )?;
+    }
+    writeln!(
+        file,
+        "total,,{:.3},{:.3},{:.2}",
+        pv_yield.total_ac_energy, pv_yield.unshaded_ac_energy, pv_yield.shading_loss_percent
+    )?;
+
+    file.flush()?;
+    Ok(())
+}
+
+/// Write the per-timestamp yield as CSV
+pub fn write_hourly_csv(path: &Path, pv_yield: &PvYield) -> Result<(), ShadowError> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+
+    writeln!(
+        file,
+        "datetime,sun_azimuth,sun_elevation,shaded_fraction,plane_irradiance_w_m2,cell_temperature_c,ac_energy_kwh,unshaded_ac_energy_kwh"
+    )?;
+    for hour in &pv_yield.hourly {
+        writeln!(
+            file,
+            "{},{:.2},{:.2},{:.4},{:.1},{:.1},{:.4},{:.4}",
+            hour.timestamp.to_rfc3339(),
+            hour.sun_azimuth,
+            hour.sun_elevation,
+            hour.shaded_fraction,
+            hour.plane_irradiance,
+            hour.cell_temperature,
+            hour.ac_energy,
+            hour.unshaded_ac_energy
+        )?;
+    }
+
+    file.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// A flat array converting 20% with no temperature or inverter losses
+    fn flat_array() -> PvArray {
+        PvArray {
+            polygon: None,
+            tilt: 0.0,
+            azimuth: 180.0,
+            module_efficiency: 0.2,
+            temperature_coefficient: 0.0,
+            inverter_losses: 0.0,
+            noct: None,
+            ambient_temperature: None,
+            albedo: None,
+        }
+    }
+
+    /// Sun overhead with 800 W/m² beam and 100 W/m² diffuse
+    fn overhead(month: u32, shaded_fraction: f64) -> PvSample {
+        PvSample {
+            timestamp: Utc.with_ymd_and_hms(2024, month, 10, 12, 0, 0).unwrap(),
+            azimuth: 180.0,
+            elevation: 90.0,
+            sky: ClearSkyIrradiance {
+                direct_normal: 800.0,
+                diffuse_horizontal: 100.0,
+            },
+            shaded_fraction,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn shading_removes_only_the_shaded_beam() {
+        // 10 m² at 900 W/m² makes 1.8 kWh an hour; half the beam shaded leaves 500 W/m²
+        let samples = [overhead(1, 0.0), overhead(1, 0.5)];
+        let pv_yield = estimate_yield(&flat_array(), 10.0, &samples, 1.0);
+
+        assert_close(pv_yield.peak_power, 2.0);
+        assert_close(pv_yield.hourly[0].ac_energy, 1.8);
+        assert_close(pv_yield.hourly[1].plane_irradiance, 500.0);
+        assert_close(pv_yield.hourly[1].ac_energy, 1.0);
+        assert_close(pv_yield.unshaded_ac_energy, 3.6);
+        assert_close(pv_yield.shading_loss_percent, (1.0 - 2.8 / 3.6) * 100.0);
+        assert_close(pv_yield.specific_yield, 1.4);
+        // NOCT 45 °C at 20 °C ambient: 25 °C warmer per 800 W/m²
+        assert_close(
+            pv_yield.hourly[0].cell_temperature,
+            20.0 + 25.0 * 900.0 / 800.0,
+        );
+    }
+
+    #[test]
+    fn monthly_table_sums_each_month() {
+        let samples = [overhead(1, 0.0), overhead(1, 1.0), overhead(2, 0.0)];
+        let pv_yield = estimate_yield(&flat_array(), 10.0, &samples, 0.5);
+
+        let months: Vec<(u32, f64, f64)> = pv_yield
+            .monthly
+            .iter()
+            .map(|month| (month.month, month.ac_energy, month.unshaded_ac_energy))
+            .collect();
+        assert_eq!(months.len(), 2);
+        // Fully shaded, only the 100 W/m² diffuse is left
+        assert_close(months[0].1, 0.9 + 0.1);
+        assert_close(months[0].2, 1.8);
+        assert_close(months[1].1, 0.9);
+        assert_close(
+            pv_yield.monthly[0].shading_loss_percent,
+            (1.0 - 1.0 / 1.8) * 100.0,
+        );
+        assert_close(pv_yield.monthly[1].shading_loss_percent, 0.0);
+    }
+
+    #[test]
+    fn sun_below_horizon_produces_nothing() {
+        let mut night = overhead(3, 0.0);
+        night.elevation = -10.0;
+        let pv_yield = estimate_yield(&flat_array(), 10.0, &[night], 1.0);
+        assert_eq!(pv_yield.total_ac_energy, 0.0);
+        assert_eq!(pv_yield.shading_loss_percent, 0.0);
+    }
+}
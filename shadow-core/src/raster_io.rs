@@ -1,3 +1,5 @@
+use crate::point_cloud::{self, PointCloudSurface};
+use crate::shadow_store::ShadowStore;
 use crate::types::*;
 use chrono::{Datelike, Timelike};
 use gdal::raster::{Buffer, ResampleAlg};
@@ -9,6 +11,9 @@ use std::path::Path;
 
 pub struct RasterIO;
 
+/// Upper bound on the time series block held in memory while writing CSV rows
+const CSV_BLOCK_BYTES: usize = 256 * 1024 * 1024;
+
 impl RasterIO {
     pub fn read_raster(path: &Path) -> Result<RasterData, ShadowError> {
         let dataset = Dataset::open(path)?;
@@ -39,6 +44,37 @@ impl RasterIO {
         })
     }
 
+    /// Read a DTM raster, or grid one from the ground points of a LAS/LAZ file
+    pub fn read_dtm(path: &Path, point_cloud_resolution: f64) -> Result<RasterData, ShadowError> {
+        if point_cloud::is_point_cloud(path) {
+            point_cloud::grid(path, PointCloudSurface::GroundMean, point_cloud_resolution)
+        } else {
+            Self::read_raster(path)
+        }
+    }
+
+    /// Read a DSM raster, or grid one from the first returns of a LAS/LAZ file
+    pub fn read_dsm(path: &Path, point_cloud_resolution: f64) -> Result<RasterData, ShadowError> {
+        if point_cloud::is_point_cloud(path) {
+            point_cloud::grid(
+                path,
+                PointCloudSurface::FirstReturnMax,
+                point_cloud_resolution,
+            )
+        } else {
+            Self::read_raster(path)
+        }
+    }
+
+    /// Coordinate system of a raster or point cloud as WKT, without reading its data
+    pub fn read_projection(path: &Path) -> Result<String, ShadowError> {
+        if point_cloud::is_point_cloud(path) {
+            point_cloud::read_projection(path)
+        } else {
+            Ok(Dataset::open(path)?.projection())
+        }
+    }
+
     pub fn read_multiband_raster(path: &Path) -> Result<RasterData, ShadowError> {
         let dataset = Dataset::open(path)?;
         let transform = dataset.geo_transform()?;
@@ -238,9 +274,33 @@ impl RasterIO {
         projection: &str,
         band_descriptions: &[String],
     ) -> Result<(), ShadowError> {
+        let (n_bands, height, width) = data.dim();
+        Self::write_geotiff_bands(
+            path,
+            n_bands,
+            (height, width),
+            transform,
+            projection,
+            band_descriptions,
+            |band_idx| Ok(data.slice(s![band_idx, .., ..]).to_owned()),
+        )
+    }
+
+    /// Write a GeoTIFF one band at a time, so callers never need all bands in memory
+    pub fn write_geotiff_bands<F>(
+        path: &Path,
+        n_bands: usize,
+        (height, width): (usize, usize),
+        transform: &[f64; 6],
+        projection: &str,
+        band_descriptions: &[String],
+        mut band_data: F,
+    ) -> Result<(), ShadowError>
+    where
+        F: FnMut(usize) -> Result<Array2<f32>, ShadowError>,
+    {
         // Use DriverManager instead of deprecated Driver::get_by_name
         let driver = DriverManager::get_driver_by_name("GTiff")?;
-        let (n_bands, height, width) = data.dim();
 
         let mut dataset = driver.create_with_band_type::<f32, _>(
             path,
@@ -263,8 +323,7 @@ impl RasterIO {
             // Set NoData value for proper masking
             band.set_no_data_value(Some(f32::NAN as f64))?;
 
-            let band_data = data.slice(s![band_idx, .., ..]);
-            let vec_data: Vec<f32> = band_data.iter().cloned().collect();
+            let vec_data: Vec<f32> = band_data(band_idx)?.iter().cloned().collect();
             let buffer = Buffer::new((width, height), vec_data);
 
             band.write((0, 0), (width, height), &buffer)?;
@@ -275,7 +334,7 @@ impl RasterIO {
 
     pub fn write_csv_with_aoi_mask(
         path: &Path,
-        shadow_data: &Array3<f32>,
+        shadow_data: &ShadowStore,
         timestamps: &[chrono::DateTime<chrono::Utc>],
         transform: &[f64; 6],
         aoi: &Polygon<f64>,
@@ -284,32 +343,38 @@ impl RasterIO {
         use geo_types::Coord;
         use std::io::Write;
 
-        let mut file = std::fs::File::create(path)?;
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
         writeln!(file, "cell_id,lat,lon,datetime,shadow_fraction")?;
 
         let (n_times, n_rows, n_cols) = shadow_data.dim();
+        let rows_per_block = shadow_data.rows_per_block(CSV_BLOCK_BYTES);
         let mut cell_id = 0;
 
-        for row in 0..n_rows {
-            for col in 0..n_cols {
-                let (lon, lat) = Self::pixel_to_world(col, row, transform);
-                let point = Coord { x: lon, y: lat };
+        for row_start in (0..n_rows).step_by(rows_per_block) {
+            let row_end = (row_start + rows_per_block).min(n_rows);
+            let block = shadow_data.read_row_block(row_start, row_end)?;
 
-                // Only export cells inside AOI
-                if aoi.contains(&point) {
-                    for t_idx in 0..n_times {
-                        let shadow_val = shadow_data[[t_idx, row, col]];
-                        writeln!(
-                            file,
-                            "{},{:.6},{:.6},{},{}",
-                            cell_id,
-                            lat,
-                            lon,
-                            timestamps[t_idx].to_rfc3339(),
-                            shadow_val
-                        )?;
+            for row in row_start..row_end {
+                for col in 0..n_cols {
+                    let (lon, lat) = Self::pixel_to_world(col, row, transform);
+                    let point = Coord { x: lon, y: lat };
+
+                    // Only export cells inside AOI
+                    if aoi.contains(&point) {
+                        for t_idx in 0..n_times {
+                            let shadow_val = block[[t_idx, row - row_start, col]];
+                            writeln!(
+                                file,
+                                "{},{:.6},{:.6},{},{}",
+                                cell_id,
+                                lat,
+                                lon,
+                                timestamps[t_idx].to_rfc3339(),
+                                shadow_val
+                            )?;
+                        }
+                        cell_id += 1;
                     }
-                    cell_id += 1;
                 }
             }
         }
@@ -319,35 +384,42 @@ impl RasterIO {
 
     pub fn write_csv(
         path: &Path,
-        shadow_data: &Array3<f32>,
+        shadow_data: &ShadowStore,
         timestamps: &[chrono::DateTime<chrono::Utc>],
         transform: &[f64; 6],
     ) -> Result<(), ShadowError> {
         use std::io::Write;
-        let mut file = std::fs::File::create(path)?;
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
 
         writeln!(file, "cell_id,lat,lon,datetime,shadow_fraction")?;
 
         let (n_times, n_rows, n_cols) = shadow_data.dim();
+        let rows_per_block = shadow_data.rows_per_block(CSV_BLOCK_BYTES);
         let mut cell_id = 0;
 
-        for row in 0..n_rows {
-            for col in 0..n_cols {
-                let (lon, lat) = Self::pixel_to_world(col, row, transform);
-
-                for t_idx in 0..n_times {
-                    let shadow_val = shadow_data[[t_idx, row, col]];
-                    writeln!(
-                        file,
-                        "{},{:.6},{:.6},{},{}",
-                        cell_id,
-                        lat,
-                        lon,
-                        timestamps[t_idx].to_rfc3339(),
-                        shadow_val
-                    )?;
+        // Read the time series in row blocks so disk-backed results stream through
+        for row_start in (0..n_rows).step_by(rows_per_block) {
+            let row_end = (row_start + rows_per_block).min(n_rows);
+            let block = shadow_data.read_row_block(row_start, row_end)?;
+
+            for row in row_start..row_end {
+                for col in 0..n_cols {
+                    let (lon, lat) = Self::pixel_to_world(col, row, transform);
+
+                    for t_idx in 0..n_times {
+                        let shadow_val = block[[t_idx, row - row_start, col]];
+                        writeln!(
+                            file,
+                            "{},{:.6},{:.6},{},{}",
+                            cell_id,
+                            lat,
+                            lon,
+                            timestamps[t_idx].to_rfc3339(),
+                            shadow_val
+                        )?;
+                    }
+                    cell_id += 1;
                 }
-                cell_id += 1;
             }
         }
 
@@ -498,7 +570,7 @@ impl RasterIO {
         start_dt: chrono::DateTime<chrono::Utc>,
         end_dt: chrono::DateTime<chrono::Utc>,
     ) -> Result<f64, ShadowError> {
-        let mut sun_calc = crate::sun_position::SunCalculator::new(lat, lon, 0.1);
+        let sun_calc = crate::sun_position::SunCalculator::new(lat, lon, 0.1);
         let mut min_elevation: f64 = 90.0;
 
         // Sample throughout the analysis period
@@ -534,7 +606,7 @@ impl RasterIO {
                     .unwrap_or(winter_check);
 
                 if check_time >= start_dt && check_time <= end_dt {
-                    let (_, elevation) = sun_calc.get_position(&check_time);
+                    let (_, elevation) = sun_calc.get_exact_position(&check_time);
                     if elevation > 0.0 && elevation < 90.0 {
                         min_elevation = min_elevation.min(elevation);
                     }
@@ -555,7 +627,7 @@ impl RasterIO {
                     .with_second(0)
                     .unwrap_or(check_date);
 
-                let (_, elevation) = sun_calc.get_position(&check_time);
+                let (_, elevation) = sun_calc.get_exact_position(&check_time);
                 if elevation > 0.0 && elevation < 90.0 {
                     min_elevation = min_elevation.min(elevation);
                 }
@@ -596,6 +668,32 @@ impl RasterIO {
         Ok(())
     }
 
+    pub fn mask_shadow_store_to_aoi(
+        store: &mut ShadowStore,
+        aoi: &Polygon<f64>,
+        transform: &[f64; 6],
+        no_data_value: f32,
+    ) -> Result<(), ShadowError> {
+        use geo::algorithm::contains::Contains;
+        use geo_types::Coord;
+
+        if let ShadowStore::Memory(data) = store {
+            return Self::mask_results_to_aoi(data, aoi, transform, no_data_value);
+        }
+
+        // Disk-backed: build the mask once, then rewrite each time slice
+        let (_n_times, n_rows, n_cols) = store.dim();
+        let outside = Array2::from_shape_fn((n_rows, n_cols), |(row, col)| {
+            let (world_x, world_y) = Self::pixel_to_world(col, row, transform);
+            !aoi.contains(&Coord {
+                x: world_x,
+                y: world_y,
+            })
+        });
+
+        store.apply_mask(&outside, no_data_value)
+    }
+
     fn get_buffered_bounds(polygon: &Polygon<f64>, buffer_m: f64) -> (f64, f64, f64, f64) {
         let coords: Vec<Coord<f64>> = polygon.exterior().coords().cloned().collect();
         let min_x = coords.iter().map(|c| c.x).fold(f64::INFINITY, f64::min) - buffer_m;
@@ -0,0 +1,424 @@
+use crate::polygonize;
+use crate::types::{ShadowError, SummaryStats};
+use geo::algorithm::map_coords::MapCoords;
+use geo::algorithm::orient::{Direction, Orient};
+use geo_types::Polygon;
+use ndarray::Array2;
+use serde_json::json;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::Path;
+
+/// Largest height difference, in metres, between a cell and its face's fitted plane
+const PLANE_DISTANCE_TOLERANCE: f64 = 0.5;
+
+/// Faces need this many cells before their own plane fit replaces the seed's slope
+const MIN_FIT_CELLS: usize = 6;
+
+/// A planar roof face: connected building cells sharing one fitted plane
+#[derive(Debug, Clone)]
+pub struct RoofFace {
+    pub cells: Vec<(usize, usize)>,
+    /// Degrees from horizontal
+    pub tilt: f64,
+    /// Azimuth the face slopes down toward, degrees clockwise from north
+    pub azimuth: f64,
+    /// Outline in (col, row) pixel coordinates
+    pub polygons: Vec<Polygon<f64>>,
+}
+
+/// Shadow and irradiance statistics over one roof face
+#[derive(Debug, Clone)]
+pub struct RoofFaceStats {
+    pub face: RoofFace,
+    /// Sloped surface area, larger than the footprint on pitched roofs
+    pub area: f64,
+    pub footprint_area: f64,
+    /// Mean sun hours per cell over the analysis period
+    pub sun_hours: f32,
+    /// Sloped area of the cells that are sunlit for at least the usable fraction
+    pub usable_area: f64,
+    pub avg_shadow_fraction: f32,
+    /// Mean global irradiation in kWh/m², when irradiance layers were calculated
+    pub global_irradiation: Option<f32>,
+}
+
+/// Parameters for region growing
+pub struct RoofSegmentation {
+    /// Largest angle, in degrees, between a cell's normal and its face's normal
+    pub angle_tolerance: f64,
+    /// Faces with a smaller footprint, in m², are dropped
+    pub min_area: f64,
+}
+
+/// Least-squares plane z = a·x + b·y + c over cells, with x east and y south in metres
+#[derive(Default)]
+struct PlaneFit {
+    n: f64,
+    sx: f64,
+    sy: f64,
+    sz: f64,
+    sxx: f64,
+    sxy: f64,
+    syy: f64,
+    sxz: f64,
+    syz: f64,
+}
+
+impl PlaneFit {
+    fn add(&mut self, x: f64, y: f64, z: f64) {
+        self.n += 1.0;
+        self.sx += x;
+        self.sy += y;
+        self.sz += z;
+        self.sxx += x * x;
+        self.sxy += x * y;
+        self.syy += y * y;
+        self.sxz += x * z;
+        self.syz += y * z;
+    }
+
+    /// (a, b, c), or None while the cells are collinear
+    fn solve(&self) -> Option<(f64, f64, f64)> {
+        // Centre the sums so the 2×2 system for the slopes is well conditioned
+        let (mx, my, mz) = (self.sx / self.n, self.sy / self.n, self.sz / self.n);
+        let cxx = self.sxx / self.n - mx * mx;
+        let cxy = self.sxy / self.n - mx * my;
+        let cyy = self.syy / self.n - my * my;
+        let cxz = self.sxz / self.n - mx * mz;
+        let cyz = self.syz / self.n - my * mz;
+        let det = cxx * cyy - cxy * cxy;
+        if det.abs() < 1e-9 {
+            return None;
+        }
+        let a = (cxz * cyy - cyz * cxy) / det;
+        let b = (cyz * cxx - cxz * cxy) / det;
+        Some((a, b, mz - a * mx - b * my))
+    }
+}
+
+/// Split roof cells into planar faces by region growing.
+///
+/// Each cell's slope comes from a plane fitted to its 3×3 neighbours on the same roof
+/// (so ground beyond the eaves doesn't tilt edge cells). A face starts at the first
+/// unassigned cell and takes in 4-neighbours whose normal is within the angle
+/// tolerance of the face's current plane and whose height lies close to it. Both the
+/// tolerance test and the height test use the face's running least-squares plane.
+pub fn segment_roof_faces<F>(
+    dsm: &Array2<f32>,
+    resolution: f64,
+    params: &RoofSegmentation,
+    is_roof: F,
+) -> Vec<RoofFace>
+where
+    F: Fn(usize, usize) -> bool,
+{
+    let (n_rows, n_cols) = dsm.dim();
+    let roof = Array2::from_shape_fn((n_rows, n_cols), |(row, col)| {
+        dsm[[row, col]].is_finite() && is_roof(row, col)
+    });
+    let position = |row: usize, col: usize| (col as f64 * resolution, row as f64 * resolution);
+
+    // Local gradients (dz/dx, dz/dy) per metre
+    let gradients = Array2::from_shape_fn((n_rows, n_cols), |(row, col)| {
+        if !roof[[row, col]] {
+            return None;
+        }
+        let mut fit = PlaneFit::default();
+        for d_row in -1..=1isize {
+            for d_col in -1..=1isize {
+                let (r, c) = (row as isize + d_row, col as isize + d_col);
+                if r < 0 || c < 0 || r >= n_rows as isize || c >= n_cols as isize {
+                    continue;
+                }
+                let (r, c) = (r as usize, c as usize);
+                let step = (dsm[[r, c]] - dsm[[row, col]]).abs() as f64;
+                // Skip neighbours across a wall or step to another roof level
+                if roof[[r, c]] && step <= PLANE_DISTANCE_TOLERANCE + resolution * 2.0 {
+                    let (x, y) = position(r, c);
+                    fit.add(x, y, dsm[[r, c]] as f64);
+                }
+            }
+        }
+        fit.solve().map(|(a, b, _)| (a, b))
+    });
+
+    let cos_tolerance = params.angle_tolerance.to_radians().cos();
+    let min_cells = (params.min_area / (resolution * resolution))
+        .ceil()
+        .max(1.0) as usize;
+    let mut assigned = Array2::<bool>::from_elem((n_rows, n_cols), false);
+    let mut faces = Vec::new();
+
+    for seed_row in 0..n_rows {
+        for seed_col in 0..n_cols {
+            if assigned[[seed_row, seed_col]] || !roof[[seed_row, seed_col]] {
+                continue;
+            }
+            let seed_gradient = match gradients[[seed_row, seed_col]] {
+                Some(gradient) => gradient,
+                None => continue,
+            };
+
+            let mut fit = PlaneFit::default();
+            let (seed_x, seed_y) = position(seed_row, seed_col);
+            let seed_z = dsm[[seed_row, seed_col]] as f64;
+            let mut plane = (
+                seed_gradient.0,
+                seed_gradient.1,
+                seed_z - seed_gradient.0 * seed_x - seed_gradient.1 * seed_y,
+            );
+
+            let mut cells = Vec::new();
+            let mut queue = VecDeque::from([(seed_row, seed_col)]);
+            assigned[[seed_row, seed_col]] = true;
+
+            while let Some((row, col)) = queue.pop_front() {
+                let (x, y) = position(row, col);
+                fit.add(x, y, dsm[[row, col]] as f64);
+                cells.push((row, col));
+                if cells.len() >= MIN_FIT_CELLS {
+                    if let Some(solved) = fit.solve() {
+                        plane = solved;
+                    }
+                }
+
+                for (d_row, d_col) in [(-1isize, 0isize), (1, 0), (0, -1), (0, 1)] {
+                    let (r, c) = (row as isize + d_row, col as isize + d_col);
+                    if r < 0 || c < 0 || r >= n_rows as isize || c >= n_cols as isize {
+                        continue;
+                    }
+                    let (r, c) = (r as usize, c as usize);
+                    if assigned[[r, c]] || !roof[[r, c]] {
+                        continue;
+                    }
+                    let gradient = match gradients[[r, c]] {
+                        Some(gradient) => gradient,
+                        None => continue,
+                    };
+                    let (nx, ny) = position(r, c);
+                    let predicted = plane.0 * nx + plane.1 * ny + plane.2;
+                    if (dsm[[r, c]] as f64 - predicted).abs() <= PLANE_DISTANCE_TOLERANCE
+                        && cos_between(gradient, (plane.0, plane.1)) >= cos_tolerance
+                    {
+                        assigned[[r, c]] = true;
+                        queue.push_back((r, c));
+                    }
+                }
+            }
+
+            if cells.len() < min_cells {
+                continue;
+            }
+            let (a, b) = fit.solve().map_or(seed_gradient, |(a, b, _)| (a, b));
+            let tilt = a.hypot(b).atan().to_degrees();
+            // Downhill is against the gradient; y points south, so north is -y
+            let azimuth = ((a.atan2(-b)).to_degrees() + 180.0).rem_euclid(360.0);
+            faces.push(RoofFace {
+                polygons: polygonize::cell_polygons(&cells),
+                cells,
+                tilt,
+                azimuth,
+            });
+        }
+    }
+
+    faces
+}
+
+/// Cosine of the angle between the normals of two planes given by their gradients
+fn cos_between(g1: (f64, f64), g2: (f64, f64)) -> f64 {
+    let dot = g1.0 * g2.0 + g1.1 * g2.1 + 1.0;
+    let n1 = (g1.0 * g1.0 + g1.1 * g1.1 + 1.0).sqrt();
+    let n2 = (g2.0 * g2.0 + g2.1 * g2.1 + 1.0).sqrt();
+    dot / (n1 * n2)
+}
+
+/// Aggregate the summary layers over each face. Cells outside the AOI (masked to
+/// NaN) are left out; faces with no cells left are dropped.
+pub fn face_statistics(
+    faces: Vec<RoofFace>,
+    stats: &SummaryStats,
+    analysis_hours: f32,
+    usable_sun_fraction: f32,
+    resolution: f64,
+) -> Vec<RoofFaceStats> {
+    let cell_area = resolution * resolution;
+    let shadow_hours = &stats.total_shadow_hours;
+    let avg_shadow = &stats.avg_shadow_percentage;
+
+    faces
+        .into_iter()
+        .filter_map(|face| {
+            let cells: Vec<(usize, usize)> = face
+                .cells
+                .iter()
+                .cloned()
+                .filter(|&(row, col)| shadow_hours[[0, row, col]].is_finite())
+                .collect();
+            if cells.is_empty() {
+                return None;
+            }
+            let n = cells.len() as f32;
+            let slope_factor = 1.0 / face.tilt.to_radians().cos();
+
+            let sun_hours: Vec<f32> = cells
+                .iter()
+                .map(|&(row, col)| (analysis_hours - shadow_hours[[0, row, col]]).max(0.0))
+                .collect();
+            let usable_cells = sun_hours
+                .iter()
+                .filter(|&&hours| hours >= usable_sun_fraction * analysis_hours)
+                .count();
+            let global_irradiation = stats.global_irradiation.as_ref().map(|layer| {
+                cells
+                    .iter()
+                    .map(|&(row, col)| layer[[0, row, col]])
+                    .sum::<f32>()
+                    / n
+            });
+
+            Some(RoofFaceStats {
+                area: cells.len() as f64 * cell_area * slope_factor,
+                footprint_area: cells.len() as f64 * cell_area,
+                sun_hours: sun_hours.iter().sum::<f32>() / n,
+                usable_area: usable_cells as f64 * cell_area * slope_factor,
+                avg_shadow_fraction: cells
+                    .iter()
+                    .map(|&(row, col)| avg_shadow[[0, row, col]])
+                    .sum::<f32>()
+                    / n,
+                global_irradiation,
+                face,
+            })
+        })
+        .collect()
+}
+
+/// Write roof faces as a GeoJSON FeatureCollection of (Multi)Polygons in the
+/// raster's coordinate system
+pub fn write_geojson(
+    path: &Path,
+    faces: &[RoofFaceStats],
+    transform: &[f64; 6],
+) -> Result<(), ShadowError> {
+    let round = |value: f64, places: i32| {
+        let scale = 10f64.powi(places);
+        (value * scale).round() / scale
+    };
+
+    let features: Vec<serde_json::Value> = faces
+        .iter()
+        .enumerate()
+        .map(|(id, stats)| {
+            let polygons: Vec<serde_json::Value> = stats
+                .face
+                .polygons
+                .iter()
+                .map(|polygon| {
+                    let world = polygon
+                        .map_coords(|coord| geo_types::Coord {
+                            x: transform[0] + coord.x * transform[1] + coord.y * transform[2],
+                            y: transform[3] + coord.x * transform[4] + coord.y * transform[5],
+                        })
+                        .orient(Direction::Default);
+                    polygon_coordinates(&world)
+                })
+                .collect();
+            let geometry = if polygons.len() == 1 {
+                json!({ "type": "Polygon", "coordinates": polygons[0] })
+            } else {
+                json!({ "type": "MultiPolygon", "coordinates": polygons })
+            };
+
+            json!({
+                "type": "Feature",
+                "geometry": geometry,
+                "properties": {
+                    "face_id": id,
+                    "area_m2": round(stats.area, 1),
+                    "footprint_area_m2": round(stats.footprint_area, 1),
+                    "tilt_deg": round(stats.face.tilt, 1),
+                    "azimuth_deg": round(stats.face.azimuth, 1),
+                    "sun_hours": round(stats.sun_hours as f64, 1),
+                    "usable_area_m2": round(stats.usable_area, 1),
+                    "avg_shadow_fraction": round(stats.avg_shadow_fraction as f64, 3),
+                    "global_irradiation_kwh_m2": stats.global_irradiation.map(|v| round(v as f64, 1)),
+                },
+            })
+        })
+        .collect();
+
+    let collection = json!({
+        "type": "FeatureCollection",
+        "features": features,
+    });
+
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    serde_json::to_writer(&mut file, &collection)
+        .map_err(|e| ShadowError::General(format!("Failed to write GeoJSON: {}", e)))?;
+    file.flush()?;
+    Ok(())
+}
+
+fn polygon_coordinates(polygon: &Polygon<f64>) -> serde_json::Value {
+    let ring = |line: &geo_types::LineString<f64>| -> Vec<[f64; 2]> {
+        line.coords().map(|c| [c.x, c.y]).collect()
+    };
+    let mut rings = vec![ring(polygon.exterior())];
+    rings.extend(polygon.interiors().iter().map(ring));
+    json!(rings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plane_fit_recovers_a_plane() {
+        let mut fit = PlaneFit::default();
+        for (x, y) in [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (2.0, 3.0)] {
+            fit.add(x, y, 0.5 * x - 0.25 * y + 4.0);
+        }
+        let (a, b, c) = fit.solve().unwrap();
+        assert!((a - 0.5).abs() < 1e-9 && (b + 0.25).abs() < 1e-9 && (c - 4.0).abs() < 1e-9);
+
+        // Points on a line leave the plane's tilt across it undetermined
+        let mut line = PlaneFit::default();
+        for x in 0..4 {
+            line.add(x as f64, 2.0, x as f64);
+        }
+        assert!(line.solve().is_none());
+    }
+
+    #[test]
+    fn gable_roof_splits_into_two_faces() {
+        // A ridge between rows 5 and 6, falling 0.5 m per metre to the north and south
+        let dsm = Array2::from_shape_fn((12, 10), |(row, _)| {
+            let from_ridge = if row <= 5 { 5 - row } else { row - 6 };
+            10.0 - 0.5 * from_ridge as f32
+        });
+        // The ridge rows see both slopes in their 3×3 fit, and form 10-cell faces that
+        // fall below the minimum area
+        let params = RoofSegmentation {
+            angle_tolerance: 10.0,
+            min_area: 15.0,
+        };
+        let mut faces = segment_roof_faces(&dsm, 1.0, &params, |_, _| true);
+        faces.sort_by(|a, b| a.azimuth.total_cmp(&b.azimuth));
+
+        let expected_tilt = 0.5f64.atan().to_degrees();
+        let summary: Vec<(usize, f64)> = faces
+            .iter()
+            .map(|face| (face.cells.len(), face.azimuth))
+            .collect();
+        assert_eq!(summary.len(), 2, "{:?}", summary);
+        for (face, (rows, azimuth)) in faces.iter().zip([(0..5, 0.0), (7..12, 180.0)]) {
+            assert_eq!(face.cells.len(), 50);
+            assert!(face.cells.iter().all(|(row, _)| rows.contains(row)));
+            assert!((face.tilt - expected_tilt).abs() < 1e-6);
+            assert!((face.azimuth - azimuth).abs() < 1e-6);
+            assert_eq!(face.polygons.len(), 1);
+        }
+    }
+}
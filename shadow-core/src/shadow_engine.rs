@@ -0,0 +1,2772 @@
+use crate::facade::{self, FacadeSegmentStats, WallSegment};
+use crate::horizon::{HorizonProfile, HORIZON_RAY_OVERREACH};
+use crate::irradiance;
+use crate::progress::{ProgressSink, ProgressUpdate};
+use crate::pv::{self, PvSample, PvYield};
+use crate::raster_io::RasterIO;
+use crate::roof::{self, RoofFace, RoofSegmentation};
+use crate::shadow_store::ShadowStore;
+use crate::sun_position::SunCalculator;
+use crate::surface::SurfaceOrientation;
+use crate::sweep_line;
+use crate::types::*;
+use crate::weather::WeatherData;
+use chrono::{Datelike, Timelike};
+use indicatif::{ProgressBar, ProgressStyle};
+use ndarray::{s, Array2, Array3, Axis};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Time series larger than this are streamed to disk when storage is `Auto`
+const AUTO_DISK_THRESHOLD_BYTES: usize = 2 * 1024 * 1024 * 1024;
+
+/// Upper bound on the time series block held in memory while computing statistics
+const STATS_BLOCK_BYTES: usize = 256 * 1024 * 1024;
+
+/// Disk stores created by this process so far, numbering their files
+static DISK_STORE_COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+/// Mean Earth radius in metres
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+/// Standard coefficient of terrestrial refraction (ray radius = Earth radius / k)
+const REFRACTION_COEFFICIENT: f64 = 0.13;
+
+/// Rays keeping less light than this through canopy count as fully shaded
+const MIN_TRANSMITTANCE: f64 = 1e-3;
+
+/// Angular radius of the solar disk in degrees (0.533° mean diameter)
+const SOLAR_ANGULAR_RADIUS_DEG: f64 = 0.2665;
+
+/// Timestamps sharing one rounded sun position, and therefore one shadow map
+struct SunPositionGroup {
+    azimuth: f64,
+    elevation: f64,
+    leaf_on: bool,
+    time_indices: Vec<usize>,
+}
+
+pub struct ShadowEngine {
+    dtm: Array2<f32>,
+    dsm: Array2<f32>,
+    heights: Array2<f32>,
+    vegetation: Option<Array2<bool>>,
+    weather: Option<WeatherData>,
+    resolution: f64,
+    transform: [f64; 6],
+    aoi_polygon: geo_types::Polygon<f64>,
+    sun_calculator: Arc<Mutex<SunCalculator>>,
+    config: Config,
+    progress_sink: Option<Arc<dyn ProgressSink>>,
+    optimization_logged: Arc<Mutex<bool>>,
+    cancel_token: CancellationToken,
+}
+
+impl ShadowEngine {
+    pub fn new(
+        dtm: Array2<f32>,
+        dsm: Array2<f32>,
+        resolution: f64,
+        transform: [f64; 6],
+        config: Config,
+    ) -> Self {
+        // Configure Rayon thread pool with specified CPU cores
+        let cpu_cores = config.get_cpu_cores();
+        println!("Setting up Rayon thread pool with {} cores", cpu_cores);
+
+        if let Err(e) = rayon::ThreadPoolBuilder::new()
+            .num_threads(cpu_cores)
+            .build_global()
+        {
+            eprintln!("Warning: Failed to configure Rayon thread pool: {}", e);
+            println!("Using default thread pool configuration");
+        }
+
+        let heights = &dsm - &dtm;
+        let polygon = config.to_polygon().unwrap_or(geo_types::Polygon::new(
+            geo_types::LineString::from(vec![
+                (0.0, 0.0),
+                (1.0, 0.0),
+                (1.0, 1.0),
+                (0.0, 1.0),
+                (0.0, 0.0),
+            ]),
+            vec![],
+        ));
+        let centroid = geo::algorithm::centroid::Centroid::centroid(&polygon)
+            .unwrap_or(geo_types::Point::new(0.0, 0.0));
+        let sun_calculator = Arc::new(Mutex::new(SunCalculator::new_with_refraction(
+            centroid.y(),
+            centroid.x(),
+            config.angle_precision,
+            config.get_curvature_refraction(),
+        )));
+
+        Self {
+            dtm,
+            dsm,
+            heights,
+            vegetation: None,
+            weather: None,
+            resolution,
+            transform,
+            aoi_polygon: polygon,
+            sun_calculator,
+            config,
+            progress_sink: None,
+            optimization_logged: Arc::new(Mutex::new(false)),
+            cancel_token: CancellationToken::new(),
+        }
+    }
+
+    /// Report progress to a sink as well as the terminal
+    pub fn with_progress_sink(mut self, sink: Arc<dyn ProgressSink>) -> Self {
+        self.progress_sink = Some(sink);
+        self
+    }
+
+    /// Stop long-running calculations with `ShadowError::Cancelled` once the token
+    /// is cancelled
+    pub fn with_cancel_token(mut self, cancel_token: CancellationToken) -> Self {
+        self.cancel_token = cancel_token;
+        self
+    }
+
+    /// Mark canopy cells from a raster on the DSM grid. Rays passing through them are
+    /// attenuated by the seasonal transmissivity instead of being blocked.
+    pub fn with_vegetation(mut self, canopy: &Array2<f32>) -> Self {
+        let classes = self.config.vegetation_classes.clone();
+        self.vegetation = Some(canopy.mapv(|value| match &classes {
+            Some(classes) => value.is_finite() && classes.contains(&(value.round() as i32)),
+            None => value > 0.0,
+        }));
+        self
+    }
+
+    /// Weight timestamps by measured or typical irradiance instead of assuming clear
+    /// sky: adds an effective sun hours layer and drives the irradiation layers.
+    pub fn with_weather(mut self, weather: WeatherData) -> Self {
+        self.weather = Some(weather);
+        self
+    }
+
+    /// (rows, cols) of the clipped grid the engine works on
+    pub fn dimensions(&self) -> (usize, usize) {
+        self.dsm.dim()
+    }
+
+    fn emit_progress(
+        &self,
+        progress: f64,
+        step: String,
+        total_steps: Option<usize>,
+        current_step: Option<usize>,
+    ) {
+        if let Some(sink) = &self.progress_sink {
+            sink.report(&ProgressUpdate {
+                progress,
+                current_step: step,
+                total_steps,
+                current_step_number: current_step,
+            });
+        }
+    }
+
+    fn check_cancelled(&self) -> Result<(), ShadowError> {
+        if self.cancel_token.is_cancelled() {
+            Err(ShadowError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn calculate_shadows(&self) -> Result<ShadowResult, ShadowError> {
+        let timestamps = self.generate_timestamps();
+        let n_times = timestamps.len();
+        let (n_rows, n_cols) = self.heights.dim();
+
+        let mut shadow_fraction = self.create_shadow_store(n_times, n_rows, n_cols)?;
+
+        // Emit initial progress
+        self.emit_progress(
+            0.0,
+            "Initializing shadow calculation...".to_string(),
+            Some(n_times),
+            Some(0),
+        );
+
+        if self.vegetation.is_some()
+            && self.config.get_shadow_algorithm() != ShadowAlgorithm::RayMarching
+        {
+            println!(
+                "Vegetation transmissivity needs per-ray tracing; using RayMarching instead of {:?}",
+                self.config.get_shadow_algorithm()
+            );
+        } else if self.shadow_algorithm() != self.config.get_shadow_algorithm() {
+            println!(
+                "Earth curvature correction needs per-ray tracing; using RayMarching instead of {:?}",
+                self.config.get_shadow_algorithm()
+            );
+        }
+
+        // Group timestamps that round to the same sun position so each distinct
+        // shadow map is computed only once
+        let sun_groups = self.group_timestamps_by_sun_position(&timestamps)?;
+
+        // Precompute horizon profiles once so each timestamp becomes a lookup
+        let horizon_profile = match self.shadow_algorithm() {
+            ShadowAlgorithm::HorizonProfile => {
+                if let Some(drawback) = self.horizon_profile_drawback(&sun_groups) {
+                    println!("{}; using RayMarching instead of HorizonProfile", drawback);
+                    None
+                } else {
+                    self.emit_progress(
+                        0.0,
+                        "Precomputing horizon profiles...".to_string(),
+                        Some(n_times),
+                        Some(0),
+                    );
+                    Some(self.compute_horizon_profile()?)
+                }
+            }
+            ShadowAlgorithm::RayMarching | ShadowAlgorithm::SweepLine => None,
+        };
+        let n_unique = sun_groups.len();
+        let saved_fraction = if n_times > 0 {
+            1.0 - n_unique as f64 / n_times as f64
+        } else {
+            0.0
+        };
+        println!(
+            "Sun position reuse: {} unique positions for {} timestamps ({:.1}% of shadow maps saved)",
+            n_unique,
+            n_times,
+            saved_fraction * 100.0
+        );
+
+        // Create progress bar for console (keep for debugging)
+        let pb = ProgressBar::new(n_unique as u64);
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template(
+                    "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})",
+                )
+                .unwrap(),
+        );
+
+        // Clear-sky beam energy reaching each cell, and diffuse energy on open ground
+        let mut direct_irradiation = self
+            .config
+            .get_irradiance()
+            .then(|| Array2::<f32>::zeros((n_rows, n_cols)));
+        let mut open_diffuse_kwh = 0.0f64;
+
+        // Sunlit hours scaled by how much of the clear-sky beam the weather lets through
+        let mut effective_sun_hours = self
+            .weather
+            .as_ref()
+            .map(|_| Array2::<f32>::zeros((n_rows, n_cols)));
+
+        // Cell orientation, for self-shading and direct irradiation on sloped surfaces
+        let surface = self.surface_orientation();
+        let mut self_shaded_hours = self
+            .config
+            .get_self_shading()
+            .then(|| Array2::<f32>::zeros((n_rows, n_cols)));
+
+        // Process unique sun positions sequentially to emit proper progress
+        for (group_idx, group) in sun_groups.iter().enumerate() {
+            self.check_cancelled()?;
+
+            let step_description = format!(
+                "Calculating shadows ({} unique sun positions for {} timestamps)...",
+                n_unique, n_times
+            );
+            let progress = (group_idx as f64) / (n_unique as f64) * 100.0;
+
+            self.emit_progress(
+                progress,
+                step_description,
+                Some(n_unique),
+                Some(group_idx + 1),
+            );
+
+            let below_horizon = self.sun_below_horizon(group.elevation);
+            let mut shadow_map = if below_horizon {
+                Array2::<f32>::ones((n_rows, n_cols))
+            } else {
+                self.calculate_shadow_map(
+                    group.azimuth,
+                    group.elevation,
+                    group.leaf_on,
+                    horizon_profile.as_ref(),
+                )?
+            };
+
+            let cos_incidence = match &surface {
+                Some(surface) if !below_horizon && self.needs_cos_incidence() => {
+                    Some(surface.cos_incidence_map(group.azimuth, group.elevation))
+                }
+                _ => None,
+            };
+
+            // Cells whose surface faces away from the sun are shaded by themselves
+            if let (Some(hours), Some(cos_incidence)) =
+                (self_shaded_hours.as_mut(), cos_incidence.as_ref())
+            {
+                let group_hours = self.config.hour_interval * group.time_indices.len() as f32;
+                for ((shadow, cell_hours), &cos) in shadow_map
+                    .iter_mut()
+                    .zip(hours.iter_mut())
+                    .zip(cos_incidence.iter())
+                {
+                    if cos <= 0.0 {
+                        *shadow = 1.0;
+                        *cell_hours += group_hours;
+                    }
+                }
+            }
+
+            for &t_idx in &group.time_indices {
+                shadow_fraction.write_slice(t_idx, &shadow_map)?;
+            }
+
+            if let (Some(weather), Some(sun_hours)) =
+                (self.weather.as_ref(), effective_sun_hours.as_mut())
+            {
+                let group_hours = self.weather_sun_hours(weather, group, &timestamps) as f32;
+                if group_hours > 0.0 {
+                    ndarray::Zip::from(sun_hours)
+                        .and(&shadow_map)
+                        .for_each(|hours, &shadow| *hours += (1.0 - shadow) * group_hours);
+                }
+            }
+
+            if let Some(direct) = direct_irradiation.as_mut() {
+                let (direct_normal_kwh, diffuse_kwh) = self.sky_energy(group, &timestamps);
+                open_diffuse_kwh += diffuse_kwh;
+                if direct_normal_kwh > 0.0 {
+                    // Beam on the cell's own surface plane, or on a horizontal one
+                    let projection = match &cos_incidence {
+                        Some(cos_incidence) if self.config.get_incidence_weighting() => {
+                            cos_incidence.mapv(|cos| cos.max(0.0))
+                        }
+                        _ => Array2::from_elem(
+                            (n_rows, n_cols),
+                            group.elevation.to_radians().sin() as f32,
+                        ),
+                    };
+                    ndarray::Zip::from(direct)
+                        .and(&shadow_map)
+                        .and(&projection)
+                        .for_each(|energy, &shadow, &projection| {
+                            *energy += (1.0 - shadow) * projection * direct_normal_kwh as f32;
+                        });
+                }
+            }
+
+            pb.set_position(group_idx as u64 + 1);
+        }
+
+        pb.finish_with_message("Shadow calculation complete");
+        self.emit_progress(
+            100.0,
+            format!(
+                "Shadow calculation complete: {} unique sun positions for {} timestamps ({:.1}% saved)",
+                n_unique,
+                n_times,
+                saved_fraction * 100.0
+            ),
+            Some(n_unique),
+            Some(n_unique),
+        );
+
+        let mut summary_stats = self.calculate_summary_stats(&shadow_fraction, &timestamps)?;
+
+        // Diffuse irradiation needs the sky view factor even when it isn't exported
+        if self.config.get_sky_view_factor() || direct_irradiation.is_some() {
+            self.emit_progress(
+                100.0,
+                "Computing sky view factor...".to_string(),
+                Some(n_unique),
+                Some(n_unique),
+            );
+            let sky_view_factor = self.compute_sky_view_factor()?;
+
+            if let Some(direct) = direct_irradiation {
+                let diffuse = sky_view_factor.mapv(|svf| svf * open_diffuse_kwh as f32);
+                let global = &direct + &diffuse;
+                summary_stats.direct_irradiation = Some(direct.insert_axis(Axis(0)));
+                summary_stats.diffuse_irradiation = Some(diffuse.insert_axis(Axis(0)));
+                summary_stats.global_irradiation = Some(global.insert_axis(Axis(0)));
+            }
+            if self.config.get_sky_view_factor() {
+                summary_stats.sky_view_factor = Some(sky_view_factor.insert_axis(Axis(0)));
+            }
+        }
+
+        if let Some(surface) = surface.filter(|_| self.config.get_slope_aspect()) {
+            summary_stats.slope = Some(surface.slope().clone().insert_axis(Axis(0)));
+            summary_stats.aspect = Some(surface.aspect().clone().insert_axis(Axis(0)));
+        }
+        summary_stats.self_shaded_hours = self_shaded_hours.map(|hours| hours.insert_axis(Axis(0)));
+        summary_stats.effective_sun_hours =
+            effective_sun_hours.map(|hours| hours.insert_axis(Axis(0)));
+
+        Ok(ShadowResult {
+            shadow_fraction,
+            timestamps,
+            summary_stats,
+            unique_sun_positions: Some(n_unique),
+        })
+    }
+
+    /// Sun hours on vertical walls found from height jumps in the DSM.
+    ///
+    /// Sample points are spaced up each wall segment just in front of its face and
+    /// cast toward the sun; a sample counts as lit only while the sun is in front of
+    /// the wall. Walls are cell edges, so these rays always use exact grid traversal.
+    pub fn calculate_facade_sun_hours(&self) -> Result<Vec<FacadeSegmentStats>, ShadowError> {
+        use geo::algorithm::contains::Contains;
+
+        // Keep walls whose front cell lies in the AOI
+        let segments = facade::extract_wall_segments(
+            &self.dsm,
+            self.config.get_facade_min_wall_height() as f32,
+            |row, col| {
+                let (x, y) = RasterIO::pixel_to_world(col, row, &self.transform);
+                self.aoi_polygon.contains(&geo_types::Coord { x, y })
+            },
+        );
+        let spacing = self.config.get_facade_level_spacing() as f32;
+        let levels: Vec<Vec<f32>> = segments
+            .iter()
+            .map(|segment| Self::facade_levels(segment, spacing))
+            .collect();
+
+        let timestamps = self.generate_timestamps();
+        let sun_groups = self.group_timestamps_by_sun_position(&timestamps)?;
+        let n_groups = sun_groups.len();
+        println!(
+            "Facade analysis: {} wall segments, {} samples, {} unique sun positions",
+            segments.len(),
+            segments
+                .iter()
+                .zip(&levels)
+                .map(|(segment, heights)| segment.edges.len() * heights.len())
+                .sum::<usize>(),
+            n_groups
+        );
+
+        let mut sun_hours: Vec<Vec<f32>> = levels.iter().map(|l| vec![0.0; l.len()]).collect();
+
+        for (group_idx, group) in sun_groups.iter().enumerate() {
+            self.check_cancelled()?;
+            self.emit_progress(
+                group_idx as f64 / n_groups as f64 * 100.0,
+                format!("Tracing facade sunlight ({} sun positions)...", n_groups),
+                Some(n_groups),
+                Some(group_idx + 1),
+            );
+
+            if self.sun_below_horizon(group.elevation) {
+                continue;
+            }
+
+            // Each disk sample as its azimuth and ray direction
+            let disk: Vec<(f64, (f64, f64, f64))> = self
+                .solar_disk_samples(group.azimuth, group.elevation)
+                .iter()
+                .map(|&(az, el)| (az, self.sun_direction(az, el)))
+                .collect();
+            let transmissivity = self.canopy_transmissivity(group.leaf_on);
+            let group_hours = self.config.hour_interval * group.time_indices.len() as f32;
+
+            let lit: Vec<Vec<f32>> = segments
+                .par_iter()
+                .zip(levels.par_iter())
+                .map(|(segment, heights)| {
+                    // Only the part of the disk in front of the wall can light it
+                    let facing_dirs: Vec<(f64, f64, f64)> = disk
+                        .iter()
+                        .filter(|&&(az, _)| (az - segment.orientation).to_radians().cos() > 0.0)
+                        .map(|&(_, dir)| dir)
+                        .collect();
+                    // Sun behind the wall, or calculation cancelled: nothing lit
+                    if facing_dirs.is_empty() || self.cancel_token.is_cancelled() {
+                        return vec![0.0; heights.len()];
+                    }
+                    heights
+                        .iter()
+                        .map(|&height| {
+                            let lit_sum: f32 = segment
+                                .edges
+                                .iter()
+                                .flat_map(|edge| facing_dirs.iter().map(move |&dir| (edge, dir)))
+                                .map(|(edge, sun_dir)| {
+                                    // Start just off the wall, inside the front cell
+                                    let (x, y) = edge.midpoint;
+                                    let (front_row, front_col) = edge.front_cell;
+                                    let col = x + (front_col as f64 - x) * 0.1;
+                                    let row = y + (front_row as f64 - y) * 0.1;
+                                    1.0 - self.traverse_grid_shadow(
+                                        row,
+                                        col,
+                                        height as f64,
+                                        sun_dir,
+                                        transmissivity,
+                                    )
+                                })
+                                .sum();
+                            lit_sum / (segment.edges.len() * disk.len()) as f32
+                        })
+                        .collect()
+                })
+                .collect();
+
+            self.check_cancelled()?;
+
+            for (segment_hours, segment_lit) in sun_hours.iter_mut().zip(lit) {
+                for (hours, lit_fraction) in segment_hours.iter_mut().zip(segment_lit) {
+                    *hours += lit_fraction * group_hours;
+                }
+            }
+        }
+
+        self.emit_progress(
+            100.0,
+            format!("Facade analysis complete: {} wall segments", segments.len()),
+            Some(n_groups),
+            Some(n_groups),
+        );
+
+        Ok(segments
+            .into_iter()
+            .zip(levels)
+            .zip(sun_hours)
+            .map(|((segment, level_heights), sun_hours)| FacadeSegmentStats {
+                segment,
+                level_heights,
+                sun_hours,
+            })
+            .collect())
+    }
+
+    /// Hillshade of the DSM lit from the north-west at 45°, from 0 (facing away
+    /// from the light) to 1
+    pub fn hillshade(&self) -> Array2<f32> {
+        SurfaceOrientation::from_elevation(&self.dsm, self.resolution)
+            .cos_incidence_map(315.0, 45.0)
+            .mapv(|value| value.max(0.0))
+    }
+
+    /// Planar roof faces of the buildings in the AOI, segmented from the DSM wherever
+    /// the nDSM is at least the roof height. Canopy cells are never roofs.
+    pub fn segment_roof_faces(&self) -> Vec<RoofFace> {
+        use geo::algorithm::contains::Contains;
+
+        let min_height = self.config.get_roof_min_height() as f32;
+        let params = RoofSegmentation {
+            angle_tolerance: self.config.get_roof_angle_tolerance(),
+            min_area: self.config.get_roof_min_area(),
+        };
+        let faces = roof::segment_roof_faces(&self.dsm, self.resolution, &params, |row, col| {
+            if self.heights[[row, col]] < min_height
+                || self
+                    .vegetation
+                    .as_ref()
+                    .is_some_and(|mask| mask[[row, col]])
+            {
+                return false;
+            }
+            let (x, y) = RasterIO::pixel_to_world(col, row, &self.transform);
+            self.aoi_polygon.contains(&geo_types::Coord { x, y })
+        });
+        println!("Roof segmentation: {} faces", faces.len());
+        faces
+    }
+
+    /// Estimate the yield of a PV array from the shading in `result`, which must come
+    /// from this engine's grid. The array covers the AOI cells inside its polygon, or
+    /// the whole AOI when it has none.
+    pub fn estimate_pv_yield(
+        &self,
+        array: &PvArray,
+        result: &ShadowResult,
+    ) -> Result<PvYield, ShadowError> {
+        use geo::algorithm::contains::Contains;
+
+        let outline = match &array.polygon {
+            Some(coords) => {
+                if coords.len() < 3 || coords.iter().any(|c| c.len() < 2) {
+                    return Err(ShadowError::General(
+                        "PV array polygon needs at least three [x, y] points".to_string(),
+                    ));
+                }
+                let ring: Vec<(f64, f64)> = coords.iter().map(|c| (c[0], c[1])).collect();
+                geo_types::Polygon::new(geo_types::LineString::from(ring), vec![])
+            }
+            None => self.aoi_polygon.clone(),
+        };
+
+        let (n_rows, n_cols) = self.heights.dim();
+        let cells: Vec<(usize, usize)> = (0..n_rows)
+            .flat_map(|row| (0..n_cols).map(move |col| (row, col)))
+            .filter(|&(row, col)| {
+                let (x, y) = RasterIO::pixel_to_world(col, row, &self.transform);
+                let point = geo_types::Coord { x, y };
+                outline.contains(&point) && self.aoi_polygon.contains(&point)
+            })
+            .collect();
+        let (row_start, row_end) = match (cells.first(), cells.last()) {
+            (Some(first), Some(last)) => (first.0, last.0 + 1),
+            _ => {
+                return Err(ShadowError::General(
+                    "PV array does not cover any cell of the analysed area".to_string(),
+                ))
+            }
+        };
+
+        // Mean shading over the array at each timestamp, skipping masked cells. The
+        // time series is read in row blocks so disk-backed results never need to fit in
+        // memory; `cells` is in row order, so each block's cells are a contiguous run.
+        let n_times = result.timestamps.len();
+        let mut sums = vec![0.0f64; n_times];
+        let mut counts = vec![0usize; n_times];
+        let rows_per_block = result.shadow_fraction.rows_per_block(STATS_BLOCK_BYTES);
+        let mut block_start = row_start;
+        while block_start < row_end {
+            self.check_cancelled()?;
+
+            let block_end = (block_start + rows_per_block).min(row_end);
+            let block = result
+                .shadow_fraction
+                .read_row_block(block_start, block_end)?;
+            let first = cells.partition_point(|&(row, _)| row < block_start);
+            let last = cells.partition_point(|&(row, _)| row < block_end);
+            for &(row, col) in &cells[first..last] {
+                for t_idx in 0..n_times {
+                    let value = block[[t_idx, row - block_start, col]];
+                    if value.is_finite() {
+                        sums[t_idx] += value as f64;
+                        counts[t_idx] += 1;
+                    }
+                }
+            }
+            block_start = block_end;
+        }
+
+        let sun_calc = self.sun_calculator.lock().unwrap();
+        let samples: Vec<PvSample> = result
+            .timestamps
+            .iter()
+            .enumerate()
+            .map(|(t_idx, timestamp)| {
+                let (azimuth, elevation) = sun_calc.get_exact_position(timestamp);
+                PvSample {
+                    timestamp: *timestamp,
+                    azimuth,
+                    elevation,
+                    sky: self.sky_irradiance(timestamp, elevation),
+                    shaded_fraction: if counts[t_idx] > 0 {
+                        sums[t_idx] / counts[t_idx] as f64
+                    } else {
+                        0.0
+                    },
+                }
+            })
+            .collect();
+        drop(sun_calc);
+
+        let footprint_area = cells.len() as f64 * self.resolution * self.resolution;
+        println!(
+            "PV array: {} cells, {:.1} m² footprint, {} timestamps",
+            cells.len(),
+            footprint_area,
+            samples.len()
+        );
+        Ok(pv::estimate_yield(
+            array,
+            footprint_area,
+            &samples,
+            self.config.hour_interval as f64,
+        ))
+    }
+
+    /// Sample heights at the middle of evenly split bands from wall base to top
+    fn facade_levels(segment: &WallSegment, spacing: f32) -> Vec<f32> {
+        let span = (segment.top_height - segment.base_height).max(0.0);
+        let n_levels = ((span / spacing).floor() as usize).max(1);
+        let band = span / n_levels as f32;
+        (0..n_levels)
+            .map(|level| segment.base_height + (level as f32 + 0.5) * band)
+            .collect()
+    }
+
+    fn create_shadow_store(
+        &self,
+        n_times: usize,
+        n_rows: usize,
+        n_cols: usize,
+    ) -> Result<ShadowStore, ShadowError> {
+        let required_bytes = n_times * n_rows * n_cols * std::mem::size_of::<f32>();
+        let use_disk = match self.config.get_result_storage() {
+            ResultStorage::Memory => false,
+            ResultStorage::Disk => true,
+            ResultStorage::Auto => required_bytes > AUTO_DISK_THRESHOLD_BYTES,
+        };
+
+        if use_disk {
+            // Process id and a per-process counter keep concurrent runs apart
+            let path = self.config.get_storage_dir().join(format!(
+                "shadows_{}_{}_{}.f32",
+                std::process::id(),
+                DISK_STORE_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+                chrono::Utc::now().format("%Y%m%d_%H%M%S%3f")
+            ));
+            println!(
+                "Streaming {:.1} MB of shadow maps to {:?}",
+                required_bytes as f64 / (1024.0 * 1024.0),
+                path
+            );
+            ShadowStore::on_disk(&path, n_times, n_rows, n_cols)
+        } else {
+            Ok(ShadowStore::in_memory(n_times, n_rows, n_cols))
+        }
+    }
+
+    /// Whether no part of the sun is above the horizon. With a finite disk the upper
+    /// limb is still visible while the centre is just below 0°.
+    fn sun_below_horizon(&self, elevation: f64) -> bool {
+        let disk_radius = if self.config.get_solar_disk_samples() > 1 {
+            SOLAR_ANGULAR_RADIUS_DEG
+        } else {
+            0.0
+        };
+        elevation + disk_radius <= 0.0
+    }
+
+    fn group_timestamps_by_sun_position(
+        &self,
+        timestamps: &[chrono::DateTime<chrono::Utc>],
+    ) -> Result<Vec<SunPositionGroup>, ShadowError> {
+        let mut groups: Vec<SunPositionGroup> = Vec::new();
+        let mut group_index: HashMap<(i64, i64, bool), usize> = HashMap::new();
+
+        // The same sun position in spring and autumn can fall either side of leaf-out
+        let leaf_season = match self.vegetation {
+            Some(_) => Some(self.config.get_leaf_season()?),
+            None => None,
+        };
+
+        let sun_calc = self.sun_calculator.lock().unwrap();
+        let inv_precision = 1.0 / self.config.angle_precision;
+
+        for (t_idx, timestamp) in timestamps.iter().enumerate() {
+            let (azimuth, elevation) = sun_calc.get_exact_position(timestamp);
+            let leaf_on = leaf_season
+                .map(|(leaf_on, leaf_off)| Self::is_leaf_on(timestamp, leaf_on, leaf_off))
+                .unwrap_or(false);
+
+            // Every position below the horizon yields the same all-shadow map
+            let key = if self.sun_below_horizon(elevation) {
+                (i64::MIN, i64::MIN, false)
+            } else {
+                (
+                    (azimuth * inv_precision).round() as i64,
+                    (elevation * inv_precision).round() as i64,
+                    leaf_on,
+                )
+            };
+
+            match group_index.get(&key) {
+                Some(&idx) => groups[idx].time_indices.push(t_idx),
+                None => {
+                    group_index.insert(key, groups.len());
+                    groups.push(SunPositionGroup {
+                        azimuth,
+                        elevation,
+                        leaf_on,
+                        time_indices: vec![t_idx],
+                    });
+                }
+            }
+        }
+
+        Ok(groups)
+    }
+
+    /// Whether `timestamp` falls in the leaf-on season. A leaf-on date later in the
+    /// year than leaf-off (southern hemisphere) wraps the season over New Year.
+    fn is_leaf_on(
+        timestamp: &chrono::DateTime<chrono::Utc>,
+        leaf_on: MonthDay,
+        leaf_off: MonthDay,
+    ) -> bool {
+        let day = (timestamp.month(), timestamp.day());
+        if leaf_on <= leaf_off {
+            day >= leaf_on && day < leaf_off
+        } else {
+            day >= leaf_on || day < leaf_off
+        }
+    }
+
+    /// Algorithm actually used: vegetation needs per-ray attenuation, which only
+    /// ray marching provides
+    fn shadow_algorithm(&self) -> ShadowAlgorithm {
+        match self.config.get_shadow_algorithm() {
+            _ if self.vegetation.is_some() => ShadowAlgorithm::RayMarching,
+            // The sweep carries one shadow height, with no distance to lower it by
+            ShadowAlgorithm::SweepLine if self.config.get_curvature_refraction() => {
+                ShadowAlgorithm::RayMarching
+            }
+            algorithm => algorithm,
+        }
+    }
+
+    /// Fraction of light kept per metre of canopy in the given season
+    fn canopy_transmissivity(&self, leaf_on: bool) -> f64 {
+        if leaf_on {
+            self.config.get_leaf_on_transmissivity()
+        } else {
+            self.config.get_leaf_off_transmissivity()
+        }
+    }
+
+    /// Whether the obstruction at a fractional pixel position is vegetation. The
+    /// interpolated height there is dominated by the tallest of the four surrounding
+    /// cells, so that cell decides; otherwise a crown's height blurred onto the
+    /// neighbouring ground would count as opaque.
+    fn is_vegetation(&self, row: f64, col: f64) -> bool {
+        let mask = match &self.vegetation {
+            Some(mask) => mask,
+            None => return false,
+        };
+        let r0 = row.floor() as usize;
+        let c0 = col.floor() as usize;
+        let r1 = (r0 + 1).min(self.dsm.nrows() - 1);
+        let c1 = (c0 + 1).min(self.dsm.ncols() - 1);
+
+        let tallest = [(r0, c0), (r0, c1), (r1, c0), (r1, c1)]
+            .into_iter()
+            .max_by(|&a, &b| self.dsm[a].total_cmp(&self.dsm[b]))
+            .unwrap_or((r0, c0));
+        mask[tallest]
+    }
+
+    fn calculate_shadow_map(
+        &self,
+        azimuth: f64,
+        elevation: f64,
+        leaf_on: bool,
+        horizon_profile: Option<&HorizonProfile>,
+    ) -> Result<Array2<f32>, ShadowError> {
+        let (n_rows, n_cols) = self.heights.dim();
+        let transmissivity = self.canopy_transmissivity(leaf_on);
+        let mut shadow_map = Array2::<f32>::zeros((n_rows, n_cols));
+
+        // Convert sun angles to ray direction
+        let sun_dir = self.sun_direction(azimuth, elevation);
+
+        // Points on the solar disk to cast toward; just the centre for a point sun
+        let disk_samples = self.solar_disk_samples(azimuth, elevation);
+        let disk_dirs: Vec<(f64, f64, f64)> = disk_samples
+            .iter()
+            .map(|&(az, el)| self.sun_direction(az, el))
+            .collect();
+
+        // Get only cells that can potentially cast shadows on AOI
+        let relevant_cells = self.get_shadow_relevant_cells(azimuth, elevation);
+
+        // The sweep-line kernel produces a whole map per disk sample in one linear pass
+        let sweep_maps = match self.shadow_algorithm() {
+            ShadowAlgorithm::SweepLine => Some(
+                disk_samples
+                    .iter()
+                    .map(|&(az, el)| sweep_line::shadow_map(&self.dsm, az, el, &self.cancel_token))
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            ShadowAlgorithm::RayMarching | ShadowAlgorithm::HorizonProfile => None,
+        };
+
+        // Process only relevant cells in parallel (major optimization!)
+        let shadow_values: Vec<((usize, usize), f32)> = relevant_cells
+            .par_iter()
+            .map(|&(row, col)| {
+                // Skip remaining work once cancelled; the map is discarded below
+                if self.cancel_token.is_cancelled() {
+                    return ((row, col), 0.0);
+                }
+                // Fraction of the disk samples that are occluded
+                let occluded: f32 = disk_samples
+                    .iter()
+                    .enumerate()
+                    .map(|(sample, &(az, el))| {
+                        if let Some(maps) = &sweep_maps {
+                            maps[sample][[row, col]]
+                        } else if let Some(profile) = horizon_profile {
+                            self.horizon_cell_shadow(
+                                profile,
+                                row,
+                                col,
+                                (az, el),
+                                disk_dirs[sample],
+                                transmissivity,
+                            )
+                        } else {
+                            self.calculate_cell_shadow(row, col, disk_dirs[sample], transmissivity)
+                        }
+                    })
+                    .sum();
+                ((row, col), occluded / disk_samples.len() as f32)
+            })
+            .collect();
+
+        self.check_cancelled()?;
+
+        // Assign results back to the shadow map
+        for ((row, col), shadow_value) in shadow_values {
+            shadow_map[[row, col]] = shadow_value;
+        }
+
+        // Apply edge refinement based on quality setting. Disk sampling already yields
+        // physical penumbra fractions, which spatial subsampling would overwrite.
+        if !matches!(self.config.shadow_quality, ShadowQuality::Fast) && disk_samples.len() == 1 {
+            shadow_map = self.refine_shadow_edges(shadow_map, sun_dir, transmissivity);
+            self.check_cancelled()?;
+        }
+
+        Ok(shadow_map)
+    }
+
+    fn get_shadow_relevant_cells(&self, azimuth: f64, elevation: f64) -> Vec<(usize, usize)> {
+        let (n_rows, n_cols) = self.heights.dim();
+        let mut relevant_cells = Vec::new();
+
+        // Get AOI bounds in pixel coordinates
+        let aoi_bounds = self.get_aoi_pixel_bounds();
+        if aoi_bounds.is_none() {
+            // Fallback: return all cells if we can't determine AOI bounds
+            return (0..n_rows)
+                .flat_map(|row| (0..n_cols).map(move |col| (row, col)))
+                .collect();
+        }
+
+        let (aoi_min_row, aoi_max_row, aoi_min_col, aoi_max_col) = aoi_bounds.unwrap();
+
+        // Calculate shadow direction (opposite of sun direction)
+        let sun_azimuth_rad = azimuth.to_radians();
+        let shadow_dir_x = -sun_azimuth_rad.sin();
+        let shadow_dir_y = sun_azimuth_rad.cos();
+
+        // Maximum shadow distance based on terrain and sun angle
+        let max_shadow_distance_pixels =
+            (self.config.buffer_meters.unwrap_or(1000.0) / self.resolution) as i32;
+
+        // Check each cell to see if it could cast a shadow on the AOI
+        for row in 0..n_rows {
+            for col in 0..n_cols {
+                // Skip cells with minimal height
+                if self.heights[[row, col]] < 0.5 {
+                    continue;
+                }
+
+                // For cells inside AOI, always include them
+                if row >= aoi_min_row
+                    && row <= aoi_max_row
+                    && col >= aoi_min_col
+                    && col <= aoi_max_col
+                {
+                    relevant_cells.push((row, col));
+                    continue;
+                }
+
+                // For cells outside AOI, check if they could cast shadows into AOI
+                if self.can_cast_shadow_into_aoi(
+                    row,
+                    col,
+                    aoi_min_row,
+                    aoi_max_row,
+                    aoi_min_col,
+                    aoi_max_col,
+                    shadow_dir_x,
+                    shadow_dir_y,
+                    max_shadow_distance_pixels,
+                    elevation,
+                ) {
+                    relevant_cells.push((row, col));
+                }
+            }
+        }
+
+        // Optimization stats removed from console output
+        let mut logged = self.optimization_logged.lock().unwrap();
+        if !*logged {
+            // Console output removed as requested
+            *logged = true;
+        }
+
+        relevant_cells
+    }
+
+    fn get_aoi_pixel_bounds(&self) -> Option<(usize, usize, usize, usize)> {
+        let (n_rows, n_cols) = self.heights.dim();
+
+        // Get AOI bounds in world coordinates
+        let coords: Vec<_> = self.aoi_polygon.exterior().coords().collect();
+        let aoi_min_x = coords.iter().map(|c| c.x).fold(f64::INFINITY, f64::min);
+        let aoi_max_x = coords.iter().map(|c| c.x).fold(f64::NEG_INFINITY, f64::max);
+        let aoi_min_y = coords.iter().map(|c| c.y).fold(f64::INFINITY, f64::min);
+        let aoi_max_y = coords.iter().map(|c| c.y).fold(f64::NEG_INFINITY, f64::max);
+
+        // Convert to pixel coordinates
+        let inv_transform = self.invert_transform(&self.transform);
+        let (min_col, max_row) = self.world_to_pixel(aoi_min_x, aoi_min_y, &inv_transform);
+        let (max_col, min_row) = self.world_to_pixel(aoi_max_x, aoi_max_y, &inv_transform);
+
+        // Ensure within raster bounds
+        let min_row = min_row.max(0) as usize;
+        let min_col = min_col.max(0) as usize;
+        let max_row = max_row.min(n_rows as i32 - 1) as usize;
+        let max_col = max_col.min(n_cols as i32 - 1) as usize;
+
+        Some((min_row, max_row, min_col, max_col))
+    }
+
+    fn can_cast_shadow_into_aoi(
+        &self,
+        cell_row: usize,
+        cell_col: usize,
+        aoi_min_row: usize,
+        aoi_max_row: usize,
+        aoi_min_col: usize,
+        aoi_max_col: usize,
+        shadow_dir_x: f64,
+        shadow_dir_y: f64,
+        max_distance: i32,
+        elevation: f64,
+    ) -> bool {
+        // Quick distance check
+        let dist_to_aoi = self.distance_to_aoi_bounds(
+            cell_row as i32,
+            cell_col as i32,
+            aoi_min_row as i32,
+            aoi_max_row as i32,
+            aoi_min_col as i32,
+            aoi_max_col as i32,
+        );
+
+        if dist_to_aoi > max_distance {
+            return false;
+        }
+
+        // Direction check: is the cell on the sun side of the AOI?
+        let aoi_center_row = (aoi_min_row + aoi_max_row) / 2;
+        let aoi_center_col = (aoi_min_col + aoi_max_col) / 2;
+
+        let to_aoi_x = aoi_center_col as f64 - cell_col as f64;
+        let to_aoi_y = aoi_center_row as f64 - cell_row as f64;
+
+        // Dot product to check if shadow direction aligns with direction to AOI
+        let alignment = shadow_dir_x * to_aoi_x + shadow_dir_y * to_aoi_y;
+        if alignment < 0.0 {
+            return false; // Cell is on wrong side to cast shadow
+        }
+
+        // Height check: can this cell's height cast a shadow that far?
+        let cell_height = self.heights[[cell_row, cell_col]] as f64;
+        let required_height = dist_to_aoi as f64 * self.resolution * elevation.to_radians().tan();
+
+        cell_height >= required_height * 0.5 // Allow some margin
+    }
+
+    fn distance_to_aoi_bounds(
+        &self,
+        row: i32,
+        col: i32,
+        min_row: i32,
+        max_row: i32,
+        min_col: i32,
+        max_col: i32,
+    ) -> i32 {
+        let row_dist = if row < min_row {
+            min_row - row
+        } else if row > max_row {
+            row - max_row
+        } else {
+            0
+        };
+
+        let col_dist = if col < min_col {
+            min_col - col
+        } else if col > max_col {
+            col - max_col
+        } else {
+            0
+        };
+
+        ((row_dist * row_dist + col_dist * col_dist) as f64).sqrt() as i32
+    }
+
+    fn invert_transform(&self, transform: &[f64; 6]) -> [f64; 6] {
+        let det = transform[1] * transform[5] - transform[2] * transform[4];
+        [
+            -transform[0] * transform[5] / det + transform[2] * transform[3] / det,
+            transform[5] / det,
+            -transform[2] / det,
+            transform[0] * transform[4] / det - transform[1] * transform[3] / det,
+            -transform[4] / det,
+            transform[1] / det,
+        ]
+    }
+
+    fn world_to_pixel(&self, x: f64, y: f64, inv_transform: &[f64; 6]) -> (i32, i32) {
+        let col = inv_transform[0] + inv_transform[1] * x + inv_transform[2] * y;
+        let row = inv_transform[3] + inv_transform[4] * x + inv_transform[5] * y;
+        (col.round() as i32, row.round() as i32)
+    }
+
+    /// Occluded fraction of the ray toward the sun: 1.0 behind opaque terrain, or the
+    /// light lost in canopy cells, each metre of which keeps `transmissivity` of it
+    fn calculate_cell_shadow(
+        &self,
+        row: usize,
+        col: usize,
+        sun_dir: (f64, f64, f64),
+        transmissivity: f64,
+    ) -> f32 {
+        let cell_height = self.dsm[[row, col]];
+        if self.config.get_ray_traversal() == RayTraversal::GridExact {
+            return self.traverse_grid_shadow(
+                row as f64,
+                col as f64,
+                cell_height as f64,
+                sun_dir,
+                transmissivity,
+            );
+        }
+        let (dx, dy, dz) = sun_dir;
+
+        // Ray marching from cell toward sun
+        let mut current_x = col as f64;
+        let mut current_y = row as f64;
+        let mut current_z = cell_height as f64;
+        let horizontal = (dx * dx + dy * dy).sqrt();
+        let curvature = self.curvature_drop_per_pixel_sq();
+
+        let step_size = 0.5;
+        let max_distance = self.config.buffer_meters.unwrap_or(1000.0) / self.resolution;
+        let step_metres = step_size * horizontal * self.resolution;
+        let mut transmittance = 1.0f64;
+        let mut distance = 0.0;
+
+        while distance < max_distance {
+            current_x += dx * step_size;
+            current_y -= dy * step_size;
+            current_z += dz * step_size;
+            distance += step_size;
+
+            // Check bounds
+            if current_x < 0.0
+                || current_y < 0.0
+                || current_x >= self.dsm.ncols() as f64 - 1.0
+                || current_y >= self.dsm.nrows() as f64 - 1.0
+            {
+                break;
+            }
+
+            // Bilinear interpolation for terrain height
+            let terrain_height = self.interpolate_height(current_y, current_x);
+
+            // Terrain further away sits lower relative to the ray on a curved Earth
+            let horizontal_distance = distance * horizontal;
+            let ray_z = current_z + curvature * horizontal_distance * horizontal_distance;
+
+            // FIX: Cast current_z to f32 for comparison
+            if terrain_height > ray_z as f32 {
+                if !self.is_vegetation(current_y, current_x) {
+                    return 1.0;
+                }
+                transmittance *= transmissivity.powf(step_metres);
+                if transmittance < MIN_TRANSMITTANCE {
+                    return 1.0;
+                }
+            }
+        }
+
+        (1.0 - transmittance) as f32
+    }
+
+    /// Shadow of a cell using its horizon profile: cells whose sun clears the sector's
+    /// horizon are lit, and the rest are confirmed by marching the ray, which usually
+    /// stops at the first occluder. This gives the ray marcher's result at a fraction
+    /// of its cost, since lit cells are the ones whose rays run the full distance.
+    fn horizon_cell_shadow(
+        &self,
+        profile: &HorizonProfile,
+        row: usize,
+        col: usize,
+        (azimuth, elevation): (f64, f64),
+        sun_dir: (f64, f64, f64),
+        transmissivity: f64,
+    ) -> f32 {
+        if profile.sun_clears_horizon(row, col, azimuth, elevation) {
+            0.0
+        } else {
+            self.calculate_cell_shadow(row, col, sun_dir, transmissivity)
+        }
+    }
+
+    /// Horizon profile bounding the configured shadow ray kernel, with rays spaced
+    /// from the shadow rays' reach (see `HorizonProfile::rays_per_sector`)
+    fn compute_horizon_profile(&self) -> Result<HorizonProfile, ShadowError> {
+        let sectors = self.config.get_horizon_sectors();
+        let (n_rows, n_cols) = self.dsm.dim();
+        let max_distance = self.config.buffer_meters.unwrap_or(1000.0) / self.resolution;
+
+        // Horizontal unit direction of every ray (same convention as sun_direction)
+        let rays_per_sector = HorizonProfile::rays_per_sector(sectors, max_distance);
+        let n_rays = sectors * rays_per_sector;
+        let directions: Vec<(f64, f64)> = (0..n_rays)
+            .map(|ray| {
+                let az_rad = (ray as f64 * 360.0 / n_rays as f64).to_radians();
+                (az_rad.sin(), az_rad.cos())
+            })
+            .collect();
+
+        println!(
+            "Precomputing horizon profiles: {} sectors of {} rays for {}x{} cells",
+            sectors, rays_per_sector, n_rows, n_cols
+        );
+
+        // Trace every ray for every cell, filling one row of the profile per parallel
+        // task; each sector keeps the highest of its rays and the next sector's first
+        let mut horizon_tan = Array3::<f32>::zeros((n_rows, n_cols, sectors));
+        horizon_tan
+            .as_slice_mut()
+            .expect("new array is contiguous")
+            .par_chunks_mut(n_cols * sectors)
+            .enumerate()
+            .for_each(|(row, row_profile)| {
+                if self.cancel_token.is_cancelled() {
+                    return;
+                }
+                let mut ray_tans = vec![0f64; n_rays];
+                for (col, cell_profile) in row_profile.chunks_mut(sectors).enumerate() {
+                    let near_tan = self.near_horizon_tan(row, col);
+                    for (tan, &(dx, dy)) in ray_tans.iter_mut().zip(&directions) {
+                        *tan = self.trace_kernel_horizon_tan(row, col, dx, -dy, max_distance);
+                    }
+                    for (sector, value) in cell_profile.iter_mut().enumerate() {
+                        let first = sector * rays_per_sector;
+                        let bound = (first..=first + rays_per_sector)
+                            .map(|ray| ray_tans[ray % n_rays])
+                            .fold(near_tan, f64::max);
+                        // Round up so the stored bound never falls below the traced one
+                        let stored = bound as f32;
+                        *value = if (stored as f64) < bound {
+                            stored.next_up()
+                        } else {
+                            stored
+                        };
+                    }
+                }
+            });
+
+        self.check_cancelled()?;
+
+        Ok(HorizonProfile::new(sectors, horizon_tan))
+    }
+
+    /// Why the horizon profile would not pay off for these sun positions, if it would
+    /// not: it has to fit in memory, and tracing its rays has to cost less than tracing
+    /// a shadow ray per disk sample of every sun position above the horizon.
+    fn horizon_profile_drawback(&self, sun_groups: &[SunPositionGroup]) -> Option<String> {
+        let sectors = self.config.get_horizon_sectors();
+        let (n_rows, n_cols) = self.dsm.dim();
+        let profile_bytes = n_rows * n_cols * sectors * std::mem::size_of::<f32>();
+        if profile_bytes > AUTO_DISK_THRESHOLD_BYTES {
+            return Some(format!(
+                "Horizon profile of {} sectors needs {:.1} GB",
+                sectors,
+                profile_bytes as f64 / 1e9
+            ));
+        }
+
+        let max_distance = self.config.buffer_meters.unwrap_or(1000.0) / self.resolution;
+        let profile_rays = sectors * HorizonProfile::rays_per_sector(sectors, max_distance);
+        let shadow_rays: usize = sun_groups
+            .iter()
+            .filter(|group| !self.sun_below_horizon(group.elevation))
+            .map(|group| {
+                self.solar_disk_samples(group.azimuth, group.elevation)
+                    .len()
+            })
+            .sum();
+        if profile_rays >= shadow_rays {
+            return Some(format!(
+                "Horizon profile needs {} rays per cell, the sun positions {}",
+                profile_rays, shadow_rays
+            ));
+        }
+        None
+    }
+
+    /// Slope and aspect of the configured surface, when any option needs them
+    fn surface_orientation(&self) -> Option<SurfaceOrientation> {
+        if !self.config.get_slope_aspect() && !self.needs_cos_incidence() {
+            return None;
+        }
+        let elevation = match self.config.get_slope_source() {
+            SlopeSource::Dsm => &self.dsm,
+            SlopeSource::Dtm => &self.dtm,
+        };
+        Some(SurfaceOrientation::from_elevation(
+            elevation,
+            self.resolution,
+        ))
+    }
+
+    /// Whether the sun's incidence angle on each cell is needed for a sun position
+    fn needs_cos_incidence(&self) -> bool {
+        self.config.get_self_shading()
+            || (self.config.get_irradiance() && self.config.get_incidence_weighting())
+    }
+
+    /// Beam energy on a surface facing the sun and diffuse energy on an unshaded
+    /// horizontal surface, over all timestamps of a sun position group, in kWh/m².
+    /// Uses the weather data when loaded, the clear-sky model otherwise.
+    fn sky_energy(
+        &self,
+        group: &SunPositionGroup,
+        timestamps: &[chrono::DateTime<chrono::Utc>],
+    ) -> (f64, f64) {
+        let kwh_per_watt = self.config.hour_interval as f64 / 1000.0;
+        group
+            .time_indices
+            .iter()
+            .fold((0.0, 0.0), |(direct, diffuse), &t_idx| {
+                let sky = self.sky_irradiance(&timestamps[t_idx], group.elevation);
+                (
+                    direct + sky.direct_normal * kwh_per_watt,
+                    diffuse + sky.diffuse_horizontal * kwh_per_watt,
+                )
+            })
+    }
+
+    /// Irradiance at a timestamp from the weather data when loaded, the clear-sky model
+    /// otherwise; zero while the sun is below the horizon
+    fn sky_irradiance(
+        &self,
+        timestamp: &chrono::DateTime<chrono::Utc>,
+        elevation: f64,
+    ) -> irradiance::ClearSkyIrradiance {
+        match &self.weather {
+            Some(weather) if elevation > 0.0 => weather.irradiance_at(timestamp),
+            Some(_) => Default::default(),
+            None => irradiance::clear_sky(timestamp, elevation),
+        }
+    }
+
+    /// Hours of a sun position group weighted by the share of clear-sky beam irradiance
+    /// the weather delivers at each timestamp, capped at 1 for clearer-than-model hours
+    fn weather_sun_hours(
+        &self,
+        weather: &WeatherData,
+        group: &SunPositionGroup,
+        timestamps: &[chrono::DateTime<chrono::Utc>],
+    ) -> f64 {
+        group
+            .time_indices
+            .iter()
+            .map(|&t_idx| {
+                let clear = irradiance::clear_sky(&timestamps[t_idx], group.elevation);
+                if clear.direct_normal <= 0.0 {
+                    return 0.0;
+                }
+                let measured = weather.irradiance_at(&timestamps[t_idx]);
+                (measured.direct_normal / clear.direct_normal).clamp(0.0, 1.0)
+            })
+            .sum::<f64>()
+            * self.config.hour_interval as f64
+    }
+
+    /// Sky view factor of every cell from the horizon angle traced in evenly spaced
+    /// azimuths: 1 − mean(sin²γ), the cosine-weighted share of the sky hemisphere a
+    /// horizontal surface sees (1 on open ground, toward 0 at the bottom of a canyon).
+    /// Horizon tangents are traced in metres per pixel like the shadow rays, and
+    /// converted to true angles here using the raster resolution.
+    fn compute_sky_view_factor(&self) -> Result<Array2<f32>, ShadowError> {
+        let n_directions = self.config.get_sky_view_directions();
+        let (n_rows, n_cols) = self.dsm.dim();
+        let max_distance = self.config.buffer_meters.unwrap_or(1000.0) / self.resolution;
+
+        println!(
+            "Computing sky view factor: {} directions for {}x{} cells",
+            n_directions, n_rows, n_cols
+        );
+
+        let directions: Vec<(f64, f64)> = (0..n_directions)
+            .map(|direction| {
+                let az_rad = HorizonProfile::sector_azimuth(direction, n_directions).to_radians();
+                (az_rad.sin(), az_rad.cos())
+            })
+            .collect();
+
+        let rows: Vec<Vec<f32>> = (0..n_rows)
+            .into_par_iter()
+            .map(|row| {
+                let mut values = vec![f32::NAN; n_cols];
+                if self.cancel_token.is_cancelled() {
+                    return values;
+                }
+                for (col, value) in values.iter_mut().enumerate() {
+                    if !self.dsm[[row, col]].is_finite() {
+                        continue;
+                    }
+                    let obstructed: f64 = directions
+                        .iter()
+                        .map(|&(dx, dy)| {
+                            let tan = self.trace_horizon_tan(row, col, dx, dy, max_distance) as f64
+                                / self.resolution;
+                            // sin² of the horizon angle, from its tangent
+                            tan * tan / (1.0 + tan * tan)
+                        })
+                        .sum();
+                    *value = (1.0 - obstructed / n_directions as f64) as f32;
+                }
+                values
+            })
+            .collect();
+
+        self.check_cancelled()?;
+
+        let mut sky_view_factor = Array2::<f32>::zeros((n_rows, n_cols));
+        for (row, values) in rows.into_iter().enumerate() {
+            for (col, value) in values.into_iter().enumerate() {
+                sky_view_factor[[row, col]] = value;
+            }
+        }
+
+        Ok(sky_view_factor)
+    }
+
+    /// Upper bound on the horizon tangent the configured shadow ray kernel can meet
+    /// along a horizontal pixel direction (`dir_y` grows southwards).
+    ///
+    /// The grid traversal tests whole cells where the ray enters them, and the fixed
+    /// step marcher interpolates between the four cell centres around each step, so
+    /// the ray is walked through those squares: cells, or the squares between cell
+    /// centres. Each square contributes its highest height (a bilinear surface never
+    /// exceeds its corners) at its nearest distance, which bounds what the kernel sees
+    /// there for any ray crossing the square, not just this one. The four squares
+    /// around a cell centre are bounded by `near_horizon_tan` instead.
+    fn trace_kernel_horizon_tan(
+        &self,
+        row: usize,
+        col: usize,
+        dir_x: f64,
+        dir_y: f64,
+        max_distance: f64,
+    ) -> f64 {
+        let start_height = self.dsm[[row, col]] as f64;
+        let curvature = self.curvature_drop_per_pixel_sq();
+        let grid_exact = self.config.get_ray_traversal() == RayTraversal::GridExact;
+        let (n_rows, n_cols) = self.dsm.dim();
+
+        // Square (r, c) spans [c, c + 1] x [r, r + 1] in these coordinates
+        let (offset, n_square_rows, n_square_cols) = if grid_exact {
+            (0.5, n_rows, n_cols)
+        } else {
+            (0.0, n_rows.saturating_sub(1), n_cols.saturating_sub(1))
+        };
+        let (x0, y0) = (col as f64 + offset, row as f64 + offset);
+        let reach = max_distance + HORIZON_RAY_OVERREACH;
+
+        let mut square_col = (x0 + dir_x * 1e-9).floor() as isize;
+        let mut square_row = (y0 + dir_y * 1e-9).floor() as isize;
+        let step_col: isize = if dir_x >= 0.0 { 1 } else { -1 };
+        let step_row: isize = if dir_y >= 0.0 { 1 } else { -1 };
+
+        // Ray distance between successive column / row edges, and to the first of each
+        let delta_x = if dir_x.abs() > 1e-12 {
+            1.0 / dir_x.abs()
+        } else {
+            f64::INFINITY
+        };
+        let delta_y = if dir_y.abs() > 1e-12 {
+            1.0 / dir_y.abs()
+        } else {
+            f64::INFINITY
+        };
+        let mut next_x = if delta_x.is_finite() {
+            ((square_col + isize::from(dir_x >= 0.0)) as f64 - x0) / dir_x
+        } else {
+            f64::INFINITY
+        };
+        let mut next_y = if delta_y.is_finite() {
+            ((square_row + isize::from(dir_y >= 0.0)) as f64 - y0) / dir_y
+        } else {
+            f64::INFINITY
+        };
+
+        let mut max_tan = 0.0f64;
+        loop {
+            let entry = if next_x < next_y {
+                square_col += step_col;
+                let entry = next_x;
+                next_x += delta_x;
+                entry
+            } else {
+                square_row += step_row;
+                let entry = next_y;
+                next_y += delta_y;
+                entry
+            };
+
+            if entry > reach
+                || square_col < 0
+                || square_row < 0
+                || square_col >= n_square_cols as isize
+                || square_row >= n_square_rows as isize
+            {
+                break;
+            }
+
+            let (r, c) = (square_row as usize, square_col as usize);
+            let height = if grid_exact {
+                self.dsm[[r, c]]
+            } else {
+                if (row.abs_diff(r) <= 1 && r <= row) && (col.abs_diff(c) <= 1 && c <= col) {
+                    continue; // Touches the cell centre
+                }
+                self.dsm
+                    .slice(s![r..r + 2, c..c + 2])
+                    .iter()
+                    .fold(f32::NEG_INFINITY, |a, &b| a.max(b))
+            };
+
+            let gap_x = (c as f64 - x0).max(x0 - (c + 1) as f64).max(0.0);
+            let gap_y = (r as f64 - y0).max(y0 - (r + 1) as f64).max(0.0);
+            let nearest = (gap_x * gap_x + gap_y * gap_y).sqrt();
+            let tan = (height as f64 - start_height - curvature * nearest * nearest) / nearest;
+            max_tan = max_tan.max(tan);
+        }
+
+        max_tan
+    }
+
+    /// Upper bound on the horizon tangent the fixed step marcher can meet in the four
+    /// squares between cell centres around a cell, in any direction; 0 for the grid
+    /// traversal, which starts testing at the neighbouring cells. Inside a square with
+    /// one corner at the cell, the bilinear height rises by at most the sum of the
+    /// other corners' rises times (x + y) ≤ √2 times the distance.
+    fn near_horizon_tan(&self, row: usize, col: usize) -> f64 {
+        if self.config.get_ray_traversal() == RayTraversal::GridExact {
+            return 0.0;
+        }
+        let (n_rows, n_cols) = self.dsm.dim();
+        if n_rows < 2 || n_cols < 2 {
+            return 0.0;
+        }
+        let start_height = self.dsm[[row, col]] as f64;
+        let mut max_tan = 0.0f64;
+        for r in row.saturating_sub(1)..=row.min(n_rows.saturating_sub(2)) {
+            for c in col.saturating_sub(1)..=col.min(n_cols.saturating_sub(2)) {
+                let rise: f64 = self
+                    .dsm
+                    .slice(s![r..r + 2, c..c + 2])
+                    .iter()
+                    .map(|&height| (height as f64 - start_height).max(0.0))
+                    .sum();
+                max_tan = max_tan.max(std::f64::consts::SQRT_2 * rise);
+            }
+        }
+        max_tan
+    }
+
+    fn trace_horizon_tan(
+        &self,
+        row: usize,
+        col: usize,
+        dx: f64,
+        dy: f64,
+        max_distance: f64,
+    ) -> f32 {
+        let cell_height = self.dsm[[row, col]] as f64;
+
+        let mut current_x = col as f64;
+        let mut current_y = row as f64;
+
+        let step_size = 0.5;
+        let curvature = self.curvature_drop_per_pixel_sq();
+        let mut distance = 0.0;
+        let mut max_tan = 0.0f64;
+
+        while distance < max_distance {
+            current_x += dx * step_size;
+            current_y -= dy * step_size;
+            distance += step_size;
+
+            if current_x < 0.0
+                || current_y < 0.0
+                || current_x >= self.dsm.ncols() as f64 - 1.0
+                || current_y >= self.dsm.nrows() as f64 - 1.0
+            {
+                break;
+            }
+
+            // Rise in metres per pixel of horizontal travel, as in calculate_cell_shadow
+            let terrain_height = self.interpolate_height(current_y, current_x) as f64
+                - curvature * distance * distance;
+            max_tan = max_tan.max((terrain_height - cell_height) / distance);
+        }
+
+        max_tan as f32
+    }
+
+    fn refine_shadow_edges(
+        &self,
+        shadow_map: Array2<f32>,
+        sun_dir: (f64, f64, f64),
+        transmissivity: f64,
+    ) -> Array2<f32> {
+        let (n_rows, n_cols) = shadow_map.dim();
+        let mut refined = shadow_map.clone();
+
+        // Create a vector of edge cell coordinates for parallel processing
+        let edge_coords: Vec<(usize, usize)> = (1..n_rows - 1)
+            .flat_map(|row| (1..n_cols - 1).map(move |col| (row, col)))
+            .filter(|&(row, col)| self.is_shadow_edge(&shadow_map, row, col))
+            .collect();
+
+        let sub_samples = match self.config.shadow_quality {
+            ShadowQuality::Normal => 2,
+            ShadowQuality::High => 4,
+            ShadowQuality::Scientific => 8,
+            _ => 1,
+        };
+
+        // Process edge cells in parallel
+        let refined_values: Vec<f32> = edge_coords
+            .par_iter()
+            .map(|&(row, col)| {
+                if self.cancel_token.is_cancelled() {
+                    return 0.0;
+                }
+                self.subsample_cell(row, col, sun_dir, sub_samples, transmissivity)
+            })
+            .collect();
+
+        // Assign refined values back to the shadow map
+        for ((row, col), &refined_value) in edge_coords.iter().zip(refined_values.iter()) {
+            refined[[*row, *col]] = refined_value;
+        }
+
+        refined
+    }
+
+    fn is_shadow_edge(&self, shadow_map: &Array2<f32>, row: usize, col: usize) -> bool {
+        let center = shadow_map[[row, col]];
+        for dr in -1..=1 {
+            for dc in -1..=1 {
+                if dr == 0 && dc == 0 {
+                    continue;
+                }
+                let r = (row as i32 + dr) as usize;
+                let c = (col as i32 + dc) as usize;
+                if (shadow_map[[r, c]] - center).abs() > 0.5 {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    fn subsample_cell(
+        &self,
+        row: usize,
+        col: usize,
+        sun_dir: (f64, f64, f64),
+        samples: usize,
+        transmissivity: f64,
+    ) -> f32 {
+        let mut shadow_sum = 0.0;
+        let step = 1.0 / samples as f64;
+
+        for i in 0..samples {
+            for j in 0..samples {
+                let sub_row = row as f64 + (i as f64 + 0.5) * step;
+                let sub_col = col as f64 + (j as f64 + 0.5) * step;
+
+                shadow_sum +=
+                    self.calculate_subpixel_shadow(sub_row, sub_col, sun_dir, transmissivity);
+            }
+        }
+
+        shadow_sum / (samples * samples) as f32
+    }
+
+    fn calculate_subpixel_shadow(
+        &self,
+        row: f64,
+        col: f64,
+        sun_dir: (f64, f64, f64),
+        transmissivity: f64,
+    ) -> f32 {
+        let cell_height = self.interpolate_height(row, col);
+        if self.config.get_ray_traversal() == RayTraversal::GridExact {
+            return self.traverse_grid_shadow(
+                row,
+                col,
+                cell_height as f64,
+                sun_dir,
+                transmissivity,
+            );
+        }
+        let (dx, dy, dz) = sun_dir;
+
+        let mut current_x = col;
+        let mut current_y = row;
+        let mut current_z = cell_height as f64;
+        let horizontal = (dx * dx + dy * dy).sqrt();
+        let curvature = self.curvature_drop_per_pixel_sq();
+
+        let step_size = 0.25;
+        let max_distance = self.config.buffer_meters.unwrap_or(1000.0) / self.resolution;
+        let step_metres = step_size * horizontal * self.resolution;
+        let mut transmittance = 1.0f64;
+        let mut distance = 0.0;
+
+        while distance < max_distance {
+            current_x += dx * step_size;
+            current_y -= dy * step_size;
+            current_z += dz * step_size;
+            distance += step_size;
+
+            if current_x < 0.0
+                || current_y < 0.0
+                || current_x >= self.dsm.ncols() as f64 - 1.0
+                || current_y >= self.dsm.nrows() as f64 - 1.0
+            {
+                break;
+            }
+
+            let terrain_height = self.interpolate_height(current_y, current_x);
+
+            // Terrain further away sits lower relative to the ray on a curved Earth
+            let horizontal_distance = distance * horizontal;
+            let ray_z = current_z + curvature * horizontal_distance * horizontal_distance;
+
+            // FIX: Cast current_z to f32 for comparison
+            if terrain_height > ray_z as f32 {
+                if !self.is_vegetation(current_y, current_x) {
+                    return 1.0;
+                }
+                transmittance *= transmissivity.powf(step_metres);
+                if transmittance < MIN_TRANSMITTANCE {
+                    return 1.0;
+                }
+            }
+        }
+
+        (1.0 - transmittance) as f32
+    }
+
+    /// Exact grid traversal (DDA) from a point toward the sun.
+    ///
+    /// DSM cells are treated as flat-topped columns centred on their pixel
+    /// coordinates. The ray only rises while it crosses a cell, so it is lowest where
+    /// it enters; testing the cell height at each edge crossing catches occluders of
+    /// any width, including one-pixel walls and poles that fixed steps can jump over.
+    fn traverse_grid_shadow(
+        &self,
+        row: f64,
+        col: f64,
+        start_height: f64,
+        sun_dir: (f64, f64, f64),
+        transmissivity: f64,
+    ) -> f32 {
+        let (dx, dy, dz) = sun_dir;
+        let horizontal = (dx * dx + dy * dy).sqrt();
+        if horizontal < 1e-9 {
+            return 0.0; // Sun at the zenith casts no shadow
+        }
+
+        // Horizontal unit direction in pixel space (rows grow southwards)
+        let dir_x = dx / horizontal;
+        let dir_y = -dy / horizontal;
+        let rise_per_pixel = dz / horizontal;
+        let max_distance = self.config.buffer_meters.unwrap_or(1000.0) / self.resolution;
+        let curvature = self.curvature_drop_per_pixel_sq();
+        let (n_rows, n_cols) = self.dsm.dim();
+        let mut transmittance = 1.0f64;
+
+        let mut cell_col = col.round() as isize;
+        let mut cell_row = row.round() as isize;
+        let step_col: isize = if dir_x >= 0.0 { 1 } else { -1 };
+        let step_row: isize = if dir_y >= 0.0 { 1 } else { -1 };
+
+        // Ray distance between successive column / row edges, and to the first of each
+        let delta_x = if dir_x.abs() > 1e-12 {
+            1.0 / dir_x.abs()
+        } else {
+            f64::INFINITY
+        };
+        let delta_y = if dir_y.abs() > 1e-12 {
+            1.0 / dir_y.abs()
+        } else {
+            f64::INFINITY
+        };
+        let mut next_x = if delta_x.is_finite() {
+            ((cell_col as f64 + 0.5 * step_col as f64) - col) / dir_x
+        } else {
+            f64::INFINITY
+        };
+        let mut next_y = if delta_y.is_finite() {
+            ((cell_row as f64 + 0.5 * step_row as f64) - row) / dir_y
+        } else {
+            f64::INFINITY
+        };
+
+        loop {
+            // Step into whichever neighbouring cell the ray reaches first
+            let distance = if next_x < next_y {
+                cell_col += step_col;
+                let distance = next_x;
+                next_x += delta_x;
+                distance
+            } else {
+                cell_row += step_row;
+                let distance = next_y;
+                next_y += delta_y;
+                distance
+            };
+
+            if distance > max_distance
+                || cell_col < 0
+                || cell_row < 0
+                || cell_col >= n_cols as isize
+                || cell_row >= n_rows as isize
+            {
+                break;
+            }
+
+            let ray_height =
+                start_height + distance * rise_per_pixel + curvature * distance * distance;
+            let (r, c) = (cell_row as usize, cell_col as usize);
+            if self.dsm[[r, c]] as f64 > ray_height {
+                if !self.vegetation.as_ref().is_some_and(|mask| mask[[r, c]]) {
+                    return 1.0;
+                }
+                // Attenuate over the ray's path through this cell
+                let path_metres = (next_x.min(next_y) - distance) * self.resolution;
+                transmittance *= transmissivity.powf(path_metres);
+                if transmittance < MIN_TRANSMITTANCE {
+                    return 1.0;
+                }
+            }
+        }
+
+        (1.0 - transmittance) as f32
+    }
+
+    /// Apparent drop of distant terrain below the local horizontal plane, in metres
+    /// per squared pixel of horizontal distance, or 0 when the correction is off.
+    ///
+    /// Terrain `d` metres away sits `d² / 2R` lower on a sphere; refraction bends the
+    /// line of sight along an arc of radius `R / k`, which offsets part of that drop.
+    fn curvature_drop_per_pixel_sq(&self) -> f64 {
+        if !self.config.get_curvature_refraction() {
+            return 0.0;
+        }
+        (1.0 - REFRACTION_COEFFICIENT) * self.resolution * self.resolution / (2.0 * EARTH_RADIUS_M)
+    }
+
+    fn interpolate_height(&self, row: f64, col: f64) -> f32 {
+        let r0 = row.floor() as usize;
+        let c0 = col.floor() as usize;
+        let r1 = (r0 + 1).min(self.dsm.nrows() - 1);
+        let c1 = (c0 + 1).min(self.dsm.ncols() - 1);
+
+        let fx = col - c0 as f64;
+        let fy = row - r0 as f64;
+
+        let h00 = self.dsm[[r0, c0]] as f64;
+        let h01 = self.dsm[[r0, c1]] as f64;
+        let h10 = self.dsm[[r1, c0]] as f64;
+        let h11 = self.dsm[[r1, c1]] as f64;
+
+        let h0 = h00 * (1.0 - fx) + h01 * fx;
+        let h1 = h10 * (1.0 - fx) + h11 * fx;
+
+        (h0 * (1.0 - fy) + h1 * fy) as f32
+    }
+
+    /// (azimuth, elevation) sample points spread evenly over the solar disk on a
+    /// sunflower spiral, or only the disk centre when penumbra is off
+    fn solar_disk_samples(&self, azimuth: f64, elevation: f64) -> Vec<(f64, f64)> {
+        let n_samples = self.config.get_solar_disk_samples();
+        if n_samples <= 1 {
+            return vec![(azimuth, elevation)];
+        }
+
+        let golden_angle = std::f64::consts::PI * (3.0 - 5f64.sqrt());
+        // A degree of azimuth spans less sky as the sun rises, so widen azimuth offsets
+        let azimuth_scale = 1.0 / elevation.to_radians().cos().max(0.05);
+
+        (0..n_samples)
+            .map(|i| {
+                let radius =
+                    SOLAR_ANGULAR_RADIUS_DEG * ((i as f64 + 0.5) / n_samples as f64).sqrt();
+                let angle = i as f64 * golden_angle;
+                (
+                    azimuth + radius * angle.cos() * azimuth_scale,
+                    elevation + radius * angle.sin(),
+                )
+            })
+            .collect()
+    }
+
+    fn sun_direction(&self, azimuth: f64, elevation: f64) -> (f64, f64, f64) {
+        let az_rad = azimuth.to_radians();
+        let el_rad = elevation.to_radians();
+
+        let dx = az_rad.sin() * el_rad.cos();
+        let dy = az_rad.cos() * el_rad.cos();
+        let dz = el_rad.sin();
+
+        (dx, dy, dz)
+    }
+
+    fn generate_timestamps(&self) -> Vec<chrono::DateTime<chrono::Utc>> {
+        let mut timestamps = Vec::new();
+
+        let start = chrono::DateTime::parse_from_rfc3339(&self.config.start_date)
+            .unwrap_or_else(|_| chrono::Utc::now().into())
+            .with_timezone(&chrono::Utc);
+        let end = chrono::DateTime::parse_from_rfc3339(&self.config.end_date)
+            .unwrap_or_else(|_| (chrono::Utc::now() + chrono::Duration::days(30)).into())
+            .with_timezone(&chrono::Utc);
+
+        // Generate solar-aware timestamps: only during daylight hours for each day
+        let mut current_date = start.date_naive();
+        let end_date = end.date_naive();
+
+        let interval_duration =
+            chrono::Duration::minutes((self.config.hour_interval * 60.0) as i64);
+
+        while current_date <= end_date {
+            let current_datetime = current_date.and_hms_opt(12, 0, 0).unwrap().and_utc();
+
+            // Get sunrise and sunset for this day using the AOI center coordinates
+            let aoi_center = self.get_aoi_center();
+            let sun_calc = crate::sun_position::SunCalculator::new_with_refraction(
+                aoi_center.1, // latitude
+                aoi_center.0, // longitude
+                self.config.angle_precision,
+                self.config.get_curvature_refraction(),
+            );
+
+            if let Some((sunrise, sunset)) = sun_calc.calculate_sunrise_sunset(&current_datetime) {
+                // Generate timestamps at specified intervals between sunrise and sunset
+                let mut daylight_time = sunrise;
+
+                // Ensure we start within the requested date range
+                if daylight_time < start {
+                    daylight_time = start;
+                }
+
+                while daylight_time <= sunset && daylight_time <= end {
+                    timestamps.push(daylight_time);
+                    daylight_time += interval_duration;
+                }
+            } else {
+                // Handle polar conditions - if solar noon has positive elevation, include some timestamps
+                let (_, noon_elevation) = sun_calc.get_exact_position(&current_datetime);
+                if noon_elevation > 0.0 {
+                    // Polar summer: sun never sets, use traditional approach for this day
+                    let day_start = current_date
+                        .and_hms_opt(0, 0, 0)
+                        .unwrap()
+                        .and_utc()
+                        .max(start);
+                    let day_end = current_date
+                        .and_hms_opt(23, 59, 59)
+                        .unwrap()
+                        .and_utc()
+                        .min(end);
+
+                    let mut day_time = day_start;
+                    while day_time <= day_end {
+                        timestamps.push(day_time);
+                        day_time += interval_duration;
+                    }
+                }
+                // Polar winter: sun never rises, skip this day entirely
+            }
+
+            current_date += chrono::Duration::days(1);
+        }
+
+        timestamps
+    }
+
+    fn get_aoi_center(&self) -> (f64, f64) {
+        if let Ok(polygon) = self.config.to_polygon() {
+            let coords: Vec<_> = polygon.exterior().coords().collect();
+            if !coords.is_empty() {
+                let sum_x: f64 = coords.iter().map(|c| c.x).sum();
+                let sum_y: f64 = coords.iter().map(|c| c.y).sum();
+                (sum_x / coords.len() as f64, sum_y / coords.len() as f64)
+            } else {
+                (0.0, 0.0) // Fallback
+            }
+        } else {
+            (0.0, 0.0) // Fallback
+        }
+    }
+
+    fn calculate_summary_stats(
+        &self,
+        shadow_fraction: &ShadowStore,
+        timestamps: &[chrono::DateTime<chrono::Utc>],
+    ) -> Result<SummaryStats, ShadowError> {
+        let (_n_times, n_rows, n_cols) = shadow_fraction.dim();
+
+        // Pre-calculate solar data for all days in the analysis period
+        let aoi_center = self.get_aoi_center();
+        let sun_calc = crate::sun_position::SunCalculator::new_with_refraction(
+            aoi_center.1, // latitude
+            aoi_center.0, // longitude
+            self.config.angle_precision,
+            self.config.get_curvature_refraction(),
+        );
+
+        // Group timestamps by date and calculate solar hours per day
+        let mut daily_solar_hours = std::collections::HashMap::new();
+        let mut solar_noon_times = std::collections::HashMap::new();
+
+        for timestamp in timestamps {
+            let date = timestamp.date_naive();
+            if !daily_solar_hours.contains_key(&date) {
+                let solar_hours = sun_calc.get_solar_hours_for_day(timestamp);
+                let solar_noon = sun_calc.calculate_solar_noon(timestamp);
+                daily_solar_hours.insert(date, solar_hours);
+                solar_noon_times.insert(date, solar_noon);
+            }
+        }
+
+        let total_analysis_days = daily_solar_hours.len() as f32;
+        let total_available_solar: f32 = daily_solar_hours.values().map(|&x| x as f32).sum();
+        let avg_daily_solar = if total_analysis_days > 0.0 {
+            total_available_solar / total_analysis_days
+        } else {
+            0.0
+        };
+
+        println!("Solar calculation debug:");
+        println!("  Total analysis days: {}", total_analysis_days);
+        println!("  Total available solar hours: {}", total_available_solar);
+        println!("  Average daily solar hours: {}", avg_daily_solar);
+        println!("  Number of timestamps: {}", timestamps.len());
+
+        // Create arrays for new statistics
+        let mut total_shadow_hours = Array2::<f32>::zeros((n_rows, n_cols));
+        let mut morning_shadow_hours = Array2::<f32>::zeros((n_rows, n_cols));
+        let mut noon_shadow_hours = Array2::<f32>::zeros((n_rows, n_cols));
+        let mut afternoon_shadow_hours = Array2::<f32>::zeros((n_rows, n_cols));
+        let mut max_consecutive = Array2::<f32>::zeros((n_rows, n_cols));
+        let mut solar_efficiency = Array2::<f32>::zeros((n_rows, n_cols));
+
+        // Read the time series in row blocks so disk-backed results never need to fit in memory
+        let rows_per_block = shadow_fraction.rows_per_block(STATS_BLOCK_BYTES);
+        let mut row_start = 0;
+
+        while row_start < n_rows {
+            self.check_cancelled()?;
+
+            let row_end = (row_start + rows_per_block).min(n_rows);
+            let block = shadow_fraction.read_row_block(row_start, row_end)?;
+
+            // Create a vector of the block's cell coordinates for parallel processing
+            let cell_coords: Vec<(usize, usize)> = (row_start..row_end)
+                .flat_map(|row| (0..n_cols).map(move |col| (row, col)))
+                .collect();
+
+            // Calculate statistics for all cells in the block in parallel
+            let stats_results: Vec<(f32, f32, f32, f32, f32, f32)> = cell_coords
+                .par_iter()
+                .map(|&(row, col)| {
+                    let cell_series = block.slice(s![.., row - row_start, col]);
+
+                    // Calculate total shadow hours and morning/noon/afternoon split
+                    let mut total_shadow_hours_cell = 0.0;
+                    let mut morning_shadow_hours_cell = 0.0;
+                    let mut noon_shadow_hours_cell = 0.0;
+                    let mut afternoon_shadow_hours_cell = 0.0;
+
+                    // Calculate shadow hours for each timestamp (simple approach)
+                    for (t_idx, &timestamp) in timestamps.iter().enumerate() {
+                        let shadow_contribution = cell_series[t_idx] * self.config.hour_interval;
+                        total_shadow_hours_cell += shadow_contribution;
+
+                        // Morning/noon/afternoon classification using solar noon ± 2 hours
+                        let date = timestamp.date_naive();
+                        if let Some(&solar_noon) = solar_noon_times.get(&date) {
+                            let noon_start = solar_noon - chrono::Duration::hours(2);
+                            let noon_end = solar_noon + chrono::Duration::hours(2);
+
+                            if timestamp < noon_start {
+                                morning_shadow_hours_cell += shadow_contribution;
+                            } else if timestamp <= noon_end {
+                                noon_shadow_hours_cell += shadow_contribution;
+                            } else {
+                                afternoon_shadow_hours_cell += shadow_contribution;
+                            }
+                        } else {
+                            // Fallback to clock-based classification if solar noon calculation fails
+                            let hour = timestamp.hour();
+                            if hour < 10 {
+                                // Before 10 AM
+                                morning_shadow_hours_cell += shadow_contribution;
+                            } else if hour < 14 {
+                                // 10 AM - 2 PM (noon period)
+                                noon_shadow_hours_cell += shadow_contribution;
+                            } else {
+                                // After 2 PM
+                                afternoon_shadow_hours_cell += shadow_contribution;
+                            }
+                        }
+                    }
+
+                    // Max consecutive shadow hours (using actual time intervals)
+                    let mut current_consecutive = 0.0;
+                    let mut max_consec = 0.0f32;
+                    for (_t_idx, &val) in cell_series.iter().enumerate() {
+                        if val > 0.5 {
+                            current_consecutive += self.config.hour_interval;
+                            max_consec = max_consec.max(current_consecutive);
+                        } else {
+                            current_consecutive = 0.0;
+                        }
+                    }
+
+                    // Solar efficiency: fraction of total available solar hours that are not shadowed (0.0-1.0)
+                    let efficiency = if total_available_solar > 0.0 {
+                        ((total_available_solar - total_shadow_hours_cell) / total_available_solar)
+                            .max(0.0)
+                    } else {
+                        0.0
+                    };
+
+                    (
+                        total_shadow_hours_cell,
+                        morning_shadow_hours_cell,
+                        noon_shadow_hours_cell,
+                        afternoon_shadow_hours_cell,
+                        max_consec,
+                        efficiency,
+                    )
+                })
+                .collect();
+
+            // Assign results back to arrays
+            for ((row, col), &(total, morning, noon, afternoon, max_consec, efficiency)) in
+                cell_coords.iter().zip(stats_results.iter())
+            {
+                total_shadow_hours[[*row, *col]] = total;
+                morning_shadow_hours[[*row, *col]] = morning;
+                noon_shadow_hours[[*row, *col]] = noon;
+                afternoon_shadow_hours[[*row, *col]] = afternoon;
+                max_consecutive[[*row, *col]] = max_consec;
+                solar_efficiency[[*row, *col]] = efficiency;
+            }
+
+            row_start = row_end;
+        }
+
+        // Calculate average shadow percentage as fraction (0.0-1.0, not 0-100)
+        // Total measurement time = number of timestamps * hour interval
+        let total_measurement_hours = timestamps.len() as f32 * self.config.hour_interval;
+        println!("  Total measurement hours: {}", total_measurement_hours);
+
+        let avg_shadow_percentage = if total_measurement_hours > 0.0 {
+            &total_shadow_hours / total_measurement_hours
+        } else {
+            Array2::<f32>::zeros((n_rows, n_cols))
+        };
+
+        // Convert to Array3 with summary layers
+        let mut total_3d = Array3::<f32>::zeros((1, n_rows, n_cols));
+        let mut avg_3d = Array3::<f32>::zeros((1, n_rows, n_cols));
+        let mut max_3d = Array3::<f32>::zeros((1, n_rows, n_cols));
+        let mut morning_3d = Array3::<f32>::zeros((1, n_rows, n_cols));
+        let mut noon_3d = Array3::<f32>::zeros((1, n_rows, n_cols));
+        let mut afternoon_3d = Array3::<f32>::zeros((1, n_rows, n_cols));
+        let mut efficiency_3d = Array3::<f32>::zeros((1, n_rows, n_cols));
+        let mut daily_solar_3d = Array3::<f32>::zeros((1, n_rows, n_cols));
+        let mut total_available_3d = Array3::<f32>::zeros((1, n_rows, n_cols));
+
+        total_3d
+            .slice_mut(s![0, .., ..])
+            .assign(&total_shadow_hours);
+        avg_3d
+            .slice_mut(s![0, .., ..])
+            .assign(&avg_shadow_percentage);
+        max_3d.slice_mut(s![0, .., ..]).assign(&max_consecutive);
+        morning_3d
+            .slice_mut(s![0, .., ..])
+            .assign(&morning_shadow_hours);
+        noon_3d.slice_mut(s![0, .., ..]).assign(&noon_shadow_hours);
+        afternoon_3d
+            .slice_mut(s![0, .., ..])
+            .assign(&afternoon_shadow_hours);
+        efficiency_3d
+            .slice_mut(s![0, .., ..])
+            .assign(&solar_efficiency);
+        // Each cell gets the average daily solar hours for the analysis period
+        daily_solar_3d.fill(avg_daily_solar);
+        // Each cell gets total available solar hours for the entire analysis period
+        total_available_3d.fill(total_available_solar);
+
+        Ok(SummaryStats {
+            total_shadow_hours: total_3d,
+            avg_shadow_percentage: avg_3d,
+            max_consecutive_shadow: max_3d,
+            morning_shadow_hours: morning_3d,
+            noon_shadow_hours: noon_3d,
+            afternoon_shadow_hours: afternoon_3d,
+            solar_efficiency_percentage: efficiency_3d,
+            daily_solar_hours: daily_solar_3d,
+            total_available_solar_hours: total_available_3d,
+            sky_view_factor: None,
+            direct_irradiation: None,
+            diffuse_irradiation: None,
+            global_irradiation: None,
+            slope: None,
+            aspect: None,
+            self_shaded_hours: None,
+            effective_sun_hours: None,
+        })
+    }
+
+    pub fn calculate_seasonal_analysis(
+        &self,
+        shadow_fraction: &Array3<f32>,
+        timestamps: &[chrono::DateTime<chrono::Utc>],
+    ) -> SeasonalAnalysis {
+        use crate::types::{MonthlyShadowStats, SeasonStats, SeasonalAnalysis};
+        use chrono::Datelike;
+        use std::collections::HashMap;
+
+        let (n_times, n_rows, n_cols) = shadow_fraction.dim();
+
+        // Group timestamps by month-year
+        let mut monthly_groups: HashMap<(u32, i32), Vec<usize>> = HashMap::new();
+        for (idx, timestamp) in timestamps.iter().enumerate() {
+            let month = timestamp.month();
+            let year = timestamp.year();
+            monthly_groups.entry((month, year)).or_default().push(idx);
+        }
+
+        // Calculate monthly statistics
+        let mut monthly_stats = Vec::new();
+        for ((month, year), time_indices) in monthly_groups.iter() {
+            let days_in_analysis = time_indices.len() as f32 / 24.0; // Approximate days
+
+            let mut month_shadow_hours = Array2::<f32>::zeros((n_rows, n_cols));
+            let mut month_shadow_count = Array2::<f32>::zeros((n_rows, n_cols));
+            let mut month_max_consecutive = Array2::<f32>::zeros((n_rows, n_cols));
+            let mut month_solar_efficiency = Array2::<f32>::zeros((n_rows, n_cols));
+
+            // Calculate statistics for each cell
+            for row in 0..n_rows {
+                for col in 0..n_cols {
+                    let mut total_shadow = 0.0f32;
+                    let mut consecutive_shadow = 0.0f32;
+                    let mut max_consecutive = 0.0f32;
+                    let mut sunlit_hours = 0.0f32;
+
+                    for &time_idx in time_indices {
+                        let shadow_val = shadow_fraction[[time_idx, row, col]];
+
+                        if shadow_val > 0.5 {
+                            total_shadow += 1.0;
+                            consecutive_shadow += 1.0;
+                        } else {
+                            sunlit_hours += 1.0;
+                            max_consecutive = max_consecutive.max(consecutive_shadow);
+                            consecutive_shadow = 0.0;
+                        }
+                    }
+
+                    // Final check for consecutive shadows
+                    max_consecutive = max_consecutive.max(consecutive_shadow);
+
+                    month_shadow_hours[[row, col]] = total_shadow;
+                    month_shadow_count[[row, col]] = time_indices.len() as f32;
+                    month_max_consecutive[[row, col]] = max_consecutive;
+
+                    // Solar efficiency: percentage of time with good solar access
+                    if time_indices.len() > 0 {
+                        month_solar_efficiency[[row, col]] =
+                            (sunlit_hours / time_indices.len() as f32) * 100.0;
+                    }
+                }
+            }
+
+            // Calculate average shadow percentage
+            let mut avg_shadow_percentage = Array2::<f32>::zeros((n_rows, n_cols));
+            for row in 0..n_rows {
+                for col in 0..n_cols {
+                    if month_shadow_count[[row, col]] > 0.0 {
+                        avg_shadow_percentage[[row, col]] = (month_shadow_hours[[row, col]]
+                            / month_shadow_count[[row, col]])
+                            * 100.0;
+                    }
+                }
+            }
+
+            monthly_stats.push(MonthlyShadowStats {
+                month: *month,
+                year: *year,
+                total_shadow_hours: month_shadow_hours,
+                avg_shadow_percentage,
+                max_consecutive_shadow: month_max_consecutive,
+                solar_efficiency_percentage: month_solar_efficiency,
+                days_in_analysis: days_in_analysis as u32,
+            });
+        }
+
+        // Sort monthly stats by year and month
+        monthly_stats.sort_by(|a, b| (a.year, a.month).cmp(&(b.year, b.month)));
+
+        // Calculate seasonal summaries
+        let seasons = vec![
+            ("Spring", vec![3, 4, 5]),
+            ("Summer", vec![6, 7, 8]),
+            ("Fall", vec![9, 10, 11]),
+            ("Winter", vec![12, 1, 2]),
+        ];
+
+        let mut seasonal_summaries = Vec::new();
+        for (season_name, season_months) in seasons {
+            let season_months_set: std::collections::HashSet<u32> =
+                season_months.iter().cloned().collect();
+
+            // Find matching monthly stats for this season
+            let season_stats: Vec<&MonthlyShadowStats> = monthly_stats
+                .iter()
+                .filter(|ms| season_months_set.contains(&ms.month))
+                .collect();
+
+            if !season_stats.is_empty() {
+                // Aggregate seasonal data
+                let mut season_shadow_hours = Array2::<f32>::zeros((n_rows, n_cols));
+                let mut season_shadow_percentage = Array2::<f32>::zeros((n_rows, n_cols));
+                let mut season_max_consecutive = Array2::<f32>::zeros((n_rows, n_cols));
+                let mut season_solar_efficiency = Array2::<f32>::zeros((n_rows, n_cols));
+                let mut total_days = 0;
+
+                for monthly_stat in &season_stats {
+                    season_shadow_hours = &season_shadow_hours + &monthly_stat.total_shadow_hours;
+                    season_shadow_percentage =
+                        &season_shadow_percentage + &monthly_stat.avg_shadow_percentage;
+                    season_solar_efficiency =
+                        &season_solar_efficiency + &monthly_stat.solar_efficiency_percentage;
+                    total_days += monthly_stat.days_in_analysis;
+
+                    // Max consecutive is the maximum across months
+                    for row in 0..n_rows {
+                        for col in 0..n_cols {
+                            season_max_consecutive[[row, col]] = season_max_consecutive[[row, col]]
+                                .max(monthly_stat.max_consecutive_shadow[[row, col]]);
+                        }
+                    }
+                }
+
+                // Average the percentage values
+                let season_count = season_stats.len() as f32;
+                season_shadow_percentage.mapv_inplace(|x| x / season_count);
+                season_solar_efficiency.mapv_inplace(|x| x / season_count);
+
+                seasonal_summaries.push(SeasonStats {
+                    season_name: season_name.to_string(),
+                    months: season_months,
+                    total_shadow_hours: season_shadow_hours,
+                    avg_shadow_percentage: season_shadow_percentage,
+                    max_consecutive_shadow: season_max_consecutive,
+                    solar_efficiency_percentage: season_solar_efficiency,
+                    total_days,
+                });
+            }
+        }
+
+        // Determine analysis period
+        let start_time = timestamps.iter().min().cloned().unwrap_or_default();
+        let end_time = timestamps.iter().max().cloned().unwrap_or_default();
+
+        SeasonalAnalysis {
+            monthly_stats,
+            seasonal_summaries,
+            analysis_period: (start_time, end_time),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Engine on a flat DTM at 1 m resolution in Fast quality with fixed-step rays,
+    /// the reference the other kernels are compared against. `overrides` replaces
+    /// fields of the default config.
+    fn test_engine(dsm: Array2<f32>, overrides: serde_json::Value) -> ShadowEngine {
+        let (n_rows, n_cols) = dsm.dim();
+        let (width, height) = (n_cols as f64, n_rows as f64);
+        let mut config = serde_json::json!({
+            "dtm_path": "",
+            "dsm_path": "",
+            "aoi": [[0.0, 0.0], [width, 0.0], [width, height], [0.0, height], [0.0, 0.0]],
+            "start_date": "2024-06-21T00:00:00Z",
+            "end_date": "2024-06-22T00:00:00Z",
+            "hour_interval": 1.0,
+            "buffer_meters": width.max(height) * 2.0,
+            "angle_precision": 0.1,
+            "shadow_quality": "Fast",
+            "ray_traversal": "FixedStep",
+        });
+        if let (Some(config), Some(overrides)) = (config.as_object_mut(), overrides.as_object()) {
+            config.extend(overrides.clone());
+        }
+        let config: Config = serde_json::from_value(config).unwrap();
+        let dtm = Array2::<f32>::zeros(dsm.dim());
+        ShadowEngine::new(dtm, dsm, 1.0, [0.0, 1.0, 0.0, height, 0.0, -1.0], config)
+    }
+
+    /// Shadow of every cell from the per-cell ray marcher
+    fn ray_marched(engine: &ShadowEngine, azimuth: f64, elevation: f64) -> Array2<f32> {
+        let sun_dir = engine.sun_direction(azimuth, elevation);
+        Array2::from_shape_fn(engine.dsm.dim(), |(row, col)| {
+            engine.calculate_cell_shadow(row, col, sun_dir, 1.0)
+        })
+    }
+
+    /// Cells shadowed by the ray marcher but lit in `other`, and the reverse
+    fn disagreements(reference: &Array2<f32>, other: &Array2<f32>) -> (Vec<(usize, usize)>, usize) {
+        let mut missed = Vec::new();
+        let mut extra = 0;
+        for ((cell, &expected), &actual) in reference.indexed_iter().zip(other.iter()) {
+            if expected >= 0.5 && actual < 0.5 {
+                missed.push(cell);
+            } else if expected < 0.5 && actual >= 0.5 {
+                extra += 1;
+            }
+        }
+        (missed, extra)
+    }
+
+    /// Whether a cell has a neighbour on the other side of a shadow boundary
+    fn on_shadow_edge(shadow: &Array2<f32>, (row, col): (usize, usize)) -> bool {
+        let (n_rows, n_cols) = shadow.dim();
+        let shadowed = shadow[[row, col]] >= 0.5;
+        (row.saturating_sub(1)..(row + 2).min(n_rows)).any(|r| {
+            (col.saturating_sub(1)..(col + 2).min(n_cols))
+                .any(|c| (shadow[[r, c]] >= 0.5) != shadowed)
+        })
+    }
+
+    /// A 12 m block and a smooth 20 m hill on flat ground
+    fn block_and_hill() -> Array2<f32> {
+        Array2::from_shape_fn((60, 60), |(row, col)| {
+            let block: f32 = if (10..18).contains(&row) && (30..42).contains(&col) {
+                12.0
+            } else {
+                0.0
+            };
+            let (dr, dc) = (row as f32 - 40.0, col as f32 - 20.0);
+            let hill = 20.0 * (-(dr * dr + dc * dc) / 60.0).exp();
+            block.max(hill)
+        })
+    }
+
+    /// A single 30 m pole, one pixel wide, in the middle of flat ground
+    fn thin_pole() -> Array2<f32> {
+        let mut dsm = Array2::<f32>::zeros((61, 61));
+        dsm[[30, 30]] = 30.0;
+        dsm
+    }
+
+    fn horizon_shadow(
+        engine: &ShadowEngine,
+        profile: &HorizonProfile,
+        azimuth: f64,
+        elevation: f64,
+    ) -> Array2<f32> {
+        let sun_dir = engine.sun_direction(azimuth, elevation);
+        Array2::from_shape_fn(engine.dsm.dim(), |(row, col)| {
+            engine.horizon_cell_shadow(profile, row, col, (azimuth, elevation), sun_dir, 1.0)
+        })
+    }
+
+    #[test]
+    fn horizon_profile_matches_ray_marching() {
+        let engine = test_engine(block_and_hill(), serde_json::json!({}));
+        let profile = engine.compute_horizon_profile().unwrap();
+
+        for &(azimuth, elevation) in &[(90.0, 20.0), (135.0, 35.0), (183.0, 15.0), (247.5, 25.0)] {
+            let reference = ray_marched(&engine, azimuth, elevation);
+            let horizon = horizon_shadow(&engine, &profile, azimuth, elevation);
+            let (missed, extra) = disagreements(&reference, &horizon);
+            assert!(
+                missed.is_empty() && extra == 0,
+                "sun {}°/{}°: {} cells lit instead of shadowed {:?}, {} shadowed instead of lit",
+                azimuth,
+                elevation,
+                missed.len(),
+                missed,
+                extra
+            );
+        }
+    }
+
+    #[test]
+    fn horizon_profile_finds_thin_pole_between_sector_boundaries() {
+        // 36 sectors of 10°; the sun sits in the middle of a sector, where a single ray
+        // per sector would pass the pole by for most cells in its shadow
+        let engine = test_engine(thin_pole(), serde_json::json!({ "horizon_sectors": 36 }));
+        let profile = engine.compute_horizon_profile().unwrap();
+
+        for &(azimuth, elevation) in &[(5.0, 30.0), (125.0, 40.0), (214.0, 30.0)] {
+            let reference = ray_marched(&engine, azimuth, elevation);
+            assert!(reference.iter().filter(|&&shadow| shadow >= 0.5).count() > 10);
+
+            let horizon = horizon_shadow(&engine, &profile, azimuth, elevation);
+            assert_eq!(
+                disagreements(&reference, &horizon),
+                (Vec::new(), 0),
+                "sun {}°/{}°",
+                azimuth,
+                elevation
+            );
+        }
+    }
+
+    #[test]
+    fn horizon_profile_bounds_grid_traversal_past_sixty_pixels() {
+        // A one-cell pole at the east end of a long strip, with low suns whose shadows
+        // reach 60-90 px west, where 1° apart rays would pass it more than a cell wide
+        let mut dsm = Array2::<f32>::zeros((9, 100));
+        dsm[[4, 95]] = 30.0;
+        let engine = test_engine(dsm, serde_json::json!({ "ray_traversal": "GridExact" }));
+        let profile = engine.compute_horizon_profile().unwrap();
+
+        for &(azimuth, elevation) in &[(90.0, 18.0), (91.3, 17.0), (88.6, 19.5)] {
+            let reference = ray_marched(&engine, azimuth, elevation);
+            let far_shadow = reference
+                .indexed_iter()
+                .filter(|&((_, col), &shadow)| col < 35 && shadow >= 0.5)
+                .count();
+            assert!(far_shadow > 0, "sun {}°/{}°", azimuth, elevation);
+
+            // Most cells are decided by the profile alone
+            let cleared = reference
+                .indexed_iter()
+                .filter(|&((row, col), _)| profile.sun_clears_horizon(row, col, azimuth, elevation))
+                .count();
+            assert!(
+                cleared * 2 > reference.len(),
+                "only {} cells cleared",
+                cleared
+            );
+
+            let horizon = horizon_shadow(&engine, &profile, azimuth, elevation);
+            assert_eq!(
+                disagreements(&reference, &horizon),
+                (Vec::new(), 0),
+                "sun {}°/{}°",
+                azimuth,
+                elevation
+            );
+        }
+    }
+
+    #[test]
+    fn sub_hour_timestamps_group_by_their_own_sun_position() {
+        let engine = test_engine(
+            Array2::<f32>::zeros((10, 10)),
+            serde_json::json!({ "hour_interval": 0.25 }),
+        );
+        let timestamps = engine.generate_timestamps();
+        let groups = engine
+            .group_timestamps_by_sun_position(&timestamps)
+            .unwrap();
+        let sun_calc = engine.sun_calculator.lock().unwrap();
+
+        let daytime: Vec<&SunPositionGroup> = groups
+            .iter()
+            .filter(|group| !engine.sun_below_horizon(group.elevation))
+            .collect();
+        let daytime_hours: std::collections::HashSet<u32> = daytime
+            .iter()
+            .flat_map(|group| &group.time_indices)
+            .map(|&t_idx| timestamps[t_idx].hour())
+            .collect();
+        // The sun moves degrees in a quarter hour, so each quarter is its own position
+        assert!(
+            daytime.len() >= 3 * daytime_hours.len(),
+            "{} positions over {} daylight hours",
+            daytime.len(),
+            daytime_hours.len()
+        );
+
+        for group in daytime {
+            for &t_idx in &group.time_indices {
+                let (azimuth, elevation) = sun_calc.get_exact_position(&timestamps[t_idx]);
+                assert!((azimuth - group.azimuth).abs() <= engine.config.angle_precision);
+                assert!((elevation - group.elevation).abs() <= engine.config.angle_precision);
+            }
+        }
+    }
+
+    #[test]
+    fn grid_exact_rays_do_not_step_over_a_one_cell_wall() {
+        // An 8 m wall one cell thick running diagonally across the grid, with a low sun
+        // roughly square to it. Behind the wall, half-pixel steps land between its
+        // cells, where interpolated heights are half the wall's, for most rays that
+        // pass the wall between 4 and 8 m up; traversing every cell catches them all.
+        let wall = Array2::from_shape_fn((60, 60), |(row, col)| if row == col { 8.0 } else { 0.0 });
+        let (azimuth, elevation): (f64, f64) = (228.0, 20.0);
+        let tan_elevation = elevation.to_radians().tan();
+        let behind_wall: Vec<(usize, usize)> = wall
+            .indexed_iter()
+            .map(|(cell, _)| cell)
+            .filter(|&(row, col)| {
+                let distance = (col as f64 - row as f64) / std::f64::consts::SQRT_2;
+                distance > 4.0 / tan_elevation + 1.0
+                    && distance < 8.0 / tan_elevation - 1.5
+                    && row > 5
+                    && col < 54
+            })
+            .collect();
+        assert!(behind_wall.len() > 100);
+
+        let fixed_step = ray_marched(
+            &test_engine(wall.clone(), serde_json::json!({})),
+            azimuth,
+            elevation,
+        );
+        let stepped_over = behind_wall
+            .iter()
+            .filter(|&&cell| fixed_step[cell] < 0.5)
+            .count();
+        assert!(
+            stepped_over * 4 > behind_wall.len(),
+            "fixed steps missed the wall for only {} of {} cells",
+            stepped_over,
+            behind_wall.len()
+        );
+
+        // Normal quality traverses the grid unless told otherwise
+        let engine = test_engine(
+            wall,
+            serde_json::json!({ "shadow_quality": "Normal", "ray_traversal": null }),
+        );
+        assert_eq!(engine.config.get_ray_traversal(), RayTraversal::GridExact);
+        let grid_exact = ray_marched(&engine, azimuth, elevation);
+        let lit: Vec<&(usize, usize)> = behind_wall
+            .iter()
+            .filter(|&&cell| grid_exact[cell] < 0.5)
+            .collect();
+        assert!(lit.is_empty(), "lit behind the wall: {:?}", lit);
+    }
+
+    /// Rows of 15 m blocks along a narrow 6 × 400 strip, to exercise sweeps whose
+    /// lines run across many more steps than the raster is wide
+    fn narrow_strip() -> Array2<f32> {
+        Array2::from_shape_fn((6, 400), |(row, col)| {
+            if (1..4).contains(&row) && col % 40 < 3 {
+                15.0
+            } else {
+                0.0
+            }
+        })
+    }
+
+    #[test]
+    fn sweep_line_agrees_with_ray_marching_cell_by_cell() {
+        let scenes = [
+            ("block and hill", block_and_hill()),
+            ("thin pole", thin_pole()),
+            ("narrow strip", narrow_strip()),
+        ];
+        for (name, dsm) in scenes {
+            let engine = test_engine(dsm, serde_json::json!({}));
+            let n_cells = engine.dsm.len();
+            for &(azimuth, elevation) in &[
+                (90.0, 20.0),
+                (135.0, 35.0),
+                (183.0, 15.0),
+                (247.5, 25.0),
+                (300.0, 10.0),
+            ] {
+                let reference = ray_marched(&engine, azimuth, elevation);
+                let sweep = sweep_line::shadow_map(
+                    &engine.dsm,
+                    azimuth,
+                    elevation,
+                    &CancellationToken::new(),
+                )
+                .unwrap();
+
+                let differing: Vec<(usize, usize)> = reference
+                    .indexed_iter()
+                    .filter(|&(cell, &shadow)| (shadow >= 0.5) != (sweep[cell] >= 0.5))
+                    .map(|(cell, _)| cell)
+                    .collect();
+
+                // The kernels sample sub-pixel heights differently, so cells on the edge
+                // of a ray-marched shadow may flip, as may the odd cell where the sun
+                // grazes an occluder; disagreement anywhere else is a bug
+                let away_from_edges: Vec<(usize, usize)> = differing
+                    .iter()
+                    .copied()
+                    .filter(|&cell| !on_shadow_edge(&reference, cell))
+                    .collect();
+                assert!(
+                    away_from_edges.len() * 200 <= n_cells,
+                    "{}, sun {}°/{}°: {} of {} cells disagree ({} away from shadow edges): {:?}",
+                    name,
+                    azimuth,
+                    elevation,
+                    differing.len(),
+                    n_cells,
+                    away_from_edges.len(),
+                    differing
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn sweep_line_falls_back_to_ray_marching_with_curvature_correction() {
+        let sweep = serde_json::json!({ "shadow_algorithm": "SweepLine" });
+        let engine = test_engine(Array2::<f32>::zeros((4, 4)), sweep.clone());
+        assert_eq!(engine.shadow_algorithm(), ShadowAlgorithm::SweepLine);
+
+        let mut curved = sweep;
+        curved["curvature_refraction"] = serde_json::json!(true);
+        let engine = test_engine(Array2::<f32>::zeros((4, 4)), curved);
+        assert_eq!(engine.shadow_algorithm(), ShadowAlgorithm::RayMarching);
+    }
+
+    #[test]
+    fn sweep_line_stops_when_cancelled() {
+        let cancel_token = CancellationToken::new();
+        cancel_token.cancel();
+        let result = sweep_line::shadow_map(&block_and_hill(), 135.0, 35.0, &cancel_token);
+        assert!(matches!(result, Err(ShadowError::Cancelled)));
+    }
+}
@@ -0,0 +1,300 @@
+use crate::types::ShadowError;
+use ndarray::{s, Array2, Array3};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Time series of shadow maps, either held in memory or streamed to a file on disk.
+///
+/// The disk backend stores one contiguous `rows × cols` block of little-endian f32
+/// per timestamp, so writing a shadow map or reading a single time slice is one
+/// seek plus one sequential read/write.
+#[derive(Debug, Clone)]
+pub enum ShadowStore {
+    Memory(Array3<f32>),
+    Disk(Arc<DiskShadowStore>),
+}
+
+#[derive(Debug)]
+pub struct DiskShadowStore {
+    path: PathBuf,
+    file: Mutex<File>,
+    dim: (usize, usize, usize),
+}
+
+impl ShadowStore {
+    pub fn in_memory(n_times: usize, n_rows: usize, n_cols: usize) -> Self {
+        ShadowStore::Memory(Array3::<f32>::zeros((n_times, n_rows, n_cols)))
+    }
+
+    pub fn on_disk(
+        path: &Path,
+        n_times: usize,
+        n_rows: usize,
+        n_cols: usize,
+    ) -> Result<Self, ShadowError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len((n_times * n_rows * n_cols * std::mem::size_of::<f32>()) as u64)?;
+
+        Ok(ShadowStore::Disk(Arc::new(DiskShadowStore {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+            dim: (n_times, n_rows, n_cols),
+        })))
+    }
+
+    pub fn from_array(data: Array3<f32>) -> Self {
+        ShadowStore::Memory(data)
+    }
+
+    /// (times, rows, cols)
+    pub fn dim(&self) -> (usize, usize, usize) {
+        match self {
+            ShadowStore::Memory(data) => data.dim(),
+            ShadowStore::Disk(store) => store.dim,
+        }
+    }
+
+    pub fn write_slice(
+        &mut self,
+        t_idx: usize,
+        shadow_map: &Array2<f32>,
+    ) -> Result<(), ShadowError> {
+        match self {
+            ShadowStore::Memory(data) => {
+                data.slice_mut(s![t_idx, .., ..]).assign(shadow_map);
+                Ok(())
+            }
+            ShadowStore::Disk(store) => store.write_slice(t_idx, shadow_map),
+        }
+    }
+
+    pub fn read_slice(&self, t_idx: usize) -> Result<Array2<f32>, ShadowError> {
+        match self {
+            ShadowStore::Memory(data) => Ok(data.slice(s![t_idx, .., ..]).to_owned()),
+            ShadowStore::Disk(store) => store.read_slice(t_idx),
+        }
+    }
+
+    /// All timestamps for rows `row_start..row_end`, shaped (times, rows, cols)
+    pub fn read_row_block(
+        &self,
+        row_start: usize,
+        row_end: usize,
+    ) -> Result<Array3<f32>, ShadowError> {
+        match self {
+            ShadowStore::Memory(data) => Ok(data.slice(s![.., row_start..row_end, ..]).to_owned()),
+            ShadowStore::Disk(store) => store.read_row_block(row_start, row_end),
+        }
+    }
+
+    /// Number of rows per block so that `read_row_block` stays within `budget_bytes`
+    pub fn rows_per_block(&self, budget_bytes: usize) -> usize {
+        let (n_times, _n_rows, n_cols) = self.dim();
+        let row_bytes = (n_times * n_cols * std::mem::size_of::<f32>()).max(1);
+        (budget_bytes / row_bytes).max(1)
+    }
+
+    /// Set every cell where `mask` is true to `no_data_value` in all time slices
+    pub fn apply_mask(
+        &mut self,
+        mask: &Array2<bool>,
+        no_data_value: f32,
+    ) -> Result<(), ShadowError> {
+        let (n_times, _n_rows, _n_cols) = self.dim();
+        for t_idx in 0..n_times {
+            let mut slice = self.read_slice(t_idx)?;
+            ndarray::Zip::from(&mut slice)
+                .and(mask)
+                .for_each(|value, &masked| {
+                    if masked {
+                        *value = no_data_value;
+                    }
+                });
+            self.write_slice(t_idx, &slice)?;
+        }
+        Ok(())
+    }
+}
+
+impl DiskShadowStore {
+    fn slice_len(&self) -> usize {
+        self.dim.1 * self.dim.2
+    }
+
+    fn write_slice(&self, t_idx: usize, shadow_map: &Array2<f32>) -> Result<(), ShadowError> {
+        let bytes: Vec<u8> = shadow_map.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let offset = (t_idx * self.slice_len() * std::mem::size_of::<f32>()) as u64;
+
+        let mut file = self.file.lock().unwrap();
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(&bytes)?;
+        Ok(())
+    }
+
+    fn read_slice(&self, t_idx: usize) -> Result<Array2<f32>, ShadowError> {
+        let (_n_times, n_rows, n_cols) = self.dim;
+        let offset = (t_idx * self.slice_len() * std::mem::size_of::<f32>()) as u64;
+
+        let mut bytes = vec![0u8; self.slice_len() * std::mem::size_of::<f32>()];
+        let mut file = self.file.lock().unwrap();
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut bytes)?;
+        drop(file);
+
+        Array2::from_shape_vec((n_rows, n_cols), Self::decode(&bytes))
+            .map_err(|e| ShadowError::General(format!("Failed to read shadow slice: {}", e)))
+    }
+
+    fn read_row_block(&self, row_start: usize, row_end: usize) -> Result<Array3<f32>, ShadowError> {
+        let (n_times, _n_rows, n_cols) = self.dim;
+        let block_rows = row_end - row_start;
+        let block_len = block_rows * n_cols;
+
+        let mut values = Vec::with_capacity(n_times * block_len);
+        let mut bytes = vec![0u8; block_len * std::mem::size_of::<f32>()];
+
+        let mut file = self.file.lock().unwrap();
+        for t_idx in 0..n_times {
+            // Rows are contiguous within a time slice, so each block is one read
+            let offset = ((t_idx * self.slice_len() + row_start * n_cols)
+                * std::mem::size_of::<f32>()) as u64;
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut bytes)?;
+            values.extend(Self::decode(&bytes));
+        }
+        drop(file);
+
+        Array3::from_shape_vec((n_times, block_rows, n_cols), values)
+            .map_err(|e| ShadowError::General(format!("Failed to read shadow rows: {}", e)))
+    }
+
+    fn decode(bytes: &[u8]) -> Vec<f32> {
+        bytes
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect()
+    }
+}
+
+impl Drop for DiskShadowStore {
+    fn drop(&mut self) {
+        // The store is scratch space for one run; remove it once the results are gone
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{Array, Axis};
+
+    const DIM: (usize, usize, usize) = (3, 5, 4);
+
+    /// A distinct value in every cell of every time slice
+    fn series() -> Array3<f32> {
+        Array::from_shape_fn(DIM, |(t, row, col)| (t * 100 + row * 10 + col) as f32)
+    }
+
+    fn scratch_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "shadow_store_test_{}_{}.bin",
+            std::process::id(),
+            name
+        ))
+    }
+
+    fn filled(mut store: ShadowStore) -> ShadowStore {
+        let data = series();
+        for (t_idx, slice) in data.axis_iter(Axis(0)).enumerate() {
+            store.write_slice(t_idx, &slice.to_owned()).unwrap();
+        }
+        store
+    }
+
+    fn stores(name: &str) -> Vec<ShadowStore> {
+        let (n_times, n_rows, n_cols) = DIM;
+        vec![
+            filled(ShadowStore::in_memory(n_times, n_rows, n_cols)),
+            filled(ShadowStore::on_disk(&scratch_path(name), n_times, n_rows, n_cols).unwrap()),
+        ]
+    }
+
+    #[test]
+    fn slices_read_back_as_written() {
+        let data = series();
+        for store in stores("slices") {
+            assert_eq!(store.dim(), DIM);
+            for t_idx in 0..DIM.0 {
+                assert_eq!(
+                    store.read_slice(t_idx).unwrap(),
+                    data.index_axis(Axis(0), t_idx)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn row_blocks_reassemble_the_series() {
+        let data = series();
+        for store in stores("row_blocks") {
+            // Two rows per block splits the five rows into blocks of 2, 2 and 1
+            let row_bytes = DIM.0 * DIM.2 * std::mem::size_of::<f32>();
+            let rows_per_block = store.rows_per_block(2 * row_bytes + 1);
+            assert_eq!(rows_per_block, 2);
+
+            let mut blocks = Vec::new();
+            let mut row_start = 0;
+            while row_start < DIM.1 {
+                let row_end = (row_start + rows_per_block).min(DIM.1);
+                let block = store.read_row_block(row_start, row_end).unwrap();
+                assert_eq!(block, data.slice(s![.., row_start..row_end, ..]));
+                blocks.push(block);
+                row_start = row_end;
+            }
+            let views: Vec<_> = blocks.iter().map(|block| block.view()).collect();
+            assert_eq!(ndarray::concatenate(Axis(1), &views).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn mask_applies_to_every_slice() {
+        let mask = Array2::from_shape_fn((DIM.1, DIM.2), |(row, col)| row == col);
+        for mut store in stores("mask") {
+            store.apply_mask(&mask, -1.0).unwrap();
+            for t_idx in 0..DIM.0 {
+                let slice = store.read_slice(t_idx).unwrap();
+                for ((cell, &value), &masked) in slice.indexed_iter().zip(mask.iter()) {
+                    assert_eq!(value == -1.0, masked, "slice {} cell {:?}", t_idx, cell);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn disk_store_removes_its_file_with_the_last_clone() {
+        let path = scratch_path("drop");
+        let store = ShadowStore::on_disk(&path, 2, 2, 2).unwrap();
+        let (n_times, n_rows, n_cols) = store.dim();
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len() as usize,
+            n_times * n_rows * n_cols * std::mem::size_of::<f32>()
+        );
+
+        let clone = store.clone();
+        drop(store);
+        assert!(path.exists());
+        drop(clone);
+        assert!(!path.exists());
+    }
+}
@@ -1,20 +1,30 @@
 use chrono::{DateTime, Datelike, Timelike, Utc};
-use std::collections::HashMap;
 
 pub struct SunCalculator {
     latitude: f64,
     longitude: f64,
     angle_precision: f64,
-    cache: HashMap<(u32, u32), (f64, f64)>,
+    refraction: bool,
 }
 
 impl SunCalculator {
     pub fn new(latitude: f64, longitude: f64, angle_precision: f64) -> Self {
+        Self::new_with_refraction(latitude, longitude, angle_precision, false)
+    }
+
+    /// With `refraction` set, elevations are apparent (as seen through a standard
+    /// atmosphere) rather than geometric
+    pub fn new_with_refraction(
+        latitude: f64,
+        longitude: f64,
+        angle_precision: f64,
+        refraction: bool,
+    ) -> Self {
         Self {
             latitude,
             longitude,
             angle_precision,
-            cache: HashMap::new(),
+            refraction,
         }
     }
 
@@ -22,8 +32,9 @@ impl SunCalculator {
         &self,
         date: &DateTime<Utc>,
     ) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
-        // Use civil twilight threshold (-0.833° for practical sunrise/sunset)
-        let target_elevation = -0.833;
+        // Upper limb on the horizon: -0.833° geometric covers refraction plus the solar
+        // semi-diameter; apparent elevations already include refraction
+        let target_elevation = if self.refraction { -0.267 } else { -0.833 };
 
         // Start from solar noon and search backwards/forwards for zero crossing
         let _noon = date.date_naive().and_hms_opt(12, 0, 0)?.and_utc();
@@ -115,19 +126,10 @@ impl SunCalculator {
         }
     }
 
-    pub fn get_position(&mut self, datetime: &DateTime<Utc>) -> (f64, f64) {
-        let day = datetime.ordinal();
-        let hour = datetime.hour();
-        let key = (day, hour);
-
-        if let Some(&cached) = self.cache.get(&key) {
-            return cached;
-        }
-
+    /// Position at exactly `datetime`, with angles rounded to the angle precision
+    pub fn get_exact_position(&self, datetime: &DateTime<Utc>) -> (f64, f64) {
         let (azimuth, elevation) = self.calculate_position(datetime);
-        let rounded = self.round_angles(azimuth, elevation);
-        self.cache.insert(key, rounded);
-        rounded
+        self.round_angles(azimuth, elevation)
     }
 
     fn calculate_position(&self, datetime: &DateTime<Utc>) -> (f64, f64) {
@@ -158,7 +160,26 @@ impl SunCalculator {
             azimuth.to_degrees()
         };
 
-        (azimuth_deg, elevation.to_degrees())
+        let elevation_deg = elevation.to_degrees();
+        if self.refraction {
+            (
+                azimuth_deg,
+                elevation_deg + Self::refraction_correction(elevation_deg),
+            )
+        } else {
+            (azimuth_deg, elevation_deg)
+        }
+    }
+
+    /// Standard atmospheric refraction in degrees for a geometric elevation
+    /// (Saemundsson's formula, 10 °C and 1010 hPa)
+    fn refraction_correction(elevation: f64) -> f64 {
+        // Below about -1° the formula diverges and the sun is out of sight anyway
+        if elevation < -1.0 {
+            return 0.0;
+        }
+        let arcminutes = 1.02 / (elevation + 10.3 / (elevation + 5.11)).to_radians().tan();
+        arcminutes / 60.0
     }
 
     fn round_angles(&self, azimuth: f64, elevation: f64) -> (f64, f64) {
@@ -0,0 +1,156 @@
+use ndarray::Array2;
+use rayon::prelude::*;
+
+/// Slope and aspect of every cell of an elevation raster, from Horn's 3×3 gradient.
+///
+/// Border cells reuse their nearest in-grid neighbours, and cells with a missing
+/// neighbour are treated as flat.
+pub struct SurfaceOrientation {
+    /// Degrees from horizontal
+    slope: Array2<f32>,
+    /// Degrees clockwise from north that the slope faces downhill (0 on flat cells)
+    aspect: Array2<f32>,
+}
+
+impl SurfaceOrientation {
+    pub fn from_elevation(elevation: &Array2<f32>, resolution: f64) -> Self {
+        let (n_rows, n_cols) = elevation.dim();
+        let mut slope = Array2::<f32>::zeros((n_rows, n_cols));
+        let mut aspect = Array2::<f32>::zeros((n_rows, n_cols));
+        if n_rows == 0 || n_cols == 0 {
+            return Self { slope, aspect };
+        }
+
+        let rows: Vec<Vec<(f32, f32)>> = (0..n_rows)
+            .into_par_iter()
+            .map(|row| {
+                (0..n_cols)
+                    .map(|col| match horn_gradient(elevation, row, col, resolution) {
+                        Some((dz_east, dz_south)) => {
+                            let slope = dz_east.hypot(dz_south).atan().to_degrees();
+                            // Downhill points against the gradient, so it is the
+                            // uphill bearing turned by 180°
+                            let aspect = if slope > 0.0 {
+                                (dz_east.atan2(-dz_south).to_degrees() + 180.0).rem_euclid(360.0)
+                            } else {
+                                0.0
+                            };
+                            (slope as f32, aspect as f32)
+                        }
+                        None => (0.0, 0.0),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        for (row, values) in rows.into_iter().enumerate() {
+            for (col, (cell_slope, cell_aspect)) in values.into_iter().enumerate() {
+                slope[[row, col]] = cell_slope;
+                aspect[[row, col]] = cell_aspect;
+            }
+        }
+
+        Self { slope, aspect }
+    }
+
+    pub fn slope(&self) -> &Array2<f32> {
+        &self.slope
+    }
+
+    pub fn aspect(&self) -> &Array2<f32> {
+        &self.aspect
+    }
+
+    /// Cosine of the angle between the cell's surface normal and the sun direction.
+    /// Zero or below means the sun is behind the surface plane.
+    pub fn cos_incidence(&self, row: usize, col: usize, azimuth: f64, elevation: f64) -> f64 {
+        let slope = (self.slope[[row, col]] as f64).to_radians();
+        let aspect = self.aspect[[row, col]] as f64;
+        let elevation = elevation.to_radians();
+        slope.cos() * elevation.sin()
+            + slope.sin() * elevation.cos() * (azimuth - aspect).to_radians().cos()
+    }
+
+    /// `cos_incidence` for every cell at one sun position
+    pub fn cos_incidence_map(&self, azimuth: f64, elevation: f64) -> Array2<f32> {
+        let (n_rows, n_cols) = self.slope.dim();
+        let rows: Vec<Vec<f32>> = (0..n_rows)
+            .into_par_iter()
+            .map(|row| {
+                (0..n_cols)
+                    .map(|col| self.cos_incidence(row, col, azimuth, elevation) as f32)
+                    .collect()
+            })
+            .collect();
+
+        let mut cos_incidence = Array2::<f32>::zeros((n_rows, n_cols));
+        for (row, values) in rows.into_iter().enumerate() {
+            for (col, value) in values.into_iter().enumerate() {
+                cos_incidence[[row, col]] = value;
+            }
+        }
+        cos_incidence
+    }
+}
+
+/// Height change per metre toward east and toward south (increasing row)
+fn horn_gradient(
+    elevation: &Array2<f32>,
+    row: usize,
+    col: usize,
+    resolution: f64,
+) -> Option<(f64, f64)> {
+    let (n_rows, n_cols) = elevation.dim();
+    let z = |d_row: isize, d_col: isize| -> f64 {
+        let r = (row as isize + d_row).clamp(0, n_rows as isize - 1) as usize;
+        let c = (col as isize + d_col).clamp(0, n_cols as isize - 1) as usize;
+        elevation[[r, c]] as f64
+    };
+
+    let dz_east = (z(-1, 1) + 2.0 * z(0, 1) + z(1, 1) - z(-1, -1) - 2.0 * z(0, -1) - z(1, -1))
+        / (8.0 * resolution);
+    let dz_south = (z(1, -1) + 2.0 * z(1, 0) + z(1, 1) - z(-1, -1) - 2.0 * z(-1, 0) - z(-1, 1))
+        / (8.0 * resolution);
+
+    if dz_east.is_finite() && dz_south.is_finite() {
+        Some((dz_east, dz_south))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A plane dropping `drop_per_row` metres per row toward the south, on 2 m cells
+    fn south_slope(drop_per_row: f32) -> SurfaceOrientation {
+        let elevation = Array2::from_shape_fn((5, 5), |(row, _)| 100.0 - drop_per_row * row as f32);
+        SurfaceOrientation::from_elevation(&elevation, 2.0)
+    }
+
+    #[test]
+    fn plane_slope_and_aspect() {
+        let surface = south_slope(2.0);
+        assert!((surface.slope()[[2, 2]] - 45.0).abs() < 1e-4);
+        assert!((surface.aspect()[[2, 2]] - 180.0).abs() < 1e-4);
+
+        let flat = south_slope(0.0);
+        assert_eq!((flat.slope()[[2, 2]], flat.aspect()[[2, 2]]), (0.0, 0.0));
+    }
+
+    #[test]
+    fn incidence_cosine_follows_the_surface_normal() {
+        // The normal of a 45° south-facing slope points at the sun due south at 45°,
+        // and lies in the plane of a sun due north at 45°
+        let surface = south_slope(2.0);
+        assert!((surface.cos_incidence(2, 2, 180.0, 45.0) - 1.0).abs() < 1e-6);
+        assert!(surface.cos_incidence(2, 2, 0.0, 45.0).abs() < 1e-6);
+        assert!(surface.cos_incidence(2, 2, 0.0, 30.0) < 0.0);
+
+        // Flat ground sees the sine of the elevation from any azimuth
+        let flat = south_slope(0.0);
+        let map = flat.cos_incidence_map(73.0, 30.0);
+        assert!(map.iter().all(|&value| (value - 0.5).abs() < 1e-6));
+    }
+}
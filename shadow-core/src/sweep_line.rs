@@ -0,0 +1,158 @@
+use crate::types::{CancellationToken, ShadowError};
+use ndarray::Array2;
+use rayon::prelude::*;
+
+/// Shadow map for one sun position by sweeping the DSM along lines parallel to the
+/// sun's azimuth.
+///
+/// Lines one pixel apart are advanced together, one pixel along the major axis at a
+/// time, walking away from the sun. Each line carries the height of the shadow
+/// surface cast by everything it has already passed; the surface drops by
+/// `tan(elevation)` metres per pixel of horizontal travel (the same convention as
+/// `ShadowEngine::calculate_cell_shadow`). A cell is shadowed when the surface,
+/// interpolated between the two lines either side of its centre, is above the cell.
+/// Every step touches each cell once and only the lines crossing the raster at that
+/// step, so the cost is O(cells) regardless of how far shadows reach or how
+/// elongated the raster is. The carried surface has no memory of where it was cast
+/// from, so it cannot apply the Earth-curvature correction; the engine traces rays
+/// instead when that correction is on. The token is checked once per step.
+pub fn shadow_map(
+    dsm: &Array2<f32>,
+    azimuth: f64,
+    elevation: f64,
+    cancel_token: &CancellationToken,
+) -> Result<Array2<f32>, ShadowError> {
+    let (n_rows, n_cols) = dsm.dim();
+    let mut shadow_map = Array2::<f32>::zeros((n_rows, n_cols));
+    if n_rows == 0 || n_cols == 0 {
+        return Ok(shadow_map);
+    }
+
+    // Direction pointing away from the sun in (col, row) pixel space
+    let az_rad = azimuth.to_radians();
+    let away_col = -az_rad.sin();
+    let away_row = az_rad.cos();
+    let drop_per_pixel = elevation.to_radians().tan();
+
+    // Walk along whichever axis the direction is closer to, so lines never skip cells
+    let cols_major = away_col.abs() >= away_row.abs();
+    let (n_major, n_minor, major_dir, minor_dir) = if cols_major {
+        (n_cols, n_rows, away_col, away_row)
+    } else {
+        (n_rows, n_cols, away_row, away_col)
+    };
+
+    let height_at = |major: usize, minor: usize| -> f64 {
+        if cols_major {
+            dsm[[minor, major]] as f64
+        } else {
+            dsm[[major, minor]] as f64
+        }
+    };
+
+    let major_step: isize = if major_dir >= 0.0 { 1 } else { -1 };
+    let major_start = if major_step > 0 {
+        0
+    } else {
+        n_major as isize - 1
+    };
+    let slope = minor_dir / major_dir.abs(); // Minor-axis offset per major-axis step
+    let step_length = (1.0 + slope * slope).sqrt(); // Horizontal pixels per step
+    let step_drop = step_length * drop_per_pixel;
+
+    // Integer line offsets covering every line that crosses the raster at some step,
+    // so every cell centre is bracketed by two lines. Line `l` sits at minor
+    // coordinate `first_offset + l + k * slope` on step `k`.
+    let last_shift = (n_major - 1) as f64 * slope;
+    let first_offset = (-1.0 - last_shift.max(0.0)).floor() as isize;
+    let last_offset = (n_minor as f64 - last_shift.min(0.0)).ceil() as isize;
+    let n_lines = (last_offset - first_offset + 1) as usize;
+
+    // Shadow surface height carried by each line, and the terrain height it last passed
+    let mut surface = vec![f64::NEG_INFINITY; n_lines];
+    let mut previous_height = vec![f64::NEG_INFINITY; n_lines];
+
+    for k in 0..n_major {
+        if cancel_token.is_cancelled() {
+            return Err(ShadowError::Cancelled);
+        }
+        let major = (major_start + k as isize * major_step) as usize;
+        let shift = k as f64 * slope;
+
+        // Lines within a pixel of the raster on this step. The others have either not
+        // reached terrain yet, so carry no surface, or have left it for good.
+        let base = first_offset as f64 + shift;
+        let active_start = ((-1.0 - base).floor().max(0.0) as usize).min(n_lines);
+        let active_end = ((n_minor as f64 - base).ceil().max(0.0) as usize + 1).min(n_lines);
+
+        // Advance the active lines one step further from the sun
+        surface[active_start..active_end]
+            .par_iter_mut()
+            .zip(previous_height[active_start..active_end].par_iter_mut())
+            .enumerate()
+            .for_each(|(index, (surface_height, last_height))| {
+                let line = active_start + index;
+                *surface_height = surface_height.max(*last_height) - step_drop;
+
+                let minor_f = snap((first_offset + line as isize) as f64 + shift);
+                *last_height = if minor_f < 0.0 || minor_f > (n_minor - 1) as f64 {
+                    f64::NEG_INFINITY
+                } else {
+                    // Line height: linear interpolation across the minor axis
+                    let m0 = minor_f.floor() as usize;
+                    let m1 = (m0 + 1).min(n_minor - 1);
+                    let frac = minor_f - m0 as f64;
+                    let h0 = height_at(major, m0);
+                    let h1 = height_at(major, m1);
+                    h0 * (1.0 - frac) + h1 * frac
+                };
+            });
+
+        // Test each cell centre on this step against the two lines around it
+        let step_values: Vec<f32> = (0..n_minor)
+            .into_par_iter()
+            .map(|minor| {
+                let line_f = snap((minor as isize - first_offset) as f64 - shift);
+                let line0 = line_f.floor() as usize;
+                let weight = line_f - line0 as f64;
+
+                let s0 = surface[line0];
+                let s1 = surface[line0 + 1];
+                let surface_height = if weight <= 0.0 || s1 == f64::NEG_INFINITY {
+                    s0
+                } else if s0 == f64::NEG_INFINITY {
+                    s1
+                } else {
+                    s0 * (1.0 - weight) + s1 * weight
+                };
+
+                if surface_height > height_at(major, minor) {
+                    1.0
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+
+        for (minor, value) in step_values.into_iter().enumerate() {
+            if cols_major {
+                shadow_map[[minor, major]] = value;
+            } else {
+                shadow_map[[major, minor]] = value;
+            }
+        }
+    }
+
+    Ok(shadow_map)
+}
+
+/// Round away floating-point noise, so that on diagonal sweeps a line passing exactly
+/// through cell centres is treated as doing so whatever its offset
+fn snap(position: f64) -> f64 {
+    let nearest = position.round();
+    if (position - nearest).abs() < 1e-9 {
+        nearest
+    } else {
+        position
+    }
+}
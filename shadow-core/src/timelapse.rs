@@ -0,0 +1,378 @@
+use crate::types::{ShadowError, ShadowResult};
+use ndarray::Array2;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+/// Colour of a fully sunlit cell
+const SUNLIT: [f32; 3] = [255.0, 224.0, 130.0];
+
+/// Colour of a fully shaded cell
+const SHADED: [f32; 3] = [40.0, 52.0, 110.0];
+
+/// Cells outside the AOI when there is no hillshade
+const BACKGROUND: [u8; 3] = [32, 32, 32];
+
+/// Frames are upscaled until they are at least this wide
+const MIN_FRAME_WIDTH: usize = 512;
+
+/// Colour quantization effort for GIF frames, 1 (best) to 30 (fastest)
+const GIF_QUANTIZATION_SPEED: i32 = 10;
+
+/// 5×7 bitmap glyphs for timestamp labels, one row per byte, low five bits used
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        _ => [0x00; GLYPH_HEIGHT],
+    }
+}
+
+/// Which time slices to render and how fast to play them
+#[derive(Debug, Clone, Copy)]
+pub struct TimelapseOptions {
+    /// First time index, inclusive
+    pub start: usize,
+    /// Last time index, inclusive
+    pub end: usize,
+    /// Display time of each GIF frame
+    pub frame_delay_ms: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct TimelapseExport {
+    pub frames: usize,
+    pub frame_directory: PathBuf,
+    pub gif_path: PathBuf,
+}
+
+/// An RGBA image
+struct Frame {
+    width: usize,
+    height: usize,
+    rgba: Vec<u8>,
+}
+
+/// Render time slices of `shadow_fraction` as colour frames, optionally shaded by a
+/// hillshade on the same grid, each labelled with its timestamp. Frames are written
+/// as a numbered PNG sequence in `directory` and as one looping animated GIF.
+///
+/// `on_frame` is called with the number of frames done and the total; returning an
+/// error stops the export.
+pub fn write_timelapse<F>(
+    directory: &Path,
+    results: &ShadowResult,
+    hillshade: Option<&Array2<f32>>,
+    options: TimelapseOptions,
+    mut on_frame: F,
+) -> Result<TimelapseExport, ShadowError>
+where
+    F: FnMut(usize, usize) -> Result<(), ShadowError>,
+{
+    let n_times = results.timestamps.len();
+    if options.start > options.end || options.end >= n_times {
+        return Err(ShadowError::Config(format!(
+            "Time range {}-{} is outside the {} timestamps",
+            options.start, options.end, n_times
+        )));
+    }
+
+    let (_, n_rows, n_cols) = results.shadow_fraction.dim();
+    let scale = MIN_FRAME_WIDTH.div_ceil(n_cols.max(1)).max(1);
+    let (width, height) = (n_cols * scale, n_rows * scale);
+    if width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(ShadowError::Config(format!(
+            "Frames of {}x{} pixels are too large for a GIF",
+            width, height
+        )));
+    }
+
+    std::fs::create_dir_all(directory)?;
+    let gif_path = directory.join("timelapse.gif");
+    let gif_error = |e: gif::EncodingError| ShadowError::General(format!("GIF error: {}", e));
+    let mut encoder = gif::Encoder::new(
+        BufWriter::new(File::create(&gif_path)?),
+        width as u16,
+        height as u16,
+        &[],
+    )
+    .map_err(gif_error)?;
+    encoder
+        .set_repeat(gif::Repeat::Infinite)
+        .map_err(gif_error)?;
+
+    let total = options.end - options.start + 1;
+    for (done, index) in (options.start..=options.end).enumerate() {
+        let shadow = results.shadow_fraction.read_slice(index)?;
+        let label = results.timestamps[index]
+            .format("%Y-%m-%d %H:%M UTC")
+            .to_string();
+        let mut frame = render_frame(&shadow, hillshade, &label, scale);
+
+        write_png(
+            &directory.join(format!("frame_{:04}.png", done + 1)),
+            &frame,
+        )?;
+
+        let mut gif_frame = gif::Frame::from_rgba_speed(
+            width as u16,
+            height as u16,
+            &mut frame.rgba,
+            GIF_QUANTIZATION_SPEED,
+        );
+        // GIF delays are in hundredths of a second
+        gif_frame.delay = (options.frame_delay_ms / 10).clamp(1, u16::MAX as u32) as u16;
+        encoder.write_frame(&gif_frame).map_err(gif_error)?;
+
+        on_frame(done + 1, total)?;
+    }
+
+    println!(
+        "Time-lapse: {} frames of {}x{} pixels in {:?}",
+        total, width, height, directory
+    );
+    Ok(TimelapseExport {
+        frames: total,
+        frame_directory: directory.to_path_buf(),
+        gif_path,
+    })
+}
+
+/// Blend from sunlit to shaded by the shadow fraction, darkened by the hillshade.
+/// Cells outside the AOI (NaN) show the hillshade in grey, or a dark background.
+fn render_frame(
+    shadow: &Array2<f32>,
+    hillshade: Option<&Array2<f32>>,
+    label: &str,
+    scale: usize,
+) -> Frame {
+    let (n_rows, n_cols) = shadow.dim();
+    let (width, height) = (n_cols * scale, n_rows * scale);
+    let mut rgba = vec![255u8; width * height * 4];
+
+    for ((row, col), &fraction) in shadow.indexed_iter() {
+        let shade = hillshade.map(|hillshade| hillshade[[row, col]].clamp(0.0, 1.0));
+        let colour: [u8; 3] = match (fraction.is_nan(), shade) {
+            (true, Some(shade)) => {
+                let grey = (60.0 + 140.0 * shade) as u8;
+                [grey, grey, grey]
+            }
+            (true, None) => BACKGROUND,
+            (false, shade) => {
+                let t = fraction.clamp(0.0, 1.0);
+                let light = shade.map_or(1.0, |shade| 0.55 + 0.45 * shade);
+                let channel = |i: usize| ((SUNLIT[i] + (SHADED[i] - SUNLIT[i]) * t) * light) as u8;
+                [channel(0), channel(1), channel(2)]
+            }
+        };
+
+        for y in row * scale..(row + 1) * scale {
+            for x in col * scale..(col + 1) * scale {
+                let offset = (y * width + x) * 4;
+                rgba[offset..offset + 3].copy_from_slice(&colour);
+            }
+        }
+    }
+
+    let mut frame = Frame {
+        width,
+        height,
+        rgba,
+    };
+    draw_label(&mut frame, label);
+    frame
+}
+
+/// White text on a dark box in the top-left corner
+fn draw_label(frame: &mut Frame, label: &str) {
+    let pixel = (frame.width / 256).clamp(1, 4);
+    let margin = 4 * pixel;
+    let advance = (GLYPH_WIDTH + 1) * pixel;
+    let box_width = label.chars().count() * advance + 2 * margin;
+    let box_height = GLYPH_HEIGHT * pixel + 2 * margin;
+
+    let mut fill = |x: usize, y: usize, colour: [u8; 3]| {
+        if x < frame.width && y < frame.height {
+            let offset = (y * frame.width + x) * 4;
+            frame.rgba[offset..offset + 3].copy_from_slice(&colour);
+        }
+    };
+
+    for y in 0..box_height {
+        for x in 0..box_width {
+            fill(x, y, [0, 0, 0]);
+        }
+    }
+    for (index, c) in label.chars().enumerate() {
+        let left = margin + index * advance;
+        for (glyph_row, bits) in glyph(c).iter().enumerate() {
+            for glyph_col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - glyph_col)) == 0 {
+                    continue;
+                }
+                for dy in 0..pixel {
+                    for dx in 0..pixel {
+                        fill(
+                            left + glyph_col * pixel + dx,
+                            margin + glyph_row * pixel + dy,
+                            [255, 255, 255],
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn write_png(path: &Path, frame: &Frame) -> Result<(), ShadowError> {
+    let png_error = |e: png::EncodingError| ShadowError::General(format!("PNG error: {}", e));
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(path)?),
+        frame.width as u32,
+        frame.height as u32,
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    writer.write_image_data(&frame.rgba).map_err(png_error)?;
+    writer.finish().map_err(png_error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shadow_store::ShadowStore;
+    use crate::types::SummaryStats;
+    use chrono::{Duration, TimeZone, Utc};
+    use ndarray::Array3;
+
+    /// Hourly results on a 2x3 grid, each slice shaded a little more than the last
+    fn results(n_times: usize) -> ShadowResult {
+        let start = Utc.with_ymd_and_hms(2024, 6, 21, 8, 0, 0).unwrap();
+        let layer = || Array3::zeros((1, 2, 3));
+        ShadowResult {
+            shadow_fraction: ShadowStore::from_array(Array3::from_shape_fn(
+                (n_times, 2, 3),
+                |(time, _, _)| time as f32 / n_times as f32,
+            )),
+            timestamps: (0..n_times)
+                .map(|hour| start + Duration::hours(hour as i64))
+                .collect(),
+            summary_stats: SummaryStats {
+                total_shadow_hours: layer(),
+                avg_shadow_percentage: layer(),
+                max_consecutive_shadow: layer(),
+                morning_shadow_hours: layer(),
+                noon_shadow_hours: layer(),
+                afternoon_shadow_hours: layer(),
+                solar_efficiency_percentage: layer(),
+                daily_solar_hours: layer(),
+                total_available_solar_hours: layer(),
+                sky_view_factor: None,
+                direct_irradiation: None,
+                diffuse_irradiation: None,
+                global_irradiation: None,
+                slope: None,
+                aspect: None,
+                self_shaded_hours: None,
+                effective_sun_hours: None,
+            },
+            unique_sun_positions: Some(n_times),
+        }
+    }
+
+    fn scratch_directory(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("timelapse_test_{}_{}", std::process::id(), name))
+    }
+
+    fn options(start: usize, end: usize) -> TimelapseOptions {
+        TimelapseOptions {
+            start,
+            end,
+            frame_delay_ms: 200,
+        }
+    }
+
+    #[test]
+    fn frame_range_must_lie_within_the_timestamps() {
+        let directory = scratch_directory("range");
+        let results = results(4);
+        for (start, end) in [(2, 1), (0, 4), (4, 4), (3, usize::MAX)] {
+            let export =
+                write_timelapse(&directory, &results, None, options(start, end), |_, _| {
+                    Ok(())
+                });
+            assert!(
+                matches!(export, Err(ShadowError::Config(_))),
+                "range {}-{}",
+                start,
+                end
+            );
+        }
+        // Nothing is written for a rejected range
+        assert!(!directory.exists());
+    }
+
+    #[test]
+    fn frame_range_is_inclusive() {
+        let directory = scratch_directory("inclusive");
+        let mut progress = Vec::new();
+        let export = write_timelapse(
+            &directory,
+            &results(4),
+            None,
+            options(1, 3),
+            |done, total| {
+                progress.push((done, total));
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(export.frames, 3);
+        assert_eq!(progress, vec![(1, 3), (2, 3), (3, 3)]);
+        assert!(export.gif_path.exists());
+        assert!(directory.join("frame_0003.png").exists());
+        assert!(!directory.join("frame_0004.png").exists());
+
+        // A single timestamp makes a one-frame time-lapse
+        let single = write_timelapse(&directory, &results(4), None, options(3, 3), |_, _| Ok(()));
+        assert_eq!(single.unwrap().frames, 1);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn progress_error_stops_the_export() {
+        let directory = scratch_directory("cancel");
+        let mut calls = 0;
+        let export = write_timelapse(&directory, &results(4), None, options(0, 3), |done, _| {
+            calls += 1;
+            if done == 2 {
+                Err(ShadowError::Cancelled)
+            } else {
+                Ok(())
+            }
+        });
+        assert!(matches!(export, Err(ShadowError::Cancelled)));
+        assert_eq!(calls, 2);
+        assert!(!directory.join("frame_0003.png").exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,565 @@
+use crate::shadow_store::ShadowStore;
+use chrono::{DateTime, Datelike, Utc};
+use geo_types::Polygon;
+use ndarray::{Array2, Array3};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    pub dtm_path: String,
+    pub dsm_path: String,
+    pub aoi: Vec<Vec<f64>>, // Simplified for JSON serialization
+    pub start_date: String,
+    pub end_date: String,
+    pub hour_interval: f32,
+    pub buffer_meters: Option<f64>, // Now optional - will be calculated automatically
+    pub angle_precision: f64,
+    pub shadow_quality: ShadowQuality,
+    pub cpu_cores: Option<usize>, // Number of CPU cores to use (None = auto-detect)
+    pub shadow_algorithm: Option<ShadowAlgorithm>, // None = per-timestamp ray marching
+    pub horizon_sectors: Option<usize>, // Azimuth sectors for horizon profiles (None = by quality)
+    pub result_storage: Option<ResultStorage>, // Where to keep the time series (None = Auto)
+    pub storage_dir: Option<String>, // Directory for disk-backed results (None = system temp dir)
+    pub ray_traversal: Option<RayTraversal>, // How rays step through the DSM (None = by quality)
+    pub curvature_refraction: Option<bool>, // Correct for Earth curvature and refraction (None = off)
+    pub solar_disk_samples: Option<usize>, // Rays across the solar disk for penumbra (None = point sun)
+    pub vegetation_path: Option<String>, // Canopy or classification raster on the DSM grid (None = all opaque)
+    pub vegetation_classes: Option<Vec<i32>>, // Raster values counted as vegetation (None = any value > 0)
+    pub leaf_on_transmissivity: Option<f64>, // Light kept per metre of canopy with leaves (None = 0.8)
+    pub leaf_off_transmissivity: Option<f64>, // Light kept per metre of bare canopy (None = 0.95)
+    pub leaf_on_date: Option<String>,        // "MM-DD" when leaves come out (None = 05-01)
+    pub leaf_off_date: Option<String>,       // "MM-DD" when leaves drop (None = 10-31)
+    pub facade_min_wall_height: Option<f64>, // Height jump treated as a wall in facade mode (None = 2.5 m)
+    pub facade_level_spacing: Option<f64>,   // Vertical spacing of facade samples (None = 1.0 m)
+    pub sky_view_factor: Option<bool>,       // Add a sky view factor summary layer (None = off)
+    pub sky_view_directions: Option<usize>, // Azimuths traced for the sky view factor (None = by quality)
+    pub irradiance: Option<bool>, // Add clear-sky irradiation layers in kWh/m² (None = off)
+    pub slope_source: Option<SlopeSource>, // Raster slope and aspect are derived from (None = DSM)
+    pub slope_aspect: Option<bool>, // Add slope and aspect layers (None = off)
+    pub self_shading: Option<bool>, // Shade cells whose surface faces away from the sun (None = off)
+    pub incidence_weighting: Option<bool>, // Direct irradiation on the sloped surface, not horizontal (None = off)
+    pub roof_min_height: Option<f64>, // Height above terrain counted as building roof (None = 2.5 m)
+    pub roof_angle_tolerance: Option<f64>, // Normal deviation allowed within one roof face (None = 10°)
+    pub roof_min_area: Option<f64>,        // Smallest roof face footprint kept (None = 5 m²)
+    pub roof_usable_sun_fraction: Option<f64>, // Share of analysis hours a usable roof cell is sunlit (None = 0.8)
+    pub pv_array: Option<PvArray>,             // PV system for yield estimates (None = not defined)
+    pub weather_path: Option<String>, // EPW or TMY CSV weighting timestamps by irradiance (None = always sunny)
+    pub point_cloud_resolution: Option<f64>, // Cell size in metres when gridding LAS/LAZ inputs (None = 1.0)
+    pub proposed_buildings: Option<Vec<BuildingFootprint>>, // Footprints burned into the DSM (None = as surveyed)
+}
+
+impl Config {
+    pub fn to_polygon(&self) -> Result<Polygon<f64>, String> {
+        if self.aoi.is_empty() {
+            return Err("AOI is empty".to_string());
+        }
+
+        let coords: Vec<(f64, f64)> = self
+            .aoi
+            .iter()
+            .map(|coord| {
+                if coord.len() >= 2 {
+                    (coord[0], coord[1])
+                } else {
+                    (0.0, 0.0)
+                }
+            })
+            .collect();
+
+        Ok(Polygon::new(geo_types::LineString::from(coords), vec![]))
+    }
+
+    pub fn get_cpu_cores(&self) -> usize {
+        match self.cpu_cores {
+            Some(cores) => {
+                let max_cores = num_cpus::get();
+                cores.min(max_cores).max(1) // Ensure between 1 and max available
+            }
+            None => num_cpus::get(), // Auto-detect all available cores
+        }
+    }
+
+    pub fn get_shadow_algorithm(&self) -> ShadowAlgorithm {
+        self.shadow_algorithm
+            .unwrap_or(ShadowAlgorithm::RayMarching)
+    }
+
+    pub fn get_result_storage(&self) -> ResultStorage {
+        self.result_storage.unwrap_or(ResultStorage::Auto)
+    }
+
+    pub fn get_storage_dir(&self) -> std::path::PathBuf {
+        match &self.storage_dir {
+            Some(dir) => std::path::PathBuf::from(dir),
+            None => std::env::temp_dir().join("shadow_calculator"),
+        }
+    }
+
+    pub fn get_point_cloud_resolution(&self) -> f64 {
+        self.point_cloud_resolution.unwrap_or(1.0).max(0.01)
+    }
+
+    pub fn get_curvature_refraction(&self) -> bool {
+        self.curvature_refraction.unwrap_or(false)
+    }
+
+    pub fn get_facade_min_wall_height(&self) -> f64 {
+        self.facade_min_wall_height.unwrap_or(2.5).max(0.5)
+    }
+
+    pub fn get_facade_level_spacing(&self) -> f64 {
+        self.facade_level_spacing.unwrap_or(1.0).max(0.1)
+    }
+
+    pub fn get_leaf_on_transmissivity(&self) -> f64 {
+        self.leaf_on_transmissivity.unwrap_or(0.8).clamp(0.0, 1.0)
+    }
+
+    pub fn get_leaf_off_transmissivity(&self) -> f64 {
+        self.leaf_off_transmissivity.unwrap_or(0.95).clamp(0.0, 1.0)
+    }
+
+    /// Leaf-on and leaf-off dates
+    pub fn get_leaf_season(&self) -> Result<(MonthDay, MonthDay), ShadowError> {
+        let leaf_on = Self::parse_month_day(self.leaf_on_date.as_deref().unwrap_or("05-01"))?;
+        let leaf_off = Self::parse_month_day(self.leaf_off_date.as_deref().unwrap_or("10-31"))?;
+        Ok((leaf_on, leaf_off))
+    }
+
+    fn parse_month_day(value: &str) -> Result<MonthDay, ShadowError> {
+        let date = chrono::NaiveDate::parse_from_str(&format!("2000-{}", value), "%Y-%m-%d")
+            .map_err(|e| {
+                ShadowError::Config(format!("Invalid leaf season date '{}': {}", value, e))
+            })?;
+        Ok((date.month(), date.day()))
+    }
+
+    pub fn get_solar_disk_samples(&self) -> usize {
+        self.solar_disk_samples.unwrap_or(1).clamp(1, 64)
+    }
+
+    pub fn get_ray_traversal(&self) -> RayTraversal {
+        match self.ray_traversal {
+            Some(traversal) => traversal,
+            None => match self.shadow_quality {
+                ShadowQuality::Fast => RayTraversal::FixedStep,
+                ShadowQuality::Normal | ShadowQuality::High | ShadowQuality::Scientific => {
+                    RayTraversal::GridExact
+                }
+            },
+        }
+    }
+
+    pub fn get_sky_view_factor(&self) -> bool {
+        self.sky_view_factor.unwrap_or(false)
+    }
+
+    pub fn get_slope_source(&self) -> SlopeSource {
+        self.slope_source.unwrap_or(SlopeSource::Dsm)
+    }
+
+    pub fn get_slope_aspect(&self) -> bool {
+        self.slope_aspect.unwrap_or(false)
+    }
+
+    pub fn get_self_shading(&self) -> bool {
+        self.self_shading.unwrap_or(false)
+    }
+
+    pub fn get_incidence_weighting(&self) -> bool {
+        self.incidence_weighting.unwrap_or(false)
+    }
+
+    pub fn get_roof_min_height(&self) -> f64 {
+        self.roof_min_height.unwrap_or(2.5).max(0.5)
+    }
+
+    pub fn get_roof_angle_tolerance(&self) -> f64 {
+        self.roof_angle_tolerance.unwrap_or(10.0).clamp(1.0, 45.0)
+    }
+
+    pub fn get_roof_min_area(&self) -> f64 {
+        self.roof_min_area.unwrap_or(5.0).max(0.0)
+    }
+
+    pub fn get_roof_usable_sun_fraction(&self) -> f64 {
+        self.roof_usable_sun_fraction.unwrap_or(0.8).clamp(0.0, 1.0)
+    }
+
+    pub fn get_irradiance(&self) -> bool {
+        self.irradiance.unwrap_or(false)
+    }
+
+    pub fn get_sky_view_directions(&self) -> usize {
+        match self.sky_view_directions {
+            Some(directions) => directions.clamp(4, 360),
+            None => match self.shadow_quality {
+                ShadowQuality::Fast => 16,
+                ShadowQuality::Normal => 32,
+                ShadowQuality::High => 64,
+                ShadowQuality::Scientific => 128,
+            },
+        }
+    }
+
+    pub fn get_horizon_sectors(&self) -> usize {
+        match self.horizon_sectors {
+            Some(sectors) => sectors.clamp(8, 720),
+            None => match self.shadow_quality {
+                ShadowQuality::Fast => 36,
+                ShadowQuality::Normal => 72,
+                ShadowQuality::High => 180,
+                ShadowQuality::Scientific => 360,
+            },
+        }
+    }
+}
+
+/// A PV array drawn on the map, with its module and inverter parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PvArray {
+    pub polygon: Option<Vec<Vec<f64>>>, // Array outline in map coordinates (None = the AOI)
+    pub tilt: f64,                      // Module tilt from horizontal, degrees
+    pub azimuth: f64,                   // Direction the modules face, degrees clockwise from north
+    pub module_efficiency: f64, // Fraction of plane-of-array irradiance converted (e.g. 0.20)
+    pub temperature_coefficient: f64, // Power change per °C above 25 °C, in %/°C (e.g. -0.35)
+    pub inverter_losses: f64,   // Fraction of DC power lost in the inverter (e.g. 0.04)
+    pub noct: Option<f64>,      // Nominal operating cell temperature (None = 45 °C)
+    pub ambient_temperature: Option<f64>, // Air temperature for the cell model (None = 20 °C)
+    pub albedo: Option<f64>,    // Ground reflectance for reflected irradiance (None = 0.2)
+}
+
+impl PvArray {
+    pub fn get_noct(&self) -> f64 {
+        self.noct.unwrap_or(45.0)
+    }
+
+    pub fn get_ambient_temperature(&self) -> f64 {
+        self.ambient_temperature.unwrap_or(20.0)
+    }
+
+    pub fn get_albedo(&self) -> f64 {
+        self.albedo.unwrap_or(0.2).clamp(0.0, 1.0)
+    }
+}
+
+/// A proposed building burned into the DSM for what-if runs, in raster coordinates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildingFootprint {
+    pub polygon: Vec<Vec<f64>>,            // Exterior ring as [x, y] points
+    pub holes: Option<Vec<Vec<Vec<f64>>>>, // Interior rings such as courtyards (None = none)
+    pub height: f64,                       // Eave height above the terrain, metres (flat roof top)
+    pub roof_shape: Option<RoofShape>,     // None = Flat
+    pub roof_height: Option<f64>, // Ridge rise above the eaves for pitched roofs (None = 3 m)
+}
+
+impl BuildingFootprint {
+    pub fn get_roof_shape(&self) -> RoofShape {
+        self.roof_shape.unwrap_or(RoofShape::Flat)
+    }
+
+    pub fn get_roof_height(&self) -> f64 {
+        match self.get_roof_shape() {
+            RoofShape::Flat => 0.0,
+            RoofShape::Gable | RoofShape::Hip => self.roof_height.unwrap_or(3.0).max(0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoofShape {
+    /// Level roof at the eave height
+    Flat,
+    /// Two planes meeting at a ridge along the footprint's longest edge
+    Gable,
+    /// Gable ridge with the ends sloped down at the same pitch
+    Hip,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ShadowQuality {
+    Fast,
+    Normal,
+    High,
+    Scientific,
+}
+
+/// Day of the year as (month, day), which orders like the dates it stands for
+pub type MonthDay = (u32, u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShadowAlgorithm {
+    /// March a ray toward the sun from every relevant cell for every timestamp
+    RayMarching,
+    /// Precompute a horizon profile per cell once; cells the sun clears are lit without
+    /// marching, the rest are ray-marched. Falls back to ray marching when the profile
+    /// would not fit in memory or would trace more rays than the sun positions need.
+    HorizonProfile,
+    /// Sweep lines parallel to the sun azimuth carrying the running shadow height.
+    /// Falls back to ray marching when the Earth curvature correction is on.
+    SweepLine,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RayTraversal {
+    /// Sample terrain at fixed sub-pixel steps along the ray
+    FixedStep,
+    /// Visit every grid cell the ray crosses and test heights at the cell edges
+    GridExact,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SlopeSource {
+    /// Slope of the surface model, so roofs get their own pitch and aspect
+    Dsm,
+    /// Slope of the bare terrain, ignoring buildings and trees
+    Dtm,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResultStorage {
+    /// Keep the whole time series in one in-memory array
+    Memory,
+    /// Stream each shadow map to a scratch file as it is computed
+    Disk,
+    /// Use disk once the time series would exceed the in-memory budget
+    Auto,
+}
+
+#[derive(Debug, Clone)]
+pub struct RasterData {
+    pub data: Array3<f32>,
+    pub transform: [f64; 6],
+    pub projection: String,
+    pub no_data_value: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SunPosition {
+    pub azimuth: f64,
+    pub elevation: f64,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ShadowResult {
+    pub shadow_fraction: ShadowStore,
+    pub timestamps: Vec<DateTime<Utc>>,
+    pub summary_stats: SummaryStats,
+    pub unique_sun_positions: Option<usize>, // Distinct shadow maps computed (None = loaded from file)
+}
+
+#[derive(Debug, Clone)]
+pub struct SummaryStats {
+    pub total_shadow_hours: Array3<f32>,
+    pub avg_shadow_percentage: Array3<f32>,
+    pub max_consecutive_shadow: Array3<f32>,
+    pub morning_shadow_hours: Array3<f32>,
+    pub noon_shadow_hours: Array3<f32>,
+    pub afternoon_shadow_hours: Array3<f32>,
+    pub solar_efficiency_percentage: Array3<f32>,
+    pub daily_solar_hours: Array3<f32>,
+    pub total_available_solar_hours: Array3<f32>,
+    // Optional layers, only present when requested in the config
+    pub sky_view_factor: Option<Array3<f32>>,
+    pub direct_irradiation: Option<Array3<f32>>, // kWh/m²
+    pub diffuse_irradiation: Option<Array3<f32>>, // kWh/m²
+    pub global_irradiation: Option<Array3<f32>>, // kWh/m²
+    pub slope: Option<Array3<f32>>,              // Degrees from horizontal
+    pub aspect: Option<Array3<f32>>,             // Degrees clockwise from north
+    pub self_shaded_hours: Option<Array3<f32>>,
+    pub effective_sun_hours: Option<Array3<f32>>, // Sun hours weighted by weather DNI / clear-sky DNI
+}
+
+/// GeoTIFF band descriptions of the optional summary layers, in the order they are
+/// written after the nine fixed summary bands, with their display names
+pub const OPTIONAL_SUMMARY_BANDS: [(&str, &str); 8] = [
+    ("Sky_View_Factor_(0-1)", "Sky View Factor"),
+    ("Direct_Irradiation_(kWh/m2)", "Direct Irradiation"),
+    ("Diffuse_Irradiation_(kWh/m2)", "Diffuse Irradiation"),
+    ("Global_Irradiation_(kWh/m2)", "Global Irradiation"),
+    ("Slope_(deg)", "Slope"),
+    ("Aspect_(deg_from_north)", "Aspect"),
+    ("Self_Shaded_Hours", "Self-Shaded Hours"),
+    ("Effective_Sun_Hours", "Effective Sun Hours"),
+];
+
+/// Field names of all summary layers, fixed layers first, in export order
+pub const SUMMARY_LAYER_NAMES: [&str; 17] = [
+    "total_shadow_hours",
+    "avg_shadow_percentage",
+    "max_consecutive_shadow",
+    "morning_shadow_hours",
+    "noon_shadow_hours",
+    "afternoon_shadow_hours",
+    "solar_efficiency_percentage",
+    "daily_solar_hours",
+    "total_available_solar_hours",
+    "sky_view_factor",
+    "direct_irradiation",
+    "diffuse_irradiation",
+    "global_irradiation",
+    "slope",
+    "aspect",
+    "self_shaded_hours",
+    "effective_sun_hours",
+];
+
+impl SummaryStats {
+    fn optional_slots(&mut self) -> [&mut Option<Array3<f32>>; 8] {
+        [
+            &mut self.sky_view_factor,
+            &mut self.direct_irradiation,
+            &mut self.diffuse_irradiation,
+            &mut self.global_irradiation,
+            &mut self.slope,
+            &mut self.aspect,
+            &mut self.self_shaded_hours,
+            &mut self.effective_sun_hours,
+        ]
+    }
+
+    /// Optional layers that are present, with their band descriptions, in export order
+    pub fn optional_layers(&self) -> Vec<(&'static str, &Array3<f32>)> {
+        [
+            &self.sky_view_factor,
+            &self.direct_irradiation,
+            &self.diffuse_irradiation,
+            &self.global_irradiation,
+            &self.slope,
+            &self.aspect,
+            &self.self_shaded_hours,
+            &self.effective_sun_hours,
+        ]
+        .into_iter()
+        .zip(OPTIONAL_SUMMARY_BANDS)
+        .filter_map(|(layer, (description, _))| layer.as_ref().map(|layer| (description, layer)))
+        .collect()
+    }
+
+    pub fn optional_layers_mut(&mut self) -> Vec<(&'static str, &mut Array3<f32>)> {
+        self.optional_slots()
+            .into_iter()
+            .zip(OPTIONAL_SUMMARY_BANDS)
+            .filter_map(|(layer, (description, _))| {
+                layer.as_mut().map(|layer| (description, layer))
+            })
+            .collect()
+    }
+
+    /// Summary layer by field name, e.g. `total_shadow_hours` or `sky_view_factor`
+    pub fn layer(&self, name: &str) -> Option<&Array3<f32>> {
+        match name {
+            "total_shadow_hours" => Some(&self.total_shadow_hours),
+            "avg_shadow_percentage" => Some(&self.avg_shadow_percentage),
+            "max_consecutive_shadow" => Some(&self.max_consecutive_shadow),
+            "morning_shadow_hours" => Some(&self.morning_shadow_hours),
+            "noon_shadow_hours" => Some(&self.noon_shadow_hours),
+            "afternoon_shadow_hours" => Some(&self.afternoon_shadow_hours),
+            "solar_efficiency_percentage" => Some(&self.solar_efficiency_percentage),
+            "daily_solar_hours" => Some(&self.daily_solar_hours),
+            "total_available_solar_hours" => Some(&self.total_available_solar_hours),
+            "sky_view_factor" => self.sky_view_factor.as_ref(),
+            "direct_irradiation" => self.direct_irradiation.as_ref(),
+            "diffuse_irradiation" => self.diffuse_irradiation.as_ref(),
+            "global_irradiation" => self.global_irradiation.as_ref(),
+            "slope" => self.slope.as_ref(),
+            "aspect" => self.aspect.as_ref(),
+            "self_shaded_hours" => self.self_shaded_hours.as_ref(),
+            "effective_sun_hours" => self.effective_sun_hours.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Layers that are present, by field name, in export order
+    pub fn layers(&self) -> Vec<(&'static str, &Array3<f32>)> {
+        SUMMARY_LAYER_NAMES
+            .into_iter()
+            .filter_map(|name| self.layer(name).map(|layer| (name, layer)))
+            .collect()
+    }
+
+    /// Store a layer read back from a results file. Returns false if the band
+    /// description is not an optional summary layer.
+    pub fn set_optional_layer(&mut self, description: &str, layer: Array3<f32>) -> bool {
+        match OPTIONAL_SUMMARY_BANDS
+            .iter()
+            .position(|(band, _)| *band == description)
+        {
+            Some(index) => {
+                *self.optional_slots()[index] = Some(layer);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonthlyShadowStats {
+    pub month: u32,
+    pub year: i32,
+    pub total_shadow_hours: Array2<f32>,
+    pub avg_shadow_percentage: Array2<f32>,
+    pub max_consecutive_shadow: Array2<f32>,
+    pub solar_efficiency_percentage: Array2<f32>,
+    pub days_in_analysis: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeasonStats {
+    pub season_name: String,
+    pub months: Vec<u32>,
+    pub total_shadow_hours: Array2<f32>,
+    pub avg_shadow_percentage: Array2<f32>,
+    pub max_consecutive_shadow: Array2<f32>,
+    pub solar_efficiency_percentage: Array2<f32>,
+    pub total_days: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeasonalAnalysis {
+    pub monthly_stats: Vec<MonthlyShadowStats>,
+    pub seasonal_summaries: Vec<SeasonStats>,
+    pub analysis_period: (chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>),
+}
+
+/// Shared flag that lets a running calculation be stopped from another thread
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[allow(dead_code)]
+pub enum ShadowError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("GDAL error: {0}")]
+    Gdal(#[from] gdal::errors::GdalError),
+    #[error("Invalid configuration: {0}")]
+    Config(String),
+    #[error("Calculation cancelled")]
+    Cancelled,
+    #[allow(dead_code)]
+    #[error("General error: {0}")]
+    General(String),
+}
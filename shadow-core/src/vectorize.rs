@@ -0,0 +1,236 @@
+use crate::polygonize;
+use crate::types::{ShadowError, ShadowResult};
+use chrono::{DateTime, Utc};
+use gdal::spatial_ref::SpatialRef;
+use gdal::vector::{FieldValue, LayerAccess, OGRFieldType, OGRwkbGeometryType, ToGdal};
+use gdal::{Dataset, DriverManager, LayerOptions};
+use geo::algorithm::area::Area;
+use geo::algorithm::map_coords::MapCoords;
+use geo::algorithm::orient::{Direction, Orient};
+use ndarray::Array2;
+use serde::Deserialize;
+use std::path::Path;
+
+/// Shadow fraction at or above which a cell counts as shaded by default
+pub const DEFAULT_SHADOW_THRESHOLD: f32 = 0.5;
+
+/// Shadow map to trace into polygons
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind")]
+pub enum VectorSource {
+    /// One time slice of `shadow_fraction`
+    TimeSlice { index: usize },
+    /// Every time slice, in one layer with a timestamp per feature
+    TimeSeries,
+    /// A summary layer by field name, e.g. `total_shadow_hours`
+    Summary { layer: String },
+}
+
+/// Which cells of a map become polygons
+#[derive(Debug, Clone, Copy)]
+pub struct CellFilter {
+    pub threshold: f32,
+    /// Keep cells below the threshold instead of at or above it
+    pub below: bool,
+}
+
+impl CellFilter {
+    fn keeps(&self, value: f32) -> bool {
+        // NaN cells lie outside the AOI and are never kept
+        if self.below {
+            value < self.threshold
+        } else {
+            value >= self.threshold
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VectorFormat {
+    GeoJson,
+    GeoPackage,
+}
+
+impl VectorFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "geojson" => Some(Self::GeoJson),
+            "gpkg" | "geopackage" => Some(Self::GeoPackage),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::GeoJson => "geojson",
+            Self::GeoPackage => "gpkg",
+        }
+    }
+
+    fn driver_name(&self) -> &'static str {
+        match self {
+            Self::GeoJson => "GeoJSON",
+            Self::GeoPackage => "GPKG",
+        }
+    }
+}
+
+/// Attribute fields of every shadow polygon layer
+const FIELDS: [(&str, OGRFieldType::Type); 7] = [
+    ("source", OGRFieldType::OFTString),
+    ("timestamp", OGRFieldType::OFTDateTime),
+    ("time_index", OGRFieldType::OFTInteger),
+    ("threshold", OGRFieldType::OFTReal),
+    ("area_m2", OGRFieldType::OFTReal),
+    ("period_start", OGRFieldType::OFTDateTime),
+    ("period_end", OGRFieldType::OFTDateTime),
+];
+
+/// Trace the kept cells of a shadow map into polygons and write them as a single
+/// layer named `shadows`, in the raster's coordinate system.
+///
+/// Each connected region is one feature. Time slices carry their `timestamp` and
+/// `time_index`; summary layers leave both unset. Every feature has the analysis
+/// period, so layers from different runs can be told apart. Returns the number of
+/// features written.
+#[allow(clippy::too_many_arguments)]
+pub fn write_shadow_polygons(
+    path: &Path,
+    format: VectorFormat,
+    results: &ShadowResult,
+    source: &VectorSource,
+    filter: CellFilter,
+    transform: &[f64; 6],
+    projection: &str,
+    cell_area: f64,
+) -> Result<usize, ShadowError> {
+    let driver = DriverManager::get_driver_by_name(format.driver_name())?;
+    let mut dataset = driver.create_vector_only(path)?;
+
+    // GeoPackage inserts are far faster inside one transaction; GeoJSON has none
+    match format {
+        VectorFormat::GeoPackage => {
+            let mut transaction = dataset.start_transaction()?;
+            let count = write_layer(
+                &mut transaction,
+                results,
+                source,
+                filter,
+                transform,
+                projection,
+                cell_area,
+            )?;
+            transaction.commit()?;
+            Ok(count)
+        }
+        VectorFormat::GeoJson => write_layer(
+            &mut dataset,
+            results,
+            source,
+            filter,
+            transform,
+            projection,
+            cell_area,
+        ),
+    }
+}
+
+fn write_layer(
+    dataset: &mut Dataset,
+    results: &ShadowResult,
+    source: &VectorSource,
+    filter: CellFilter,
+    transform: &[f64; 6],
+    projection: &str,
+    cell_area: f64,
+) -> Result<usize, ShadowError> {
+    let srs = if projection.is_empty() {
+        None
+    } else {
+        Some(SpatialRef::from_wkt(projection)?)
+    };
+    let mut layer = dataset.create_layer(LayerOptions {
+        name: "shadows",
+        srs: srs.as_ref(),
+        ty: OGRwkbGeometryType::wkbPolygon,
+        options: None,
+    })?;
+    layer.create_defn_fields(&FIELDS)?;
+
+    let period = match (results.timestamps.first(), results.timestamps.last()) {
+        (Some(start), Some(end)) => Some((*start, *end)),
+        _ => None,
+    };
+    let mut count = 0;
+    let mut write_map = |map: &Array2<f32>,
+                         source_name: &str,
+                         time: Option<(usize, DateTime<Utc>)>|
+     -> Result<(), ShadowError> {
+        let cells: Vec<(usize, usize)> = map
+            .indexed_iter()
+            .filter(|(_, &value)| filter.keeps(value))
+            .map(|(cell, _)| cell)
+            .collect();
+
+        for polygon in polygonize::cell_polygons(&cells) {
+            // Pixel-space area is the number of cells, holes excluded
+            let area = polygon.unsigned_area() * cell_area;
+            let world = polygon
+                .map_coords(|coord| geo_types::Coord {
+                    x: transform[0] + coord.x * transform[1] + coord.y * transform[2],
+                    y: transform[3] + coord.x * transform[4] + coord.y * transform[5],
+                })
+                .orient(Direction::Default);
+
+            let mut names = vec!["source", "threshold", "area_m2"];
+            let mut values = vec![
+                FieldValue::StringValue(source_name.to_string()),
+                FieldValue::RealValue(filter.threshold as f64),
+                FieldValue::RealValue(area),
+            ];
+            if let Some((index, timestamp)) = time {
+                names.extend(["timestamp", "time_index"]);
+                values.push(FieldValue::DateTimeValue(timestamp.into()));
+                values.push(FieldValue::IntegerValue(index as i32));
+            }
+            if let Some((start, end)) = period {
+                names.extend(["period_start", "period_end"]);
+                values.push(FieldValue::DateTimeValue(start.into()));
+                values.push(FieldValue::DateTimeValue(end.into()));
+            }
+            layer.create_feature_fields(world.to_gdal()?, &names, &values)?;
+            count += 1;
+        }
+        Ok(())
+    };
+
+    match source {
+        VectorSource::TimeSlice { index } => {
+            let timestamp = results.timestamps.get(*index).ok_or_else(|| {
+                ShadowError::Config(format!(
+                    "Time index {} is out of range (0-{})",
+                    index,
+                    results.timestamps.len().saturating_sub(1)
+                ))
+            })?;
+            let slice = results.shadow_fraction.read_slice(*index)?;
+            write_map(&slice, "shadow_fraction", Some((*index, *timestamp)))?;
+        }
+        VectorSource::TimeSeries => {
+            for (index, timestamp) in results.timestamps.iter().enumerate() {
+                let slice = results.shadow_fraction.read_slice(index)?;
+                write_map(&slice, "shadow_fraction", Some((index, *timestamp)))?;
+            }
+        }
+        VectorSource::Summary { layer: name } => {
+            let summary = results.summary_stats.layer(name).ok_or_else(|| {
+                ShadowError::Config(format!("No summary layer named '{}' in the results", name))
+            })?;
+            let map = summary.index_axis(ndarray::Axis(0), 0).to_owned();
+            write_map(&map, name, None)?;
+        }
+    }
+
+    println!("Wrote {} shadow polygons", count);
+    Ok(count)
+}
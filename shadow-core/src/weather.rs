@@ -0,0 +1,411 @@
+use crate::irradiance::ClearSkyIrradiance;
+use crate::types::ShadowError;
+use chrono::{DateTime, Datelike, NaiveDate, Timelike, Utc};
+use std::path::Path;
+
+/// Hours in the 365-day reference year weather records are placed on
+const HOURS_PER_YEAR: f64 = 8760.0;
+
+/// Header lines before the hourly records of an EPW file
+const EPW_HEADER_LINES: usize = 8;
+
+/// One weather record, placed at the middle of its interval
+#[derive(Debug, Clone, Copy)]
+struct WeatherRecord {
+    /// Hours since 1 January 00:00 UTC in a 365-day year
+    hour_of_year: f64,
+    direct_normal: f64,
+    diffuse_horizontal: f64,
+}
+
+/// Measured or typical irradiance for a year, from an EPW or TMY CSV file.
+///
+/// Records are matched to analysis timestamps by time of year only, so a typical
+/// meteorological year can weight an analysis of any year. 29 February uses the
+/// records of 28 February.
+#[derive(Debug, Clone)]
+pub struct WeatherData {
+    records: Vec<WeatherRecord>,
+}
+
+impl WeatherData {
+    /// Read an EnergyPlus `.epw` file, or a TMY3 or NSRDB/SAM CSV file
+    pub fn from_path(path: &Path) -> Result<Self, ShadowError> {
+        let text = std::fs::read_to_string(path)?;
+        let is_epw = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("epw"))
+            || text.starts_with("LOCATION");
+        let records = if is_epw {
+            parse_epw(&text)?
+        } else {
+            parse_tmy_csv(&text)?
+        };
+        Self::from_records(records)
+    }
+
+    fn from_records(mut records: Vec<WeatherRecord>) -> Result<Self, ShadowError> {
+        if records.is_empty() {
+            return Err(ShadowError::Config(
+                "Weather file has no usable irradiance records".to_string(),
+            ));
+        }
+        records.sort_by(|a, b| a.hour_of_year.total_cmp(&b.hour_of_year));
+        println!(
+            "Weather data: {} records over {:.0} hours of the year",
+            records.len(),
+            records.last().unwrap().hour_of_year - records[0].hour_of_year
+        );
+        Ok(Self { records })
+    }
+
+    /// Irradiance at a timestamp, interpolated linearly between the two nearest
+    /// records and wrapping over New Year
+    pub fn irradiance_at(&self, timestamp: &DateTime<Utc>) -> ClearSkyIrradiance {
+        let hour = hour_of_year(timestamp);
+        let next = self
+            .records
+            .partition_point(|record| record.hour_of_year <= hour);
+
+        let (before, before_hour) = match next {
+            0 => {
+                let last = self.records[self.records.len() - 1];
+                (last, last.hour_of_year - HOURS_PER_YEAR)
+            }
+            n => (self.records[n - 1], self.records[n - 1].hour_of_year),
+        };
+        let (after, after_hour) = match self.records.get(next) {
+            Some(record) => (*record, record.hour_of_year),
+            None => (
+                self.records[0],
+                self.records[0].hour_of_year + HOURS_PER_YEAR,
+            ),
+        };
+
+        let weight = if after_hour > before_hour {
+            ((hour - before_hour) / (after_hour - before_hour)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        ClearSkyIrradiance {
+            direct_normal: before.direct_normal
+                + (after.direct_normal - before.direct_normal) * weight,
+            diffuse_horizontal: before.diffuse_horizontal
+                + (after.diffuse_horizontal - before.diffuse_horizontal) * weight,
+        }
+    }
+}
+
+/// Hours since 1 January 00:00 of the timestamp's year, with leap days folded
+/// onto 28 February so every year lines up with the 365-day reference year
+fn hour_of_year(timestamp: &DateTime<Utc>) -> f64 {
+    let mut day = timestamp.ordinal0();
+    let leap_year = NaiveDate::from_ymd_opt(timestamp.year(), 2, 29).is_some();
+    if leap_year && day >= 59 {
+        day -= 1;
+    }
+    day as f64 * 24.0
+        + timestamp.hour() as f64
+        + timestamp.minute() as f64 / 60.0
+        + timestamp.second() as f64 / 3600.0
+}
+
+/// Place a record given in local standard time on the UTC reference year. `hour`
+/// is the middle of the record's interval in hours after local midnight.
+fn reference_hour(month: u32, day: u32, hour: f64, utc_offset: f64) -> Option<f64> {
+    // Leap-day records would duplicate 28 February
+    let date = NaiveDate::from_ymd_opt(2001, month, day)?;
+    let hour = date.ordinal0() as f64 * 24.0 + hour - utc_offset;
+    Some(hour.rem_euclid(HOURS_PER_YEAR))
+}
+
+/// Irradiance value, or None when it is flagged as missing
+fn irradiance_value(field: Option<&str>) -> Option<f64> {
+    let value: f64 = field?.trim().parse().ok()?;
+    (0.0..9999.0).contains(&value).then_some(value)
+}
+
+/// EPW records are hourly in local standard time, with hour 1 covering 00:00–01:00.
+/// The UTC offset is the ninth field of the LOCATION line.
+fn parse_epw(text: &str) -> Result<Vec<WeatherRecord>, ShadowError> {
+    let location = text.lines().next().unwrap_or_default();
+    let utc_offset: f64 = location
+        .split(',')
+        .nth(8)
+        .and_then(|field| field.trim().parse().ok())
+        .ok_or_else(|| {
+            ShadowError::Config("EPW file has no time zone in its LOCATION line".to_string())
+        })?;
+
+    let records = text
+        .lines()
+        .skip(EPW_HEADER_LINES)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').collect();
+            let month = fields.get(1)?.trim().parse().ok()?;
+            let day = fields.get(2)?.trim().parse().ok()?;
+            let hour: f64 = fields.get(3)?.trim().parse().ok()?;
+            Some(WeatherRecord {
+                hour_of_year: reference_hour(month, day, hour - 0.5, utc_offset)?,
+                direct_normal: irradiance_value(fields.get(14).copied())?,
+                diffuse_horizontal: irradiance_value(fields.get(15).copied())?,
+            })
+        })
+        .collect();
+    Ok(records)
+}
+
+/// Column name of a CSV header field without its unit suffix, e.g. `DNI (W/m^2)`
+fn column_name(field: &str) -> &str {
+    field.split('(').next().unwrap_or_default().trim()
+}
+
+/// Index of the header field naming a column, ignoring units and case
+fn find_column(header: &[&str], name: &str) -> Option<usize> {
+    header
+        .iter()
+        .position(|field| column_name(field).eq_ignore_ascii_case(name))
+}
+
+/// TMY3 CSVs have a station line with the time zone in its fourth field, then a
+/// header with `Date (MM/DD/YYYY)` and hour-ending `Time (HH:MM)` columns.
+/// NSRDB/SAM CSVs have a metadata header and value line with a `Time Zone` column,
+/// then a header with `Month`, `Day`, `Hour` and `Minute` columns marking the start
+/// of each interval. Both are in local standard time. The header is the first line
+/// naming DNI, DHI and Date or Month columns, so metadata fields such as `DNI Units`
+/// are not mistaken for it.
+fn parse_tmy_csv(text: &str) -> Result<Vec<WeatherRecord>, ShadowError> {
+    let lines: Vec<Vec<&str>> = text
+        .lines()
+        .map(|line| line.split(',').map(|field| field.trim()).collect())
+        .collect();
+
+    let header_index = lines
+        .iter()
+        .position(|fields| {
+            ["DNI", "DHI"]
+                .iter()
+                .all(|name| find_column(fields, name).is_some())
+                && ["Date", "Month"]
+                    .iter()
+                    .any(|name| find_column(fields, name).is_some())
+        })
+        .ok_or_else(|| {
+            ShadowError::Config("Weather CSV has no header with DNI and DHI columns".to_string())
+        })?;
+    let header = &lines[header_index];
+    let column = |name: &str| find_column(header, name);
+    let dni_column = column("DNI").unwrap();
+    let dhi_column = column("DHI").unwrap();
+
+    let utc_offset = match header_index {
+        // TMY3: station line, then the header
+        1 => lines[0].get(3).and_then(|field| field.parse::<f64>().ok()),
+        // NSRDB/SAM: metadata names and values above the header
+        _ if header_index >= 2 => lines[0]
+            .iter()
+            .position(|field| field.eq_ignore_ascii_case("Time Zone"))
+            .and_then(|index| lines[1].get(index))
+            .and_then(|field| field.parse::<f64>().ok()),
+        _ => None,
+    }
+    .ok_or_else(|| ShadowError::Config("Weather CSV has no time zone".to_string()))?;
+
+    let rows = &lines[header_index + 1..];
+    let records: Vec<WeatherRecord> = match (column("Date"), column("Time")) {
+        (Some(date_column), Some(time_column)) => rows
+            .iter()
+            .filter_map(|fields| {
+                let mut date = fields.get(date_column)?.split('/');
+                let month = date.next()?.parse().ok()?;
+                let day = date.next()?.parse().ok()?;
+                let mut time = fields.get(time_column)?.split(':');
+                let hour: f64 = time.next()?.parse().ok()?;
+                let minute: f64 = time.next().unwrap_or("0").parse().ok()?;
+                Some(WeatherRecord {
+                    hour_of_year: reference_hour(
+                        month,
+                        day,
+                        hour + minute / 60.0 - 0.5,
+                        utc_offset,
+                    )?,
+                    direct_normal: irradiance_value(fields.get(dni_column).copied())?,
+                    diffuse_horizontal: irradiance_value(fields.get(dhi_column).copied())?,
+                })
+            })
+            .collect(),
+        _ => {
+            let (month_column, day_column, hour_column) =
+                match (column("Month"), column("Day"), column("Hour")) {
+                    (Some(month), Some(day), Some(hour)) => (month, day, hour),
+                    _ => {
+                        return Err(ShadowError::Config(
+                            "Weather CSV needs Date/Time or Month/Day/Hour columns".to_string(),
+                        ))
+                    }
+                };
+            let minute_column = column("Minute");
+            let parsed: Vec<(u32, u32, f64, f64, f64)> = rows
+                .iter()
+                .filter_map(|fields| {
+                    let minute: f64 = match minute_column {
+                        Some(index) => fields.get(index)?.parse().ok()?,
+                        None => 0.0,
+                    };
+                    Some((
+                        fields.get(month_column)?.parse().ok()?,
+                        fields.get(day_column)?.parse().ok()?,
+                        fields.get(hour_column)?.parse::<f64>().ok()? + minute / 60.0,
+                        irradiance_value(fields.get(dni_column).copied())?,
+                        irradiance_value(fields.get(dhi_column).copied())?,
+                    ))
+                })
+                .collect();
+
+            // Intervals start at the given time; half-hourly files have two per hour
+            let interval = match parsed.as_slice() {
+                [(_, _, first, ..), (_, _, second, ..), ..] if second > first => second - first,
+                _ => 1.0,
+            };
+            parsed
+                .into_iter()
+                .filter_map(|(month, day, hour, direct_normal, diffuse_horizontal)| {
+                    Some(WeatherRecord {
+                        hour_of_year: reference_hour(
+                            month,
+                            day,
+                            hour + interval / 2.0,
+                            utc_offset,
+                        )?,
+                        direct_normal,
+                        diffuse_horizontal,
+                    })
+                })
+                .collect()
+        }
+    };
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// EPW text with the given UTC offset and (month, day, hour, DNI, DHI) records
+    fn epw(utc_offset: f64, records: &[(u32, u32, u32, f64, f64)]) -> String {
+        let mut text = format!(
+            "LOCATION,Test,-,-,-,000000,40.0,-105.0,{},1600\n",
+            utc_offset
+        );
+        for line in [
+            "DESIGN",
+            "TYPICAL",
+            "GROUND",
+            "HOLIDAYS",
+            "COMMENTS 1",
+            "COMMENTS 2",
+        ] {
+            text.push_str(line);
+            text.push('\n');
+        }
+        text.push_str("DATA PERIODS,1,1,Data,Sunday,1/1,12/31\n");
+        for &(month, day, hour, dni, dhi) in records {
+            text.push_str(&format!(
+                "1999,{},{},{},60,x,20,10,50,84000,0,0,300,0,{},{},0\n",
+                month, day, hour, dni, dhi
+            ));
+        }
+        text
+    }
+
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn assert_irradiance(weather: &WeatherData, timestamp: DateTime<Utc>, expected: (f64, f64)) {
+        let sky = weather.irradiance_at(&timestamp);
+        assert!(
+            (sky.direct_normal - expected.0).abs() < 1e-9
+                && (sky.diffuse_horizontal - expected.1).abs() < 1e-9,
+            "{}: ({}, {}) != {:?}",
+            timestamp,
+            sky.direct_normal,
+            sky.diffuse_horizontal,
+            expected
+        );
+    }
+
+    #[test]
+    fn epw_records_shift_from_local_standard_time() {
+        // UTC-5: hour 13 covers 12:00-13:00 local, centred on 17:30 UTC
+        let text = epw(-5.0, &[(1, 1, 13, 500.0, 100.0), (1, 1, 14, 700.0, 80.0)]);
+        let weather = WeatherData::from_records(parse_epw(&text).unwrap()).unwrap();
+
+        assert_irradiance(&weather, at(2023, 1, 1, 17, 30), (500.0, 100.0));
+        assert_irradiance(&weather, at(2023, 1, 1, 18, 0), (600.0, 90.0));
+        assert_irradiance(&weather, at(2023, 1, 1, 18, 30), (700.0, 80.0));
+    }
+
+    #[test]
+    fn interpolation_wraps_over_new_year() {
+        let text = epw(0.0, &[(1, 1, 1, 300.0, 30.0), (12, 31, 24, 100.0, 10.0)]);
+        let weather = WeatherData::from_records(parse_epw(&text).unwrap()).unwrap();
+
+        // Midway between 31 December 23:30 and 1 January 00:30
+        assert_irradiance(&weather, at(2023, 1, 1, 0, 0), (200.0, 20.0));
+        assert_irradiance(&weather, at(2023, 12, 31, 23, 45), (150.0, 15.0));
+    }
+
+    #[test]
+    fn leap_day_uses_the_records_of_28_february() {
+        let text = epw(0.0, &[(2, 28, 13, 400.0, 40.0), (3, 1, 13, 600.0, 60.0)]);
+        let weather = WeatherData::from_records(parse_epw(&text).unwrap()).unwrap();
+
+        assert_irradiance(&weather, at(2024, 2, 28, 12, 30), (400.0, 40.0));
+        assert_irradiance(&weather, at(2024, 2, 29, 12, 30), (400.0, 40.0));
+        assert_irradiance(&weather, at(2024, 3, 1, 12, 30), (600.0, 60.0));
+        assert_irradiance(&weather, at(2023, 3, 1, 12, 30), (600.0, 60.0));
+    }
+
+    #[test]
+    fn tmy3_times_end_their_hour() {
+        let text = "\
+724940,\"SAN FRANCISCO INTL AP\",CA,-8.0,37.617,-122.400,2
+Date (MM/DD/YYYY),Time (HH:MM),ETR (W/m^2),GHI (W/m^2),DNI (W/m^2),DNI source,DHI (W/m^2)
+06/01/1988,09:00,900,500,610,1,120
+06/01/1988,10:00,1000,650,710,1,110
+";
+        let weather = WeatherData::from_records(parse_tmy_csv(text).unwrap()).unwrap();
+
+        // 08:00-09:00 PST is centred on 16:30 UTC
+        assert_irradiance(&weather, at(2021, 6, 1, 16, 30), (610.0, 120.0));
+        assert_irradiance(&weather, at(2021, 6, 1, 17, 30), (710.0, 110.0));
+    }
+
+    #[test]
+    fn nsrdb_header_follows_the_unit_metadata() {
+        // The metadata line names `DHI Units` and `DNI Units`, which are not columns
+        let text = "\
+Source,Location ID,City,State,Country,Latitude,Longitude,Time Zone,Elevation,DHI Units,DNI Units
+NSRDB,12345,-,-,-,40.0,-105.0,-7,1600,w/m2,w/m2
+Year,Month,Day,Hour,Minute,DHI,DNI,GHI
+2020,6,1,12,0,100,800,900
+2020,6,1,12,30,90,820,910
+";
+        let records = parse_tmy_csv(text).unwrap();
+        assert_eq!(records.len(), 2);
+        let weather = WeatherData::from_records(records).unwrap();
+
+        // Half-hour intervals starting 12:00 MST, centred on 19:15 UTC
+        assert_irradiance(&weather, at(2022, 6, 1, 19, 15), (800.0, 100.0));
+        assert_irradiance(&weather, at(2022, 6, 1, 19, 45), (820.0, 90.0));
+    }
+
+    #[test]
+    fn csv_without_irradiance_header_is_rejected() {
+        let text = "Source,DNI Units,DHI Units\nNSRDB,w/m2,w/m2\n";
+        assert!(matches!(parse_tmy_csv(text), Err(ShadowError::Config(_))));
+    }
+}
@@ -0,0 +1,311 @@
+//! Python extension module `shadow_calculator`, exposing the shadow engine and sun
+//! positions on numpy arrays for scripted studies.
+
+use chrono::{DateTime, Utc};
+use numpy::datetime::{units, Datetime};
+use numpy::{AllowTypeChange, PyArray1, PyArrayLike2, PyArrayMethods, PyReadonlyArray1};
+use pyo3::exceptions::{PyKeyboardInterrupt, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use shadow_core::footprints;
+use shadow_core::pipeline;
+use shadow_core::raster_io::RasterIO;
+use shadow_core::shadow_engine::ShadowEngine as Engine;
+use shadow_core::sun_position::SunCalculator as SunCalc;
+use shadow_core::types::{Config, ShadowError};
+use shadow_core::weather::WeatherData;
+use std::path::Path;
+
+fn engine_error(e: ShadowError) -> PyErr {
+    match e {
+        ShadowError::Cancelled => PyKeyboardInterrupt::new_err("Shadow calculation cancelled"),
+        ShadowError::Config(message) => PyValueError::new_err(message),
+        e => PyRuntimeError::new_err(format!("Shadow calculation failed: {}", e)),
+    }
+}
+
+/// Copy a numpy array of any dtype into a float32 grid
+fn to_grid(array: &PyArrayLike2<'_, f32, AllowTypeChange>) -> ndarray::Array2<f32> {
+    let view = array.as_array();
+    ndarray::Array2::from_shape_vec(view.dim(), view.iter().copied().collect())
+        .expect("shape matches the number of elements")
+}
+
+/// Config from a dict with the same fields as the app's JSON config. Paths are not
+/// needed for in-memory grids, and the AOI defaults to the whole grid.
+fn config_from_dict(
+    config: &Bound<'_, PyDict>,
+    transform: &[f64; 6],
+    (n_rows, n_cols): (usize, usize),
+) -> PyResult<Config> {
+    let json: String = config
+        .py()
+        .import("json")?
+        .call_method1("dumps", (config,))?
+        .extract()?;
+    let mut value: serde_json::Value = serde_json::from_str(&json)
+        .map_err(|e| PyValueError::new_err(format!("Invalid config: {}", e)))?;
+    let fields = value
+        .as_object_mut()
+        .ok_or_else(|| PyValueError::new_err("Config must be a dict"))?;
+
+    fields.entry("dtm_path").or_insert_with(|| "".into());
+    fields.entry("dsm_path").or_insert_with(|| "".into());
+    fields.entry("aoi").or_insert_with(|| {
+        let (min_x, max_y) = (transform[0], transform[3]);
+        let max_x = min_x + n_cols as f64 * transform[1];
+        let min_y = max_y + n_rows as f64 * transform[5]; // transform[5] is negative
+        serde_json::json!([
+            [min_x, min_y],
+            [max_x, min_y],
+            [max_x, max_y],
+            [min_x, max_y],
+            [min_x, min_y]
+        ])
+    });
+
+    serde_json::from_value(value)
+        .map_err(|e| PyValueError::new_err(format!("Invalid config: {}", e)))
+}
+
+/// Timestamps as a numpy `datetime64[s]` array
+fn datetime64_array<'py>(
+    py: Python<'py>,
+    timestamps: &[DateTime<Utc>],
+) -> Bound<'py, PyArray1<Datetime<units::Seconds>>> {
+    PyArray1::from_vec(
+        py,
+        timestamps
+            .iter()
+            .map(|timestamp| Datetime::from(timestamp.timestamp()))
+            .collect(),
+    )
+}
+
+/// Shadow engine on in-memory DTM/DSM grids.
+///
+/// `transform` is the GDAL geotransform of the grids and `config` a dict with the
+/// fields of the app's configuration (`start_date`, `end_date`, `hour_interval`,
+/// `angle_precision`, `shadow_quality`, ...). Like the app, the engine expects
+/// geographic coordinates; `resolution` is the cell size in metres and defaults to
+/// the cell width converted at the AOI's centre latitude. `proposed_buildings` are
+/// burned into the DSM, a `vegetation_path` raster must be on the same grid as the
+/// arrays, and a `weather_path` file weights timestamps as in the app.
+#[pyclass(name = "ShadowEngine")]
+struct PyShadowEngine {
+    engine: Engine,
+    config: Config,
+    transform: [f64; 6],
+}
+
+#[pymethods]
+impl PyShadowEngine {
+    #[new]
+    #[pyo3(signature = (dtm, dsm, transform, config, resolution = None))]
+    fn new(
+        dtm: PyArrayLike2<'_, f32, AllowTypeChange>,
+        dsm: PyArrayLike2<'_, f32, AllowTypeChange>,
+        transform: [f64; 6],
+        config: &Bound<'_, PyDict>,
+        resolution: Option<f64>,
+    ) -> PyResult<Self> {
+        let dtm = to_grid(&dtm);
+        let mut dsm = to_grid(&dsm);
+        if dtm.dim() != dsm.dim() {
+            return Err(PyValueError::new_err(format!(
+                "DTM grid {:?} does not match DSM grid {:?}",
+                dtm.dim(),
+                dsm.dim()
+            )));
+        }
+
+        let config = config_from_dict(config, &transform, dsm.dim())?;
+
+        // Proposed buildings replace whatever the DSM has inside their footprints
+        let burned = match config.proposed_buildings.as_deref() {
+            Some(buildings) if !buildings.is_empty() => Some(
+                footprints::burn_footprints(&mut dsm, &dtm, &transform, buildings)
+                    .map_err(engine_error)?,
+            ),
+            _ => None,
+        };
+
+        let vegetation = match &config.vegetation_path {
+            Some(path) => {
+                let raster = RasterIO::read_raster(Path::new(path)).map_err(engine_error)?;
+                let mut vegetation = raster.data.index_axis_move(ndarray::Axis(0), 0);
+                if vegetation.dim() != dsm.dim() {
+                    return Err(PyValueError::new_err(format!(
+                        "Vegetation raster grid {:?} does not match DSM grid {:?}",
+                        vegetation.dim(),
+                        dsm.dim()
+                    )));
+                }
+                // Trees on a proposed building's site are gone once it is built
+                if let Some(burned) = &burned {
+                    ndarray::Zip::from(&mut vegetation)
+                        .and(burned)
+                        .for_each(|value, &burned| {
+                            if burned {
+                                *value = f32::NAN;
+                            }
+                        });
+                }
+                Some(vegetation)
+            }
+            None => None,
+        };
+        let weather = match &config.weather_path {
+            Some(path) => Some(WeatherData::from_path(Path::new(path)).map_err(engine_error)?),
+            None => None,
+        };
+
+        let resolution = match resolution {
+            Some(resolution) => resolution,
+            None => {
+                let polygon = config.to_polygon().map_err(PyValueError::new_err)?;
+                let center_lat = polygon.exterior().coords().map(|c| c.y).sum::<f64>()
+                    / polygon.exterior().coords().count() as f64;
+                pipeline::degrees_to_meters(transform[1].abs(), center_lat)
+            }
+        };
+
+        let mut engine = Engine::new(dtm, dsm, resolution, transform, config.clone());
+        if let Some(vegetation) = &vegetation {
+            engine = engine.with_vegetation(vegetation);
+        }
+        if let Some(weather) = weather {
+            engine = engine.with_weather(weather);
+        }
+
+        Ok(Self {
+            engine,
+            config,
+            transform,
+        })
+    }
+
+    /// (rows, cols) of the grid
+    #[getter]
+    fn shape(&self) -> (usize, usize) {
+        self.engine.dimensions()
+    }
+
+    /// Run the analysis. Returns a dict with `shadow_fraction` (time, rows, cols),
+    /// `timestamps` (datetime64[s], UTC), `summary` (layer name to 2-D array) and
+    /// `unique_sun_positions`. Cells outside the AOI are NaN unless `mask_to_aoi` is
+    /// false.
+    #[pyo3(signature = (mask_to_aoi = true))]
+    fn calculate_shadows<'py>(
+        &self,
+        py: Python<'py>,
+        mask_to_aoi: bool,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let mut results = py
+            .detach(|| self.engine.calculate_shadows())
+            .map_err(engine_error)?;
+        if mask_to_aoi {
+            pipeline::mask_results_to_aoi(&mut results, &self.config, &self.transform)
+                .map_err(PyValueError::new_err)?;
+        }
+
+        let (n_times, n_rows, n_cols) = results.shadow_fraction.dim();
+        let shadow_fraction = py.detach(|| {
+            let mut values = Vec::with_capacity(n_times * n_rows * n_cols);
+            for index in 0..n_times {
+                values.extend(results.shadow_fraction.read_slice(index)?.iter().copied());
+            }
+            Ok::<_, ShadowError>(values)
+        });
+        let shadow_fraction = PyArray1::from_vec(py, shadow_fraction.map_err(engine_error)?)
+            .reshape([n_times, n_rows, n_cols])?;
+
+        let summary = PyDict::new(py);
+        for (name, layer) in results.summary_stats.layers() {
+            let values = layer
+                .index_axis(ndarray::Axis(0), 0)
+                .iter()
+                .copied()
+                .collect();
+            summary.set_item(
+                name,
+                PyArray1::from_vec(py, values).reshape([n_rows, n_cols])?,
+            )?;
+        }
+
+        let output = PyDict::new(py);
+        output.set_item("shadow_fraction", shadow_fraction)?;
+        output.set_item("timestamps", datetime64_array(py, &results.timestamps))?;
+        output.set_item("summary", summary)?;
+        output.set_item("unique_sun_positions", results.unique_sun_positions)?;
+        Ok(output)
+    }
+}
+
+/// Sun azimuth and elevation for a location, rounded to `angle_precision` degrees as
+/// the engine uses them. With `refraction`, elevations are apparent rather than
+/// geometric.
+#[pyclass(name = "SunCalculator")]
+struct PySunCalculator {
+    calculator: SunCalc,
+}
+
+#[pymethods]
+impl PySunCalculator {
+    #[new]
+    #[pyo3(signature = (latitude, longitude, angle_precision = 0.1, refraction = false))]
+    fn new(latitude: f64, longitude: f64, angle_precision: f64, refraction: bool) -> Self {
+        Self {
+            calculator: SunCalc::new_with_refraction(
+                latitude,
+                longitude,
+                angle_precision,
+                refraction,
+            ),
+        }
+    }
+
+    /// Positions at UTC timestamps (anything numpy converts to datetime64) as an
+    /// (n, 2) array of azimuth (degrees clockwise from north) and elevation
+    fn positions<'py>(
+        &self,
+        py: Python<'py>,
+        timestamps: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let seconds = py
+            .import("numpy")?
+            .call_method1("asarray", (timestamps, "datetime64[s]"))?
+            .call_method1("astype", ("int64",))?
+            .call_method0("ravel")?;
+        let seconds: PyReadonlyArray1<'py, i64> = seconds.extract()?;
+
+        let mut values = Vec::with_capacity(seconds.len()? * 2);
+        for &second in seconds.as_array() {
+            let timestamp = DateTime::from_timestamp(second, 0)
+                .ok_or_else(|| PyValueError::new_err("Timestamp out of range"))?;
+            let (azimuth, elevation) = self.calculator.get_exact_position(&timestamp);
+            values.extend([azimuth, elevation]);
+        }
+        let n = values.len() / 2;
+        Ok(PyArray1::from_vec(py, values).reshape([n, 2])?.into_any())
+    }
+
+    /// Hours between sunrise and sunset on the UTC date of a timestamp
+    fn solar_hours(&self, py: Python<'_>, date: &Bound<'_, PyAny>) -> PyResult<f64> {
+        let second: i64 = py
+            .import("numpy")?
+            .call_method1("datetime64", (date, "s"))?
+            .call_method1("astype", ("int64",))?
+            .extract()?;
+        let timestamp = DateTime::from_timestamp(second, 0)
+            .ok_or_else(|| PyValueError::new_err("Timestamp out of range"))?;
+        Ok(self.calculator.get_solar_hours_for_day(&timestamp))
+    }
+}
+
+#[pymodule]
+fn shadow_calculator(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyShadowEngine>()?;
+    module.add_class::<PySunCalculator>()?;
+    Ok(())
+}
@@ -3,24 +3,121 @@
     windows_subsystem = "windows"
 )]
 
-mod raster_io;
-mod shadow_engine;
-mod sun_position;
-mod types;
-
 use raster_io::RasterIO;
 use serde::{Deserialize, Serialize};
-use shadow_engine::ShadowEngine;
+use shadow_core::pipeline::{self, PreparedEngine};
+use shadow_core::progress::{ProgressSink, ProgressUpdate};
+use shadow_core::{
+    comparison, facade, footprints, pv, raster_io, roof, shadow_store, timelapse, types, vectorize,
+};
+use shadow_store::ShadowStore;
+use std::collections::HashMap;
 use std::path::Path;
-use std::sync::Mutex;
-use tauri::State;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{Manager, State};
 use types::*;
 
+/// Forwards engine progress to the frontend as `progress-update` events
+struct TauriProgress(tauri::AppHandle);
+
+impl ProgressSink for TauriProgress {
+    fn report(&self, update: &ProgressUpdate) {
+        let _ = self.0.emit_all("progress-update", update);
+    }
+}
+
+fn tauri_progress(app_handle: tauri::AppHandle) -> Option<Arc<dyn ProgressSink>> {
+    Some(Arc::new(TauriProgress(app_handle)))
+}
+
+/// Error of a command the user can cancel, so the frontend tells a cancelled run
+/// from a failure by `kind` instead of by the message text
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum CommandError {
+    Cancelled,
+    Failed { message: String },
+}
+
+impl CommandError {
+    /// Engine error, with `context` in front of the message of a failure
+    fn from_engine(e: ShadowError, context: &str) -> Self {
+        match e {
+            ShadowError::Cancelled => CommandError::Cancelled,
+            e => CommandError::Failed {
+                message: format!("{}: {}", context, e),
+            },
+        }
+    }
+}
+
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        CommandError::Failed { message }
+    }
+}
+
+impl From<&str> for CommandError {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
 struct AppState {
     current_config: Mutex<Option<Config>>,
     current_results: Mutex<Option<ShadowResult>>,
     raster_bounds: Mutex<Option<RasterBounds>>,
     clipped_raster_info: Mutex<Option<ClippedRasterInfo>>,
+    baseline: Mutex<Option<Baseline>>,
+    running: RunningCommands,
+}
+
+/// Cancellation tokens of the cancellable commands still running. Each command gets
+/// a fresh token, so starting one never clears a cancellation meant for another, and
+/// `cancel_calculation` cancels them all.
+#[derive(Default)]
+struct RunningCommands {
+    next_id: AtomicU64,
+    tokens: Mutex<HashMap<u64, CancellationToken>>,
+}
+
+impl RunningCommands {
+    fn start(&self) -> RunningCommand<'_> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let token = CancellationToken::new();
+        self.tokens.lock().unwrap().insert(id, token.clone());
+        RunningCommand {
+            commands: self,
+            id,
+            token,
+        }
+    }
+
+    fn cancel_all(&self) {
+        for token in self.tokens.lock().unwrap().values() {
+            token.cancel();
+        }
+    }
+}
+
+/// A running command's token, unregistered when the command returns
+struct RunningCommand<'a> {
+    commands: &'a RunningCommands,
+    id: u64,
+    token: CancellationToken,
+}
+
+impl Drop for RunningCommand<'_> {
+    fn drop(&mut self) {
+        self.commands.tokens.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Summary layers kept as the reference a scenario is compared against
+struct Baseline {
+    summary_stats: SummaryStats,
+    clipped_info: ClippedRasterInfo,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +125,40 @@ struct ClippedRasterInfo {
     bounds: RasterBounds,
     transform: Vec<f64>,
     dimensions: (usize, usize), // (rows, cols)
+    projection: String,
+}
+
+impl ClippedRasterInfo {
+    /// Bounds of a north-up grid from its transform and (rows, cols)
+    fn new(transform: &[f64; 6], dimensions: (usize, usize), projection: String) -> Self {
+        let (n_rows, n_cols) = dimensions;
+        let min_lat = transform[3] + (n_rows as f64 * transform[5]); // transform[5] is negative
+        let max_lat = transform[3];
+        Self {
+            bounds: RasterBounds {
+                min_lon: transform[0],
+                max_lon: transform[0] + (n_cols as f64 * transform[1]),
+                min_lat: min_lat.min(max_lat),
+                max_lat: max_lat.max(min_lat),
+            },
+            transform: transform.to_vec(),
+            dimensions,
+            projection,
+        }
+    }
+
+    fn geo_transform(&self) -> Result<[f64; 6], String> {
+        self.transform
+            .as_slice()
+            .try_into()
+            .map_err(|_| "Invalid raster transform".to_string())
+    }
+
+    /// Cell size in metres at the grid's centre latitude
+    fn resolution_meters(&self) -> f64 {
+        let center_lat = (self.bounds.min_lat + self.bounds.max_lat) / 2.0;
+        pipeline::degrees_to_meters(self.transform[1].abs(), center_lat)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -71,11 +202,13 @@ struct SeasonalAnalysisData {
 async fn load_rasters(
     dtm_path: String,
     dsm_path: String,
+    point_cloud_resolution: Option<f64>,
     state: State<'_, AppState>,
 ) -> Result<RasterBounds, String> {
-    let dtm = RasterIO::read_raster(Path::new(&dtm_path))
+    let resolution = point_cloud_resolution.unwrap_or(1.0).max(0.01);
+    let dtm = RasterIO::read_dtm(Path::new(&dtm_path), resolution)
         .map_err(|e| format!("Failed to load DTM: {}", e))?;
-    let dsm = RasterIO::read_raster(Path::new(&dsm_path))
+    let dsm = RasterIO::read_dsm(Path::new(&dsm_path), resolution)
         .map_err(|e| format!("Failed to load DSM: {}", e))?;
 
     // Validate matching dimensions
@@ -112,229 +245,311 @@ async fn load_rasters(
     Ok(bounds)
 }
 
+/// Read proposed building footprints from a vector file, in the DSM's coordinate
+/// system, for the frontend to store in `Config::proposed_buildings`
+#[tauri::command]
+async fn load_building_footprints(
+    path: String,
+    dsm_path: String,
+    height_field: String,
+    roof_shape_field: Option<String>,
+    roof_height_field: Option<String>,
+) -> Result<Vec<BuildingFootprint>, String> {
+    let projection = RasterIO::read_projection(Path::new(&dsm_path))
+        .map_err(|e| format!("Failed to read DSM coordinate system: {}", e))?;
+    let fields = footprints::FootprintFields {
+        height: height_field,
+        roof_shape: roof_shape_field,
+        roof_height: roof_height_field,
+    };
+    footprints::read_footprints(Path::new(&path), &fields, &projection)
+        .map_err(|e| format!("Failed to read building footprints: {}", e))
+}
+
 #[tauri::command]
 async fn calculate_shadows(
     config: Config,
     state: State<'_, AppState>,
     app_handle: tauri::AppHandle,
-) -> Result<String, String> {
+) -> Result<String, CommandError> {
     println!("Starting shadow calculation with config: {:?}", config);
 
-    // Load rasters
-    let dtm_data = RasterIO::read_raster(Path::new(&config.dtm_path))
-        .map_err(|e| format!("Failed to load DTM: {}", e))?;
-    let dsm_data = RasterIO::read_raster(Path::new(&config.dsm_path))
-        .map_err(|e| format!("Failed to load DSM: {}", e))?;
-
-    // Convert AOI to polygon
-    let polygon = config
-        .to_polygon()
-        .map_err(|e| format!("Failed to parse AOI: {}", e))?;
-
-    // Calculate automatic buffer based on terrain and solar geometry
-    let auto_buffer_meters = RasterIO::calculate_automatic_buffer(
-        &dtm_data,
-        &dsm_data,
-        &polygon,
-        &config.start_date,
-        &config.end_date,
-    )
-    .map_err(|e| format!("Failed to calculate automatic buffer: {}", e))?;
-
-    // Get the center latitude for conversion
-    let center_lat = polygon.exterior().coords().map(|c| c.y).sum::<f64>()
-        / polygon.exterior().coords().count() as f64;
+    let command = state.running.start();
+
+    // Clipped raster info is stored only once the calculation succeeds, so a
+    // cancelled run keeps the previous results usable
+    let PreparedEngine {
+        engine,
+        transform,
+        projection,
+        ..
+    } = pipeline::prepare_engine(&config, tauri_progress(app_handle), command.token.clone())?;
+    let clipped_info = ClippedRasterInfo::new(&transform, engine.dimensions(), projection);
+    let mut results = engine
+        .calculate_shadows()
+        .map_err(|e| CommandError::from_engine(e, "Shadow calculation failed"))?;
 
-    let buffer_degrees = meters_to_degrees(auto_buffer_meters, center_lat);
-    println!(
-        "Automatic buffer: {:.1}m = {:.6}° at latitude {:.3}°",
-        auto_buffer_meters, buffer_degrees, center_lat
-    );
+    let num_timestamps = results.timestamps.len();
+    let unique_sun_positions = results.unique_sun_positions.unwrap_or(num_timestamps);
 
-    // Clip to AOI with buffer (now in degrees)
-    let dtm_clipped = RasterIO::clip_to_aoi(&dtm_data, &polygon, buffer_degrees)
-        .map_err(|e| format!("Failed to clip DTM: {}", e))?;
-    let dsm_clipped = RasterIO::clip_to_aoi(&dsm_data, &polygon, buffer_degrees)
-        .map_err(|e| format!("Failed to clip DSM: {}", e))?;
-
-    // Extract 2D arrays
-    let dtm_2d = dtm_clipped.data.slice(ndarray::s![0, .., ..]).to_owned();
-    let dsm_2d = dsm_clipped.data.slice(ndarray::s![0, .., ..]).to_owned();
-
-    // Store clipped raster information for later visualization
-    let (n_rows, n_cols) = dtm_2d.dim();
-    let clipped_bounds = RasterBounds {
-        min_lon: dtm_clipped.transform[0],
-        max_lon: dtm_clipped.transform[0] + (n_cols as f64 * dtm_clipped.transform[1]),
-        min_lat: dtm_clipped.transform[3] + (n_rows as f64 * dtm_clipped.transform[5]), // transform[5] is negative
-        max_lat: dtm_clipped.transform[3],
-    };
+    // Apply AOI masking to results before storing for visualization and analysis
+    pipeline::mask_results_to_aoi(&mut results, &config, &transform)?;
 
-    let clipped_info = ClippedRasterInfo {
-        bounds: clipped_bounds,
-        transform: dtm_clipped.transform.to_vec(),
-        dimensions: (n_rows, n_cols),
-    };
+    // Store results in state
+    let mut results_guard = state.current_results.lock().unwrap();
+    *results_guard = Some(results);
 
     let mut clipped_info_guard = state.clipped_raster_info.lock().unwrap();
     *clipped_info_guard = Some(clipped_info);
-    drop(clipped_info_guard);
 
-    // Calculate pixel resolution in meters
-    let resolution = degrees_to_meters(dtm_clipped.transform[1].abs(), center_lat);
-    println!(
-        "Pixel resolution: {:.6}° = {:.2}m at latitude {:.3}°",
-        dtm_clipped.transform[1].abs(),
-        resolution,
-        center_lat
-    );
+    let mut config_guard = state.current_config.lock().unwrap();
+    *config_guard = Some(config);
 
-    // Create shadow engine with automatic buffer in meters
-    let mut config_with_meter_buffer = config.clone();
-    config_with_meter_buffer.buffer_meters = Some(auto_buffer_meters); // Use automatic buffer
+    Ok(format!(
+        "Calculated shadows for {} timestamps ({} unique sun positions)",
+        num_timestamps, unique_sun_positions
+    ))
+}
 
-    let engine = ShadowEngine::new_with_app_handle(
-        dtm_2d,
-        dsm_2d,
+#[tauri::command]
+async fn export_facade_analysis(
+    config: Config,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> Result<String, CommandError> {
+    println!("Starting facade analysis with config: {:?}", config);
+
+    let command = state.running.start();
+
+    let PreparedEngine {
+        engine,
+        transform,
         resolution,
-        dtm_clipped.transform,
-        config_with_meter_buffer,
-        app_handle,
-    );
-    let mut results = engine
-        .calculate_shadows()
-        .map_err(|e| format!("Shadow calculation failed: {}", e))?;
+        ..
+    } = pipeline::prepare_engine(&config, tauri_progress(app_handle), command.token.clone())?;
+    let facades = engine
+        .calculate_facade_sun_hours()
+        .map_err(|e| CommandError::from_engine(e, "Facade analysis failed"))?;
 
-    let num_timestamps = results.timestamps.len();
+    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+    let path = export_directory()?.join(format!("facades_{}.geojson", timestamp));
+    println!("Exporting facade analysis to: {:?}", path);
 
-    // Apply AOI masking to results before storing for visualization and analysis
-    let polygon = config
-        .to_polygon()
-        .map_err(|e| format!("Failed to parse AOI for masking: {}", e))?;
-
-    // Mask the shadow fraction data (time series)
-    RasterIO::mask_results_to_aoi(
-        &mut results.shadow_fraction,
-        &polygon,
-        &dtm_clipped.transform,
-        f32::NAN,
-    )
-    .map_err(|e| format!("Failed to mask shadow fraction results to AOI: {}", e))?;
-
-    // Mask all summary stats layers
-    RasterIO::mask_results_to_aoi(
-        &mut results.summary_stats.total_shadow_hours,
-        &polygon,
-        &dtm_clipped.transform,
-        f32::NAN,
-    )
-    .map_err(|e| format!("Failed to mask total shadow hours to AOI: {}", e))?;
+    facade::write_geojson(&path, &facades, &transform, resolution)
+        .map_err(|e| format!("Failed to write facade GeoJSON: {}", e))?;
 
-    RasterIO::mask_results_to_aoi(
-        &mut results.summary_stats.avg_shadow_percentage,
-        &polygon,
-        &dtm_clipped.transform,
-        f32::NAN,
-    )
-    .map_err(|e| format!("Failed to mask avg shadow percentage to AOI: {}", e))?;
+    Ok(format!(
+        "Exported {} wall segments to: {}",
+        facades.len(),
+        path.display()
+    ))
+}
 
-    RasterIO::mask_results_to_aoi(
-        &mut results.summary_stats.max_consecutive_shadow,
-        &polygon,
-        &dtm_clipped.transform,
-        f32::NAN,
-    )
-    .map_err(|e| format!("Failed to mask max consecutive shadow to AOI: {}", e))?;
+#[tauri::command]
+async fn export_roof_analysis(
+    config: Config,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> Result<String, CommandError> {
+    println!("Starting roof analysis with config: {:?}", config);
 
-    RasterIO::mask_results_to_aoi(
-        &mut results.summary_stats.morning_shadow_hours,
-        &polygon,
-        &dtm_clipped.transform,
-        f32::NAN,
-    )
-    .map_err(|e| format!("Failed to mask morning shadow hours to AOI: {}", e))?;
+    let command = state.running.start();
 
-    RasterIO::mask_results_to_aoi(
-        &mut results.summary_stats.noon_shadow_hours,
-        &polygon,
-        &dtm_clipped.transform,
-        f32::NAN,
-    )
-    .map_err(|e| format!("Failed to mask noon shadow hours to AOI: {}", e))?;
+    let PreparedEngine {
+        engine,
+        transform,
+        resolution,
+        ..
+    } = pipeline::prepare_engine(&config, tauri_progress(app_handle), command.token.clone())?;
+    let faces = engine.segment_roof_faces();
+
+    // Per-face statistics come from the shadow results on the same grid
+    let roofs = {
+        let results = state.current_results.lock().unwrap();
+        let results = results
+            .as_ref()
+            .ok_or("Run the shadow analysis before exporting roof faces")?;
+        let (_, n_rows, n_cols) = results.summary_stats.total_shadow_hours.dim();
+        if (n_rows, n_cols) != engine.dimensions() {
+            return Err(
+                "Shadow results do not match this configuration; run the analysis again".into(),
+            );
+        }
+        let analysis_hours = results.timestamps.len() as f32 * config.hour_interval;
+        roof::face_statistics(
+            faces,
+            &results.summary_stats,
+            analysis_hours,
+            config.get_roof_usable_sun_fraction() as f32,
+            resolution,
+        )
+    };
 
-    RasterIO::mask_results_to_aoi(
-        &mut results.summary_stats.afternoon_shadow_hours,
-        &polygon,
-        &dtm_clipped.transform,
-        f32::NAN,
-    )
-    .map_err(|e| format!("Failed to mask afternoon shadow hours to AOI: {}", e))?;
+    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+    let path = export_directory()?.join(format!("roofs_{}.geojson", timestamp));
+    println!("Exporting roof analysis to: {:?}", path);
 
-    RasterIO::mask_results_to_aoi(
-        &mut results.summary_stats.solar_efficiency_percentage,
-        &polygon,
-        &dtm_clipped.transform,
-        f32::NAN,
-    )
-    .map_err(|e| format!("Failed to mask solar efficiency percentage to AOI: {}", e))?;
+    roof::write_geojson(&path, &roofs, &transform)
+        .map_err(|e| format!("Failed to write roof GeoJSON: {}", e))?;
 
-    RasterIO::mask_results_to_aoi(
-        &mut results.summary_stats.daily_solar_hours,
-        &polygon,
-        &dtm_clipped.transform,
-        f32::NAN,
-    )
-    .map_err(|e| format!("Failed to mask daily solar hours to AOI: {}", e))?;
+    Ok(format!(
+        "Exported {} roof faces to: {}",
+        roofs.len(),
+        path.display()
+    ))
+}
 
-    RasterIO::mask_results_to_aoi(
-        &mut results.summary_stats.total_available_solar_hours,
-        &polygon,
-        &dtm_clipped.transform,
-        f32::NAN,
-    )
-    .map_err(|e| format!("Failed to mask total available solar hours to AOI: {}", e))?;
+#[derive(Debug, Serialize)]
+struct PvYieldExport {
+    monthly_path: String,
+    hourly_path: String,
+    #[serde(flatten)]
+    pv_yield: pv::PvYield,
+}
+
+#[tauri::command]
+async fn export_pv_yield(
+    config: Config,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> Result<PvYieldExport, CommandError> {
+    println!("Starting PV yield estimate with config: {:?}", config);
+
+    let array = config
+        .pv_array
+        .clone()
+        .ok_or("Define a PV array before estimating its yield")?;
+    let command = state.running.start();
+
+    let PreparedEngine { engine, .. } =
+        pipeline::prepare_engine(&config, tauri_progress(app_handle), command.token.clone())?;
+
+    let pv_yield = {
+        let results = state.current_results.lock().unwrap();
+        let results = results
+            .as_ref()
+            .ok_or("Run the shadow analysis before estimating PV yield")?;
+        let (_, n_rows, n_cols) = results.summary_stats.total_shadow_hours.dim();
+        if (n_rows, n_cols) != engine.dimensions() {
+            return Err(
+                "Shadow results do not match this configuration; run the analysis again".into(),
+            );
+        }
+        engine
+            .estimate_pv_yield(&array, results)
+            .map_err(|e| CommandError::from_engine(e, "Failed to estimate PV yield"))?
+    };
 
-    println!("Results masked to AOI boundaries for visualization and analysis");
+    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+    let directory = export_directory()?;
+    let monthly_path = directory.join(format!("pv_yield_monthly_{}.csv", timestamp));
+    let hourly_path = directory.join(format!("pv_yield_hourly_{}.csv", timestamp));
+    println!("Exporting PV yield to: {:?}", monthly_path);
 
-    // Store results in state
-    let mut results_guard = state.current_results.lock().unwrap();
-    *results_guard = Some(results);
+    pv::write_monthly_csv(&monthly_path, &pv_yield)
+        .map_err(|e| format!("Failed to write monthly PV yield: {}", e))?;
+    pv::write_hourly_csv(&hourly_path, &pv_yield)
+        .map_err(|e| format!("Failed to write hourly PV yield: {}", e))?;
 
-    let mut config_guard = state.current_config.lock().unwrap();
-    *config_guard = Some(config);
+    println!(
+        "PV yield: {:.1} kWh AC ({:.1}% shading loss, {:.0} kWh/kWp)",
+        pv_yield.total_ac_energy, pv_yield.shading_loss_percent, pv_yield.specific_yield
+    );
 
-    Ok(format!(
-        "Calculated shadows for {} timestamps",
-        num_timestamps
-    ))
+    Ok(PvYieldExport {
+        monthly_path: monthly_path.display().to_string(),
+        hourly_path: hourly_path.display().to_string(),
+        pv_yield,
+    })
 }
 
-// Helper function to convert meters to degrees
-fn meters_to_degrees(meters: f64, latitude: f64) -> f64 {
-    // At the equator: 1 degree ≈ 111,320 meters
-    // At latitude φ: 1 degree longitude ≈ 111,320 * cos(φ) meters
-    // 1 degree latitude ≈ 111,320 meters (approximately constant)
-
-    let lat_rad = latitude.to_radians();
+#[derive(Debug, Serialize)]
+struct TimelapseExport {
+    frames: usize,
+    frame_directory: String,
+    gif_path: String,
+}
 
-    // For longitude: meters / (111320 * cos(latitude))
-    // For latitude: meters / 111320
-    // We'll use an average for a rough square buffer
+/// Render a range of shadow maps as a PNG sequence and an animated GIF. The hillshade
+/// background needs the DSM of `config`, on the same grid as the results.
+#[tauri::command]
+async fn export_timelapse(
+    config: Config,
+    start_index: Option<usize>,
+    end_index: Option<usize>,
+    hillshade: Option<bool>,
+    frame_delay_ms: Option<u32>,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> Result<TimelapseExport, CommandError> {
+    let command = state.running.start();
+
+    let hillshade = if hillshade.unwrap_or(false) {
+        let PreparedEngine { engine, .. } = pipeline::prepare_engine(
+            &config,
+            tauri_progress(app_handle.clone()),
+            command.token.clone(),
+        )?;
+        Some((engine.dimensions(), engine.hillshade()))
+    } else {
+        None
+    };
 
-    let lon_meters_per_degree = 111320.0 * lat_rad.cos();
-    let lat_meters_per_degree = 111320.0;
+    let results = state.current_results.lock().unwrap();
+    let results = results
+        .as_ref()
+        .ok_or("No results available for a time-lapse")?;
+    let (n_times, n_rows, n_cols) = results.shadow_fraction.dim();
+    if let Some((dimensions, _)) = &hillshade {
+        if *dimensions != (n_rows, n_cols) {
+            return Err(
+                "Shadow results do not match this configuration; run the analysis again".into(),
+            );
+        }
+    }
 
-    // Use the average for a roughly square buffer
-    let avg_meters_per_degree = (lon_meters_per_degree + lat_meters_per_degree) / 2.0;
+    let options = timelapse::TimelapseOptions {
+        start: start_index.unwrap_or(0),
+        end: end_index.unwrap_or(n_times.saturating_sub(1)),
+        frame_delay_ms: frame_delay_ms.unwrap_or(500),
+    };
+    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+    let directory = export_directory()?.join(format!("timelapse_{}", timestamp));
+    println!("Exporting time-lapse to: {:?}", directory);
+
+    let progress = TauriProgress(app_handle);
+    let export = timelapse::write_timelapse(
+        &directory,
+        results,
+        hillshade.as_ref().map(|(_, hillshade)| hillshade),
+        options,
+        |done, total| {
+            if command.token.is_cancelled() {
+                return Err(ShadowError::Cancelled);
+            }
+            progress.report(&ProgressUpdate {
+                progress: done as f64 / total as f64 * 100.0,
+                current_step: format!("Rendering frame {} of {}", done, total),
+                total_steps: Some(total),
+                current_step_number: Some(done),
+            });
+            Ok(())
+        },
+    )
+    .map_err(|e| CommandError::from_engine(e, "Failed to export time-lapse"))?;
 
-    meters / avg_meters_per_degree
+    Ok(TimelapseExport {
+        frames: export.frames,
+        frame_directory: export.frame_directory.display().to_string(),
+        gif_path: export.gif_path.display().to_string(),
+    })
 }
 
-// Helper function to convert degrees to meters
-fn degrees_to_meters(degrees: f64, latitude: f64) -> f64 {
-    let lat_rad = latitude.to_radians();
-    let lon_meters_per_degree = 111320.0 * lat_rad.cos();
-    degrees * lon_meters_per_degree
+#[tauri::command]
+async fn cancel_calculation(state: State<'_, AppState>) -> Result<(), String> {
+    println!("Cancelling shadow calculation");
+    state.running.cancel_all();
+    Ok(())
 }
 
 #[tauri::command]
@@ -358,7 +573,8 @@ async fn get_shadow_at_time(
 
             let slice = results
                 .shadow_fraction
-                .slice(ndarray::s![time_index, .., ..]);
+                .read_slice(time_index)
+                .map_err(|e| format!("Failed to read shadow map: {}", e))?;
             let rows: Vec<Vec<f32>> = slice.outer_iter().map(|row| row.to_vec()).collect();
             Ok(rows)
         }
@@ -383,6 +599,11 @@ struct AllSummaryData {
     afternoon_shadow_hours: Vec<Vec<f32>>,
     daily_solar_hours: Vec<Vec<f32>>,
     total_available_solar_hours: Vec<Vec<f32>>,
+    sky_view_factor: Option<Vec<Vec<f32>>>,
+    direct_irradiation: Option<Vec<Vec<f32>>>,
+    diffuse_irradiation: Option<Vec<Vec<f32>>>,
+    global_irradiation: Option<Vec<Vec<f32>>>,
+    effective_sun_hours: Option<Vec<Vec<f32>>>,
     bounds: RasterBounds,
     transform: Vec<f64>,
 }
@@ -495,6 +716,18 @@ async fn get_all_summary_data(state: State<'_, AppState>) -> Result<AllSummaryDa
                 .map(|row| row.to_vec())
                 .collect();
 
+            // Optional layers are only sent when they were calculated
+            let optional_rows = |layer: &Option<ndarray::Array3<f32>>| -> Option<Vec<Vec<f32>>> {
+                layer.as_ref().map(|layer| {
+                    layer
+                        .slice(ndarray::s![0, .., ..])
+                        .outer_iter()
+                        .map(|row| row.to_vec())
+                        .collect()
+                })
+            };
+            let summary_stats = &results.summary_stats;
+
             Ok(AllSummaryData {
                 total_shadow_hours,
                 avg_shadow_percentage,
@@ -504,6 +737,11 @@ async fn get_all_summary_data(state: State<'_, AppState>) -> Result<AllSummaryDa
                 afternoon_shadow_hours,
                 daily_solar_hours,
                 total_available_solar_hours,
+                sky_view_factor: optional_rows(&summary_stats.sky_view_factor),
+                direct_irradiation: optional_rows(&summary_stats.direct_irradiation),
+                diffuse_irradiation: optional_rows(&summary_stats.diffuse_irradiation),
+                global_irradiation: optional_rows(&summary_stats.global_irradiation),
+                effective_sun_hours: optional_rows(&summary_stats.effective_sun_hours),
                 bounds: clipped_info.bounds.clone(),
                 transform: clipped_info.transform.clone(),
             })
@@ -512,6 +750,72 @@ async fn get_all_summary_data(state: State<'_, AppState>) -> Result<AllSummaryDa
     }
 }
 
+/// Display names of the optional summary layers at the start of a results file,
+/// which follow the nine fixed summary bands and are recognised by description
+fn optional_summary_layers(band_descriptions: &[String]) -> Vec<&'static str> {
+    band_descriptions
+        .iter()
+        .skip(9)
+        .map_while(|description| {
+            OPTIONAL_SUMMARY_BANDS
+                .iter()
+                .find(|(band, _)| band == description)
+                .map(|&(_, name)| name)
+        })
+        .collect()
+}
+
+/// Summary layers from the leading bands of a results file: the nine fixed bands,
+/// then any optional layers. Returns the stats and the number of summary bands.
+fn summary_stats_from_bands(
+    data: &ndarray::Array3<f32>,
+    band_descriptions: &[String],
+) -> (SummaryStats, usize) {
+    let n_summary = 9 + optional_summary_layers(band_descriptions).len();
+    let mut summary_stats = SummaryStats {
+        total_shadow_hours: data.slice(ndarray::s![0..1, .., ..]).to_owned(),
+        avg_shadow_percentage: data.slice(ndarray::s![1..2, .., ..]).to_owned(),
+        max_consecutive_shadow: data.slice(ndarray::s![2..3, .., ..]).to_owned(),
+        morning_shadow_hours: data.slice(ndarray::s![3..4, .., ..]).to_owned(),
+        noon_shadow_hours: data.slice(ndarray::s![4..5, .., ..]).to_owned(),
+        afternoon_shadow_hours: data.slice(ndarray::s![5..6, .., ..]).to_owned(),
+        solar_efficiency_percentage: data.slice(ndarray::s![6..7, .., ..]).to_owned(),
+        daily_solar_hours: data.slice(ndarray::s![7..8, .., ..]).to_owned(),
+        total_available_solar_hours: data.slice(ndarray::s![8..9, .., ..]).to_owned(),
+        sky_view_factor: None,
+        direct_irradiation: None,
+        diffuse_irradiation: None,
+        global_irradiation: None,
+        slope: None,
+        aspect: None,
+        self_shaded_hours: None,
+        effective_sun_hours: None,
+    };
+    for (band, description) in band_descriptions.iter().enumerate().take(n_summary).skip(9) {
+        summary_stats.set_optional_layer(
+            description,
+            data.slice(ndarray::s![band..band + 1, .., ..]).to_owned(),
+        );
+    }
+    (summary_stats, n_summary)
+}
+
+/// Output directory in the user's documents folder, created if it doesn't exist
+fn export_directory() -> Result<std::path::PathBuf, String> {
+    let output_dir = match dirs::document_dir() {
+        Some(dir) => dir.join("ShadowCalculator_Exports"),
+        None => std::env::current_dir()
+            .unwrap_or_else(|_| std::path::PathBuf::from("."))
+            .parent()
+            .unwrap_or(&std::path::PathBuf::from("."))
+            .join("exports"),
+    };
+
+    std::fs::create_dir_all(&output_dir)
+        .map_err(|e| format!("Failed to create output directory: {}", e))?;
+    Ok(output_dir)
+}
+
 #[tauri::command]
 async fn export_results(
     output_path: String,
@@ -523,19 +827,7 @@ async fn export_results(
 
     match (results.as_ref(), config.as_ref()) {
         (Some(results), Some(config)) => {
-            // Create output directory in user's home or documents folder
-            let output_dir = match dirs::document_dir() {
-                Some(dir) => dir.join("ShadowCalculator_Exports"),
-                None => std::env::current_dir()
-                    .unwrap_or_else(|_| std::path::PathBuf::from("."))
-                    .parent()
-                    .unwrap_or(&std::path::PathBuf::from("."))
-                    .join("exports"),
-            };
-
-            // Create directory if it doesn't exist
-            std::fs::create_dir_all(&output_dir)
-                .map_err(|e| format!("Failed to create output directory: {}", e))?;
+            let output_dir = export_directory()?;
 
             // Generate timestamp for unique filename
             let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
@@ -554,8 +846,11 @@ async fn export_results(
             match format.as_str() {
                 "geotiff" => {
                     // Get transform from original raster
-                    let dtm_data = RasterIO::read_raster(Path::new(&config.dtm_path))
-                        .map_err(|e| format!("Failed to load DTM: {}", e))?;
+                    let dtm_data = RasterIO::read_dtm(
+                        Path::new(&config.dtm_path),
+                        config.get_point_cloud_resolution(),
+                    )
+                    .map_err(|e| format!("Failed to load DTM: {}", e))?;
 
                     let polygon = config
                         .to_polygon()
@@ -570,113 +865,28 @@ async fn export_results(
                         // Fallback to 100m if not set
                         100.0
                     });
-                    let buffer_degrees = meters_to_degrees(buffer_meters, center_lat);
+                    let buffer_degrees = pipeline::meters_to_degrees(buffer_meters, center_lat);
 
                     let clipped = RasterIO::clip_to_aoi(&dtm_data, &polygon, buffer_degrees)
                         .map_err(|e| format!("Failed to clip: {}", e))?;
 
-                    // Combine summary stats and time series
-                    let n_summary = 9;
-                    let (n_times, n_rows, n_cols) = results.shadow_fraction.dim();
-                    let mut combined =
-                        ndarray::Array3::<f32>::zeros((n_summary + n_times, n_rows, n_cols));
-
-                    // Add summary layers with proper indexing
-                    combined.slice_mut(ndarray::s![0, .., ..]).assign(
-                        &results
-                            .summary_stats
-                            .total_shadow_hours
-                            .slice(ndarray::s![0, .., ..]),
-                    );
-                    combined.slice_mut(ndarray::s![1, .., ..]).assign(
-                        &results
-                            .summary_stats
-                            .avg_shadow_percentage
-                            .slice(ndarray::s![0, .., ..]),
-                    );
-                    combined.slice_mut(ndarray::s![2, .., ..]).assign(
-                        &results
-                            .summary_stats
-                            .max_consecutive_shadow
-                            .slice(ndarray::s![0, .., ..]),
-                    );
-                    combined.slice_mut(ndarray::s![3, .., ..]).assign(
-                        &results
-                            .summary_stats
-                            .morning_shadow_hours
-                            .slice(ndarray::s![0, .., ..]),
-                    );
-                    combined.slice_mut(ndarray::s![4, .., ..]).assign(
-                        &results
-                            .summary_stats
-                            .noon_shadow_hours
-                            .slice(ndarray::s![0, .., ..]),
-                    );
-                    combined.slice_mut(ndarray::s![5, .., ..]).assign(
-                        &results
-                            .summary_stats
-                            .afternoon_shadow_hours
-                            .slice(ndarray::s![0, .., ..]),
-                    );
-                    combined.slice_mut(ndarray::s![6, .., ..]).assign(
-                        &results
-                            .summary_stats
-                            .solar_efficiency_percentage
-                            .slice(ndarray::s![0, .., ..]),
-                    );
-                    combined.slice_mut(ndarray::s![7, .., ..]).assign(
-                        &results
-                            .summary_stats
-                            .daily_solar_hours
-                            .slice(ndarray::s![0, .., ..]),
-                    );
-                    combined.slice_mut(ndarray::s![8, .., ..]).assign(
-                        &results
-                            .summary_stats
-                            .total_available_solar_hours
-                            .slice(ndarray::s![0, .., ..]),
-                    );
-
-                    // Add time series
-                    combined
-                        .slice_mut(ndarray::s![n_summary.., .., ..])
-                        .assign(&results.shadow_fraction);
-
                     // Results are already masked to AOI during calculation
-
-                    // Create band descriptions for better identification
-                    let mut band_descriptions = vec![
-                        "Total_Shadow_Hours".to_string(),
-                        "Average_Shadow_Fraction_(0-1)".to_string(),
-                        "Max_Consecutive_Shadow_Hours".to_string(),
-                        "Morning_Shadow_Hours_(before_solar_noon_minus_2h)".to_string(),
-                        "Noon_Shadow_Hours_(solar_noon_±2h)".to_string(),
-                        "Afternoon_Shadow_Hours_(after_solar_noon_plus_2h)".to_string(),
-                        "Solar_Efficiency_Fraction_(0-1)".to_string(),
-                        "Average_Daily_Solar_Hours".to_string(),
-                        "Total_Available_Solar_Hours".to_string(),
-                    ];
-
-                    // Add timestamp descriptions for each time layer
-                    for timestamp in &results.timestamps {
-                        band_descriptions.push(timestamp.format("%Y-%m-%d_%H:%M_UTC").to_string());
-                    }
-
-                    // Write GeoTIFF with band descriptions
-                    RasterIO::write_geotiff_with_descriptions(
+                    pipeline::write_results_geotiff(
                         &path,
-                        &combined,
+                        results,
                         &clipped.transform,
                         &clipped.projection,
-                        &band_descriptions,
                     )
                     .map_err(|e| format!("Failed to write GeoTIFF: {}", e))?;
 
                     Ok(format!("GeoTIFF exported to: {}", path.display()))
                 }
                 "csv" => {
-                    let dtm_data = RasterIO::read_raster(Path::new(&config.dtm_path))
-                        .map_err(|e| format!("Failed to load DTM: {}", e))?;
+                    let dtm_data = RasterIO::read_dtm(
+                        Path::new(&config.dtm_path),
+                        config.get_point_cloud_resolution(),
+                    )
+                    .map_err(|e| format!("Failed to load DTM: {}", e))?;
 
                     let polygon = config
                         .to_polygon()
@@ -691,7 +901,7 @@ async fn export_results(
                         // Fallback to 100m if not set
                         100.0
                     });
-                    let buffer_degrees = meters_to_degrees(buffer_meters, center_lat);
+                    let buffer_degrees = pipeline::meters_to_degrees(buffer_meters, center_lat);
 
                     let clipped = RasterIO::clip_to_aoi(&dtm_data, &polygon, buffer_degrees)
                         .map_err(|e| format!("Failed to clip: {}", e))?;
@@ -714,6 +924,65 @@ async fn export_results(
     }
 }
 
+/// Trace a shadow map into polygons and write them to the export directory
+#[tauri::command]
+async fn export_shadow_polygons(
+    source: vectorize::VectorSource,
+    threshold: Option<f32>,
+    below: Option<bool>,
+    format: String,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let format = vectorize::VectorFormat::from_name(&format)
+        .ok_or_else(|| format!("Unsupported vector format '{}'", format))?;
+    // Summary layers have their own units, so only shadow fractions get a default
+    let threshold = match (&source, threshold) {
+        (_, Some(threshold)) => threshold,
+        (vectorize::VectorSource::Summary { layer }, None) => {
+            return Err(format!("Set a threshold for the '{}' layer", layer))
+        }
+        (_, None) => vectorize::DEFAULT_SHADOW_THRESHOLD,
+    };
+    let filter = vectorize::CellFilter {
+        threshold,
+        below: below.unwrap_or(false),
+    };
+
+    let results = state.current_results.lock().unwrap();
+    let clipped_info = state.clipped_raster_info.lock().unwrap();
+    let (results, clipped_info) = match (results.as_ref(), clipped_info.as_ref()) {
+        (Some(results), Some(clipped_info)) => (results, clipped_info),
+        _ => return Err("No results available to vectorize".to_string()),
+    };
+
+    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+    let path = export_directory()?.join(format!(
+        "shadow_polygons_{}.{}",
+        timestamp,
+        format.extension()
+    ));
+    println!("Exporting shadow polygons to: {:?}", path);
+
+    let resolution = clipped_info.resolution_meters();
+    let count = vectorize::write_shadow_polygons(
+        &path,
+        format,
+        results,
+        &source,
+        filter,
+        &clipped_info.geo_transform()?,
+        &clipped_info.projection,
+        resolution * resolution,
+    )
+    .map_err(|e| format!("Failed to write shadow polygons: {}", e))?;
+
+    Ok(format!(
+        "Exported {} shadow polygons to: {}",
+        count,
+        path.display()
+    ))
+}
+
 #[tauri::command]
 async fn get_timestamps(state: State<'_, AppState>) -> Result<Vec<String>, String> {
     let results = state.current_results.lock().unwrap();
@@ -772,7 +1041,7 @@ async fn validate_results_file(file_path: String) -> Result<ResultsMetadata, Str
     }
 
     // Expected summary layers (first 9 bands)
-    let summary_layers = vec![
+    let mut summary_layers = vec![
         "Total Shadow Hours".to_string(),
         "Average Shadow Fraction".to_string(),
         "Max Consecutive Shadow Hours".to_string(),
@@ -783,6 +1052,9 @@ async fn validate_results_file(file_path: String) -> Result<ResultsMetadata, Str
         "Average Daily Solar Hours".to_string(),
         "Total Available Solar Hours".to_string(),
     ];
+    let optional_layers = optional_summary_layers(&band_descriptions);
+    let n_summary = 9 + optional_layers.len();
+    summary_layers.extend(optional_layers.iter().map(|name| name.to_string()));
 
     // Calculate bounds
     let transform = &raster_data.transform;
@@ -802,13 +1074,13 @@ async fn validate_results_file(file_path: String) -> Result<ResultsMetadata, Str
     };
 
     // Extract metadata from band descriptions
-    let num_time_bands = n_bands - 9; // First 9 bands are summary layers
+    let num_time_bands = n_bands - n_summary; // Summary layers come first
 
     let (start_date, end_date, estimated_hour_interval) = if num_time_bands > 0
-        && band_descriptions.len() > 9
+        && band_descriptions.len() > n_summary
     {
-        // Parse timestamps from band descriptions (bands 9+ contain timestamp info)
-        let timestamps: Vec<chrono::DateTime<chrono::Utc>> = band_descriptions[9..]
+        // Parse timestamps from band descriptions (time bands follow the summary layers)
+        let timestamps: Vec<chrono::DateTime<chrono::Utc>> = band_descriptions[n_summary..]
             .iter()
             .filter_map(|desc| {
                 // Try to parse timestamp from description format: "YYYY-MM-DD_HH:MM_UTC"
@@ -876,31 +1148,27 @@ async fn load_results_file(
         return Err("Invalid results file: missing summary layers".to_string());
     }
 
-    // Extract summary stats (first 9 bands)
-    let summary_stats = SummaryStats {
-        total_shadow_hours: raster_data.data.slice(ndarray::s![0..1, .., ..]).to_owned(),
-        avg_shadow_percentage: raster_data.data.slice(ndarray::s![1..2, .., ..]).to_owned(),
-        max_consecutive_shadow: raster_data.data.slice(ndarray::s![2..3, .., ..]).to_owned(),
-        morning_shadow_hours: raster_data.data.slice(ndarray::s![3..4, .., ..]).to_owned(),
-        noon_shadow_hours: raster_data.data.slice(ndarray::s![4..5, .., ..]).to_owned(),
-        afternoon_shadow_hours: raster_data.data.slice(ndarray::s![5..6, .., ..]).to_owned(),
-        solar_efficiency_percentage: raster_data.data.slice(ndarray::s![6..7, .., ..]).to_owned(),
-        daily_solar_hours: raster_data.data.slice(ndarray::s![7..8, .., ..]).to_owned(),
-        total_available_solar_hours: raster_data.data.slice(ndarray::s![8..9, .., ..]).to_owned(),
-    };
+    // Extract summary stats (first 9 bands, then any optional layers)
+    let (summary_stats, n_summary) =
+        summary_stats_from_bands(&raster_data.data, &band_descriptions);
 
-    // Extract time series data (bands 9+)
-    let num_time_bands = n_bands - 9;
+    // Extract time series data (bands after the summary layers)
+    let num_time_bands = n_bands - n_summary;
     let shadow_fraction = if num_time_bands > 0 {
-        raster_data.data.slice(ndarray::s![9.., .., ..]).to_owned()
+        ShadowStore::from_array(
+            raster_data
+                .data
+                .slice(ndarray::s![n_summary.., .., ..])
+                .to_owned(),
+        )
     } else {
         // Create empty time series if no time data
-        ndarray::Array3::<f32>::zeros((0, n_rows, n_cols))
+        ShadowStore::in_memory(0, n_rows, n_cols)
     };
 
-    // Parse timestamps from band descriptions (bands 9+ contain timestamp info)
+    // Parse timestamps from band descriptions (time bands follow the summary layers)
     let timestamps: Vec<chrono::DateTime<chrono::Utc>> = if num_time_bands > 0 {
-        band_descriptions[9..]
+        band_descriptions[n_summary..]
             .iter()
             .map(|desc| {
                 // Try to parse timestamp from description format: "YYYY-MM-DD_HH:MM_UTC"
@@ -926,28 +1194,16 @@ async fn load_results_file(
         shadow_fraction,
         timestamps,
         summary_stats,
-    };
-
-    // Calculate bounds from transform
-    let transform = &raster_data.transform;
-    let min_lon = transform[0];
-    let max_lon = transform[0] + (n_cols as f64 * transform[1]);
-    let max_lat = transform[3];
-    let min_lat = transform[3] + (n_rows as f64 * transform[5]);
-
-    let bounds = RasterBounds {
-        min_lon,
-        max_lon,
-        min_lat: min_lat.min(max_lat),
-        max_lat: max_lat.max(min_lat),
+        unique_sun_positions: None,
     };
 
     // Store results and metadata in state
-    let clipped_info = ClippedRasterInfo {
-        bounds: bounds.clone(),
-        transform: raster_data.transform.to_vec(),
-        dimensions: (n_rows, n_cols),
-    };
+    let clipped_info = ClippedRasterInfo::new(
+        &raster_data.transform,
+        (n_rows, n_cols),
+        raster_data.projection.clone(),
+    );
+    let bounds = clipped_info.bounds.clone();
 
     let mut results_guard = state.current_results.lock().unwrap();
     *results_guard = Some(results);
@@ -964,6 +1220,160 @@ async fn load_results_file(
     ))
 }
 
+/// Keep the current results as the baseline for scenario comparison
+#[tauri::command]
+async fn set_baseline(state: State<'_, AppState>) -> Result<String, String> {
+    let results = state.current_results.lock().unwrap();
+    let clipped_info = state.clipped_raster_info.lock().unwrap();
+
+    match (results.as_ref(), clipped_info.as_ref()) {
+        (Some(results), Some(clipped_info)) => {
+            let (n_rows, n_cols) = clipped_info.dimensions;
+            *state.baseline.lock().unwrap() = Some(Baseline {
+                summary_stats: results.summary_stats.clone(),
+                clipped_info: clipped_info.clone(),
+            });
+            Ok(format!(
+                "Kept {}x{} results over {} timestamps as the baseline",
+                n_rows,
+                n_cols,
+                results.timestamps.len()
+            ))
+        }
+        _ => Err("No results available to keep as baseline".to_string()),
+    }
+}
+
+/// Use the summary layers of an exported results file as the baseline
+#[tauri::command]
+async fn load_baseline_file(
+    file_path: String,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let (raster_data, band_descriptions) =
+        RasterIO::read_multiband_raster_with_descriptions(Path::new(&file_path))
+            .map_err(|e| format!("Failed to read baseline file: {}", e))?;
+
+    let (n_bands, n_rows, n_cols) = raster_data.data.dim();
+    if n_bands < 9 {
+        return Err("Invalid baseline file: missing summary layers".to_string());
+    }
+    let (summary_stats, _) = summary_stats_from_bands(&raster_data.data, &band_descriptions);
+
+    let clipped_info = ClippedRasterInfo::new(
+        &raster_data.transform,
+        (n_rows, n_cols),
+        raster_data.projection.clone(),
+    );
+
+    *state.baseline.lock().unwrap() = Some(Baseline {
+        summary_stats,
+        clipped_info,
+    });
+    Ok(format!(
+        "Loaded {}x{} baseline from {}",
+        n_rows, n_cols, file_path
+    ))
+}
+
+#[derive(Debug, Serialize)]
+struct ComparisonExport {
+    path: String,
+    #[serde(flatten)]
+    summary: comparison::ComparisonSummary,
+}
+
+/// Delta layers of the current results against the baseline, with the grid they are on
+struct Comparison {
+    layers: comparison::DeltaLayers,
+    summary: comparison::ComparisonSummary,
+    clipped_info: ClippedRasterInfo,
+}
+
+fn compare_with_baseline(
+    state: &AppState,
+    sun_hours_threshold: Option<f64>,
+) -> Result<Comparison, String> {
+    let baseline = state.baseline.lock().unwrap();
+    let results = state.current_results.lock().unwrap();
+    let clipped_info = state.clipped_raster_info.lock().unwrap();
+
+    let baseline = baseline
+        .as_ref()
+        .ok_or("Set a baseline before comparing scenarios")?;
+    let (results, clipped_info) = match (results.as_ref(), clipped_info.as_ref()) {
+        (Some(results), Some(clipped_info)) => (results, clipped_info),
+        _ => return Err("No scenario results available to compare".to_string()),
+    };
+
+    // Both runs must cover the same AOI on the same grid for cell-by-cell deltas
+    let same_transform = baseline
+        .clipped_info
+        .transform
+        .iter()
+        .zip(&clipped_info.transform)
+        .all(|(a, b)| (a - b).abs() <= 1e-9 * a.abs().max(1.0));
+    if baseline.clipped_info.dimensions != clipped_info.dimensions || !same_transform {
+        return Err(format!(
+            "Baseline grid {:?} does not match scenario grid {:?}; use the same rasters and AOI for both runs",
+            baseline.clipped_info.dimensions, clipped_info.dimensions
+        ));
+    }
+
+    let resolution = clipped_info.resolution_meters();
+    let (layers, summary) = comparison::compare(
+        &baseline.summary_stats,
+        &results.summary_stats,
+        sun_hours_threshold.unwrap_or(comparison::DEFAULT_SUN_HOURS_THRESHOLD),
+        resolution * resolution,
+    )
+    .map_err(|e| format!("Failed to compare with baseline: {}", e))?;
+    Ok(Comparison {
+        layers,
+        summary,
+        clipped_info: clipped_info.clone(),
+    })
+}
+
+/// Write the delta layers as a multi-band GeoTIFF in the export directory
+#[tauri::command]
+async fn export_comparison(
+    sun_hours_threshold: Option<f64>,
+    state: State<'_, AppState>,
+) -> Result<ComparisonExport, String> {
+    let Comparison {
+        layers,
+        summary,
+        clipped_info,
+    } = compare_with_baseline(&state, sun_hours_threshold)?;
+
+    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+    let path = export_directory()?.join(format!("comparison_{}.tif", timestamp));
+    println!("Exporting scenario comparison to: {:?}", path);
+
+    let transform = clipped_info.geo_transform()?;
+    let band_descriptions: Vec<String> = comparison::DELTA_BANDS
+        .iter()
+        .map(|description| description.to_string())
+        .collect();
+    let bands = layers.bands();
+    RasterIO::write_geotiff_bands(
+        &path,
+        bands.len(),
+        clipped_info.dimensions,
+        &transform,
+        &clipped_info.projection,
+        &band_descriptions,
+        |band_idx| Ok(bands[band_idx].clone()),
+    )
+    .map_err(|e| format!("Failed to write comparison GeoTIFF: {}", e))?;
+
+    Ok(ComparisonExport {
+        path: path.display().to_string(),
+        summary,
+    })
+}
+
 #[tauri::command]
 async fn debug_tiff_structure(file_path: String) -> Result<String, String> {
     let path = Path::new(&file_path);
@@ -1053,37 +1463,44 @@ async fn get_seasonal_analysis(state: State<'_, AppState>) -> Result<SeasonalAna
                 let mut month_solar_efficiency = Array2::<f32>::zeros((n_rows, n_cols));
                 let mut month_max_consecutive = Array2::<f32>::zeros((n_rows, n_cols));
 
-                // Calculate statistics for each cell
-                for row in 0..n_rows {
-                    for col in 0..n_cols {
-                        let mut total_shadow = 0.0f32;
-                        let mut consecutive_shadow = 0.0f32;
-                        let mut max_consecutive = 0.0f32;
-                        let mut sunlit_hours = 0.0f32;
+                // Stream the month's time slices so disk-backed results are read only once
+                let mut consecutive_shadow = Array2::<f32>::zeros((n_rows, n_cols));
+                let mut sunlit_hours = Array2::<f32>::zeros((n_rows, n_cols));
+
+                for &time_idx in time_indices {
+                    let slice = results
+                        .shadow_fraction
+                        .read_slice(time_idx)
+                        .map_err(|e| format!("Failed to read shadow map: {}", e))?;
 
-                        for &time_idx in time_indices {
-                            let shadow_val = results.shadow_fraction[[time_idx, row, col]];
+                    for row in 0..n_rows {
+                        for col in 0..n_cols {
+                            let shadow_val = slice[[row, col]];
 
                             if shadow_val > 0.5 {
-                                total_shadow += 1.0;
-                                consecutive_shadow += 1.0;
+                                month_shadow_hours[[row, col]] += 1.0;
+                                consecutive_shadow[[row, col]] += 1.0;
                             } else {
-                                sunlit_hours += 1.0;
-                                max_consecutive = max_consecutive.max(consecutive_shadow);
-                                consecutive_shadow = 0.0;
+                                sunlit_hours[[row, col]] += 1.0;
+                                month_max_consecutive[[row, col]] = month_max_consecutive
+                                    [[row, col]]
+                                .max(consecutive_shadow[[row, col]]);
+                                consecutive_shadow[[row, col]] = 0.0;
                             }
                         }
+                    }
+                }
 
+                for row in 0..n_rows {
+                    for col in 0..n_cols {
                         // Final check for consecutive shadows
-                        max_consecutive = max_consecutive.max(consecutive_shadow);
-
-                        month_shadow_hours[[row, col]] = total_shadow;
-                        month_max_consecutive[[row, col]] = max_consecutive;
+                        month_max_consecutive[[row, col]] =
+                            month_max_consecutive[[row, col]].max(consecutive_shadow[[row, col]]);
 
                         // Solar efficiency: percentage of time with good solar access
                         if time_indices.len() > 0 {
                             month_solar_efficiency[[row, col]] =
-                                (sunlit_hours / time_indices.len() as f32) * 100.0;
+                                (sunlit_hours[[row, col]] / time_indices.len() as f32) * 100.0;
                         }
                     }
                 }
@@ -1238,11 +1655,20 @@ fn main() {
             current_results: Mutex::new(None),
             raster_bounds: Mutex::new(None),
             clipped_raster_info: Mutex::new(None),
+            baseline: Mutex::new(None),
+            running: RunningCommands::default(),
         })
         .invoke_handler(tauri::generate_handler![
             load_rasters,
+            load_building_footprints,
             calculate_shadows,
+            cancel_calculation,
+            export_facade_analysis,
+            export_roof_analysis,
+            export_pv_yield,
+            export_timelapse,
             export_results,
+            export_shadow_polygons,
             get_shadow_at_time,
             get_timestamps,
             get_average_shadow_raster,
@@ -1251,6 +1677,9 @@ fn main() {
             get_seasonal_analysis,
             validate_results_file,
             load_results_file,
+            set_baseline,
+            load_baseline_file,
+            export_comparison,
             debug_tiff_structure
         ])
         .run(tauri::generate_context!())
@@ -8,8 +8,18 @@ import ProgressModal from './components/ProgressModal';
 import PerformanceSettings from './components/PerformanceSettings';
 import SeasonalDashboard, { SeasonalDashboardInline } from './components/SeasonalDashboard';
 import { invoke } from '@tauri-apps/api/tauri';
+import { open } from '@tauri-apps/api/dialog';
 import { listen } from '@tauri-apps/api/event';
-import { Config, ShadowQuality, UploadMode, ResultsMetadata, SeasonalAnalysis } from './types';
+import { Config, ShadowQuality, UploadMode, ResultsMetadata, SeasonalAnalysis, PvArray, PvYieldExport, ComparisonExport, TimelapseExport, CommandError } from './types';
+
+// South-facing array over the whole AOI, used until an array is configured
+const DEFAULT_PV_ARRAY: PvArray = {
+  tilt: 30,
+  azimuth: 180,
+  module_efficiency: 0.2,
+  temperature_coefficient: -0.35,
+  inverter_losses: 0.04,
+};
 
 interface RasterBounds {
   min_lon: number;
@@ -40,10 +50,25 @@ interface AllSummaryData {
   afternoon_shadow_hours: number[][];
   daily_solar_hours: number[][];
   total_available_solar_hours: number[][];
+  sky_view_factor?: number[][] | null;
+  direct_irradiation?: number[][] | null;
+  diffuse_irradiation?: number[][] | null;
+  global_irradiation?: number[][] | null;
+  effective_sun_hours?: number[][] | null;
   bounds: RasterBounds;
   transform: number[];
 }
 
+// Cancellable commands reject with a CommandError rather than a plain message
+function isCancelled(error: unknown): boolean {
+  return (error as CommandError | undefined)?.kind === 'cancelled';
+}
+
+function commandErrorMessage(error: unknown): string {
+  const commandError = error as CommandError | undefined;
+  return commandError?.kind === 'failed' ? commandError.message : String(error);
+}
+
 function App() {
   // Upload mode state
   const [uploadMode, setUploadMode] = useState<UploadMode>('calculate');
@@ -75,6 +100,7 @@ function App() {
   const [currentView, setCurrentView] = useState<'map' | 'seasonal'>('map');
   const [isToolsDropdownOpen, setIsToolsDropdownOpen] = useState(false);
   const [isSettingsOpen, setIsSettingsOpen] = useState(false);
+  const [hasBaseline, setHasBaseline] = useState(false);
 
   // Progress state
   const [progressData, setProgressData] = useState<ProgressUpdate>({
@@ -230,13 +256,219 @@ function App() {
       const summaryData = await invoke<AllSummaryData>('get_all_summary_data');
       setAllSummaryData(summaryData);
     } catch (error) {
-      console.error('Calculation failed:', error);
-      setError(`Calculation failed: ${error}`);
+      if (isCancelled(error)) {
+        // Previous results are kept by the backend, nothing to reset here
+        console.log('Calculation cancelled');
+      } else {
+        console.error('Calculation failed:', error);
+        setError(`Calculation failed: ${commandErrorMessage(error)}`);
+      }
+    } finally {
+      setIsCalculating(false);
+    }
+  }, [config]);
+
+  const handleFacadeAnalysis = useCallback(async () => {
+    if (!config.dtm_path || !config.dsm_path || config.aoi.length === 0) {
+      setError('Please provide all required inputs');
+      return;
+    }
+
+    setIsCalculating(true);
+    setError('');
+    setProgressData({
+      progress: 0,
+      current_step: 'Finding building walls...',
+      total_steps: undefined,
+      current_step_number: undefined,
+    });
+
+    try {
+      const result = await invoke('export_facade_analysis', { config });
+      alert(`✅ Facade analysis complete!\n\n${String(result)}`);
+    } catch (error) {
+      if (isCancelled(error)) {
+        console.log('Facade analysis cancelled');
+      } else {
+        console.error('Facade analysis failed:', error);
+        setError(`Facade analysis failed: ${commandErrorMessage(error)}`);
+      }
+    } finally {
+      setIsCalculating(false);
+    }
+  }, [config]);
+
+  const handleRoofAnalysis = useCallback(async () => {
+    setIsCalculating(true);
+    setError('');
+    setProgressData({
+      progress: 0,
+      current_step: 'Segmenting roof faces...',
+      total_steps: undefined,
+      current_step_number: undefined,
+    });
+
+    try {
+      const result = await invoke('export_roof_analysis', { config });
+      alert(`✅ Roof analysis complete!\n\n${String(result)}`);
+    } catch (error) {
+      if (isCancelled(error)) {
+        console.log('Roof analysis cancelled');
+      } else {
+        console.error('Roof analysis failed:', error);
+        setError(`Roof analysis failed: ${commandErrorMessage(error)}`);
+      }
+    } finally {
+      setIsCalculating(false);
+    }
+  }, [config]);
+
+  const handlePvYield = useCallback(async () => {
+    setIsCalculating(true);
+    setError('');
+    setProgressData({
+      progress: 0,
+      current_step: 'Estimating PV yield...',
+      total_steps: undefined,
+      current_step_number: undefined,
+    });
+
+    try {
+      const pvArray = config.pv_array ?? DEFAULT_PV_ARRAY;
+      const result = await invoke<PvYieldExport>('export_pv_yield', {
+        config: { ...config, pv_array: pvArray },
+      });
+      const months = result.monthly
+        .map(m => `${m.year}-${String(m.month).padStart(2, '0')}: ${m.ac_energy.toFixed(0)} kWh (${m.shading_loss_percent.toFixed(1)}% shading loss)`)
+        .join('\n');
+      alert(
+        `✅ PV yield estimate complete!\n\n` +
+        `${result.peak_power.toFixed(1)} kWp, ${result.total_ac_energy.toFixed(0)} kWh AC ` +
+        `(${result.specific_yield.toFixed(0)} kWh/kWp)\n` +
+        `Shading loss: ${result.shading_loss_percent.toFixed(1)}% of ${result.unshaded_ac_energy.toFixed(0)} kWh unshaded\n\n` +
+        `${months}\n\nSaved to:\n${result.monthly_path}\n${result.hourly_path}`
+      );
+    } catch (error) {
+      if (isCancelled(error)) {
+        console.log('PV yield estimate cancelled');
+      } else {
+        console.error('PV yield estimate failed:', error);
+        setError(`PV yield estimate failed: ${commandErrorMessage(error)}`);
+      }
+    } finally {
+      setIsCalculating(false);
+    }
+  }, [config]);
+
+  const handleExportPolygons = useCallback(async (timeSeries: boolean) => {
+    try {
+      const result = await invoke('export_shadow_polygons', {
+        source: timeSeries ? { kind: 'TimeSeries' } : { kind: 'TimeSlice', index: currentTimeIndex },
+        threshold: null,
+        below: null,
+        format: timeSeries ? 'gpkg' : 'geojson',
+      });
+      alert(`✅ Export successful!\n\n${String(result)}`);
+    } catch (error) {
+      console.error('Polygon export failed:', error);
+      setError(`Polygon export failed: ${error}`);
+    }
+  }, [currentTimeIndex]);
+
+  const handleExportTimelapse = useCallback(async () => {
+    setIsCalculating(true);
+    setError('');
+    setProgressData({
+      progress: 0,
+      current_step: 'Rendering time-lapse...',
+      total_steps: undefined,
+      current_step_number: undefined,
+    });
+
+    try {
+      // Whole time series, over a hillshade when the DSM is available
+      const result = await invoke<TimelapseExport>('export_timelapse', {
+        config,
+        startIndex: null,
+        endIndex: null,
+        hillshade: Boolean(config.dsm_path),
+        frameDelayMs: null,
+      });
+      alert(
+        `✅ Time-lapse exported!\n\n${result.frames} frames\n\n` +
+        `Animation:\n${result.gif_path}\n\nPNG frames:\n${result.frame_directory}`
+      );
+    } catch (error) {
+      if (isCancelled(error)) {
+        console.log('Time-lapse export cancelled');
+      } else {
+        console.error('Time-lapse export failed:', error);
+        setError(`Time-lapse export failed: ${commandErrorMessage(error)}`);
+      }
     } finally {
       setIsCalculating(false);
     }
   }, [config]);
 
+  const handleSetBaseline = useCallback(async () => {
+    try {
+      const result = await invoke('set_baseline');
+      setHasBaseline(true);
+      alert(`✅ ${String(result)}\n\nChange the DSM or proposed buildings, run the analysis again, then compare.`);
+    } catch (error) {
+      console.error('Setting baseline failed:', error);
+      setError(`Setting baseline failed: ${error}`);
+    }
+  }, []);
+
+  const handleLoadBaseline = useCallback(async () => {
+    try {
+      const selected = await open({
+        multiple: false,
+        filters: [{ name: 'Shadow Calculator Results', extensions: ['tif', 'tiff'] }],
+      });
+      if (!selected || Array.isArray(selected)) return;
+
+      const result = await invoke('load_baseline_file', { filePath: selected });
+      setHasBaseline(true);
+      alert(`✅ ${String(result)}`);
+    } catch (error) {
+      console.error('Loading baseline failed:', error);
+      setError(`Loading baseline failed: ${error}`);
+    }
+  }, []);
+
+  const handleExportComparison = useCallback(async () => {
+    try {
+      const result = await invoke<ComparisonExport>('export_comparison', {
+        sunHoursThreshold: null,
+      });
+      const share = (cells: number) => (result.cells > 0 ? (100 * cells) / result.cells : 0).toFixed(1);
+      alert(
+        `✅ Scenario comparison exported!\n\n` +
+        `Mean added shadow: ${result.mean_added_shadow_hours.toFixed(2)} h (max ${result.max_added_shadow_hours.toFixed(1)} h)\n` +
+        `More shadow: ${result.cells_more_shadow} cells (${share(result.cells_more_shadow)}%), ` +
+        `less shadow: ${result.cells_less_shadow} cells\n` +
+        `Mean lost solar efficiency: ${(100 * result.mean_lost_solar_efficiency).toFixed(1)}%\n` +
+        `Newly below ${result.sun_hours_threshold} daily sun hours: ${result.newly_failing_cells} cells ` +
+        `(${result.newly_failing_area.toFixed(0)} m², ${share(result.newly_failing_cells)}%)\n\n` +
+        `Saved to:\n${result.path}`
+      );
+    } catch (error) {
+      console.error('Comparison failed:', error);
+      setError(`Comparison failed: ${error}`);
+    }
+  }, []);
+
+  const handleCancelCalculation = useCallback(async () => {
+    setProgressData(prev => ({ ...prev, current_step: 'Cancelling...' }));
+    try {
+      await invoke('cancel_calculation');
+    } catch (error) {
+      console.error('Failed to cancel calculation:', error);
+    }
+  }, []);
+
   const handleTimeChange = useCallback(async (index: number) => {
     setCurrentTimeIndex(index);
     try {
@@ -573,6 +805,29 @@ function App() {
                     'Run Shadow Analysis'
                   )}
                 </button>
+                <button
+                  onClick={handleFacadeAnalysis}
+                  disabled={isCalculating || !config.dtm_path || !config.dsm_path || config.aoi.length === 0}
+                  className="w-full py-2 px-3 bg-gray-700 hover:bg-gray-600 disabled:bg-gray-700 disabled:text-gray-500 disabled:cursor-not-allowed text-white text-sm rounded-lg transition-colors"
+                >
+                  Export Facade Sun Hours (GeoJSON)
+                </button>
+                <button
+                  onClick={handleRoofAnalysis}
+                  disabled={isCalculating || !allSummaryData}
+                  title={!allSummaryData ? 'Run the shadow analysis first' : undefined}
+                  className="w-full py-2 px-3 bg-gray-700 hover:bg-gray-600 disabled:bg-gray-700 disabled:text-gray-500 disabled:cursor-not-allowed text-white text-sm rounded-lg transition-colors"
+                >
+                  Export Roof Faces (GeoJSON)
+                </button>
+                <button
+                  onClick={handlePvYield}
+                  disabled={isCalculating || !allSummaryData}
+                  title={!allSummaryData ? 'Run the shadow analysis first' : undefined}
+                  className="w-full py-2 px-3 bg-gray-700 hover:bg-gray-600 disabled:bg-gray-700 disabled:text-gray-500 disabled:cursor-not-allowed text-white text-sm rounded-lg transition-colors"
+                >
+                  Estimate PV Yield (CSV)
+                </button>
                 
                 {(!config.dtm_path || !config.dsm_path || config.aoi.length === 0) && (
                   <div className="text-xs text-gray-500 space-y-1">
@@ -618,6 +873,59 @@ function App() {
                 >
                   Export as CSV
                 </button>
+                <div className="grid grid-cols-2 gap-2">
+                  <button
+                    onClick={() => handleExportPolygons(false)}
+                    disabled={isCalculating}
+                    title="Shadow polygons of the time shown on the map"
+                    className="py-2 px-3 bg-emerald-600 hover:bg-emerald-700 disabled:bg-gray-600 disabled:cursor-not-allowed text-white text-sm rounded-lg transition-colors"
+                  >
+                    Polygons (GeoJSON)
+                  </button>
+                  <button
+                    onClick={() => handleExportPolygons(true)}
+                    disabled={isCalculating}
+                    title="Shadow polygons of every timestamp in one layer"
+                    className="py-2 px-3 bg-emerald-600 hover:bg-emerald-700 disabled:bg-gray-600 disabled:cursor-not-allowed text-white text-sm rounded-lg transition-colors"
+                  >
+                    Time Series (GPKG)
+                  </button>
+                </div>
+                <button
+                  onClick={handleExportTimelapse}
+                  disabled={isCalculating}
+                  className="w-full py-2 px-3 bg-emerald-600 hover:bg-emerald-700 disabled:bg-gray-600 disabled:cursor-not-allowed text-white text-sm rounded-lg transition-colors"
+                >
+                  Export Time-lapse (GIF + PNG)
+                </button>
+
+                <div className="text-xs font-medium text-gray-400 pt-2">Scenario Comparison</div>
+                <div className="grid grid-cols-2 gap-2">
+                  <button
+                    onClick={handleSetBaseline}
+                    disabled={isCalculating}
+                    title="Keep the current results as the baseline"
+                    className="py-2 px-3 bg-gray-700 hover:bg-gray-600 disabled:bg-gray-700 disabled:text-gray-500 disabled:cursor-not-allowed text-white text-sm rounded-lg transition-colors"
+                  >
+                    Set as Baseline
+                  </button>
+                  <button
+                    onClick={handleLoadBaseline}
+                    disabled={isCalculating}
+                    title="Use an exported results GeoTIFF as the baseline"
+                    className="py-2 px-3 bg-gray-700 hover:bg-gray-600 disabled:bg-gray-700 disabled:text-gray-500 disabled:cursor-not-allowed text-white text-sm rounded-lg transition-colors"
+                  >
+                    Load Baseline
+                  </button>
+                </div>
+                <button
+                  onClick={handleExportComparison}
+                  disabled={isCalculating || !hasBaseline}
+                  title={!hasBaseline ? 'Set or load a baseline first' : undefined}
+                  className="w-full py-2 px-3 bg-gray-700 hover:bg-gray-600 disabled:bg-gray-700 disabled:text-gray-500 disabled:cursor-not-allowed text-white text-sm rounded-lg transition-colors"
+                >
+                  Compare with Baseline (GeoTIFF)
+                </button>
               </div>
             )}
           </div>
@@ -657,6 +965,8 @@ function App() {
         currentStep={progressData.current_step}
         totalSteps={progressData.total_steps}
         currentStepNumber={progressData.current_step_number}
+        onCancel={handleCancelCalculation}
+        canCancel={true}
       />
 
       {/* Performance Settings Modal */}
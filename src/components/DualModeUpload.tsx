@@ -30,6 +30,7 @@ const DualModeUpload: React.FC<DualModeUploadProps> = ({
         title: 'Select DTM File',
         filters: [
           { name: 'Raster Files', extensions: ['tif', 'tiff'] },
+          { name: 'Point Clouds', extensions: ['las', 'laz'] },
         ],
       });
       
@@ -54,6 +55,7 @@ const DualModeUpload: React.FC<DualModeUploadProps> = ({
         title: 'Select DSM File',
         filters: [
           { name: 'Raster Files', extensions: ['tif', 'tiff'] },
+          { name: 'Point Clouds', extensions: ['las', 'laz'] },
         ],
       });
       
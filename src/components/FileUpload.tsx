@@ -17,6 +17,9 @@ const FileUpload: React.FC<FileUploadProps> = ({ onFilesSelected }) => {
         filters: [{
           name: 'Raster files',
           extensions: ['tif', 'tiff', 'img', 'hgt', 'asc']
+        }, {
+          name: 'Point clouds',
+          extensions: ['las', 'laz']
         }]
       });
 
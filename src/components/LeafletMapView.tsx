@@ -35,6 +35,11 @@ interface AllSummaryData {
   afternoon_shadow_hours: number[][];
   daily_solar_hours: number[][];
   total_available_solar_hours: number[][];
+  sky_view_factor?: number[][] | null;
+  direct_irradiation?: number[][] | null;
+  diffuse_irradiation?: number[][] | null;
+  global_irradiation?: number[][] | null;
+  effective_sun_hours?: number[][] | null;
   bounds: RasterBounds;
   transform: number[];
 }
@@ -456,6 +461,15 @@ const LeafletMapView: React.FC<MapViewProps> = ({
         const afternoonHours = getRasterValueAtLatLng(e.latlng, allSummaryData.afternoon_shadow_hours);
         const dailySolarHours = getRasterValueAtLatLng(e.latlng, allSummaryData.daily_solar_hours);
         const totalAvailableSolar = getRasterValueAtLatLng(e.latlng, allSummaryData.total_available_solar_hours);
+        const skyViewFactor = allSummaryData.sky_view_factor
+          ? getRasterValueAtLatLng(e.latlng, allSummaryData.sky_view_factor)
+          : null;
+        const globalIrradiation = allSummaryData.global_irradiation
+          ? getRasterValueAtLatLng(e.latlng, allSummaryData.global_irradiation)
+          : null;
+        const effectiveSunHours = allSummaryData.effective_sun_hours
+          ? getRasterValueAtLatLng(e.latlng, allSummaryData.effective_sun_hours)
+          : null;
 
         if (shadowValue !== null) {
           // Calculate number of analysis days: total_available_solar / daily_solar_hours
@@ -491,6 +505,18 @@ const LeafletMapView: React.FC<MapViewProps> = ({
               <div style="margin-bottom: 12px; font-size: 13px; color: #4b5563;">
                 Average solar day length: <span style="font-weight: bold;">${dailySolarHours ? dailySolarHours.toFixed(1) : '0.0'}h</span>
               </div>
+              ${skyViewFactor !== null ? `
+              <div style="margin-bottom: 12px; font-size: 13px; color: #4b5563;">
+                Sky view factor: <span style="font-weight: bold;">${skyViewFactor.toFixed(2)}</span>
+              </div>` : ''}
+              ${globalIrradiation !== null ? `
+              <div style="margin-bottom: 12px; font-size: 13px; color: #4b5563;">
+                ${effectiveSunHours !== null ? 'Weather' : 'Clear-sky'} irradiation: <span style="font-weight: bold;">${globalIrradiation.toFixed(1)} kWh/m²</span>
+              </div>` : ''}
+              ${effectiveSunHours !== null ? `
+              <div style="margin-bottom: 12px; font-size: 13px; color: #4b5563;">
+                Effective sun hours: <span style="font-weight: bold;">${effectiveSunHours.toFixed(1)} h</span>
+              </div>` : ''}
               <div style="display: grid; grid-template-columns: 1fr 1fr; gap: 8px; font-size: 13px; margin-bottom: 12px;">
                 <div style="background: #fef3c7; padding: 8px; border-radius: 4px;">
                   <div style="font-weight: bold; color: #92400e; font-size: 11px;">SHADOW HOURS/DAY</div>
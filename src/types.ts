@@ -1,5 +1,13 @@
 export type ShadowQuality = 'Fast' | 'Normal' | 'High' | 'Scientific';
 
+export type ShadowAlgorithm = 'RayMarching' | 'HorizonProfile' | 'SweepLine';
+
+export type ResultStorage = 'Memory' | 'Disk' | 'Auto';
+
+export type RayTraversal = 'FixedStep' | 'GridExact';
+export type SlopeSource = 'Dsm' | 'Dtm';
+export type RoofShape = 'Flat' | 'Gable' | 'Hip';
+
 export interface Config {
   dtm_path: string;
   dsm_path: string;
@@ -11,8 +19,109 @@ export interface Config {
   angle_precision: number;
   shadow_quality: ShadowQuality;
   cpu_cores?: number; // Optional number of CPU cores to use
+  shadow_algorithm?: ShadowAlgorithm; // Defaults to RayMarching
+  horizon_sectors?: number; // Azimuth sectors for HorizonProfile (defaults by quality)
+  result_storage?: ResultStorage; // Defaults to Auto (disk once the time series gets large)
+  storage_dir?: string; // Directory for disk-backed results (defaults to system temp dir)
+  ray_traversal?: RayTraversal; // Defaults by quality: Fast uses FixedStep, others GridExact
+  curvature_refraction?: boolean; // Earth curvature and atmospheric refraction correction (defaults to off)
+  solar_disk_samples?: number; // Rays across the solar disk for penumbra (defaults to 1, a point sun)
+  vegetation_path?: string; // Canopy or classification raster on the DSM grid (defaults to none, all opaque)
+  vegetation_classes?: number[]; // Raster values counted as vegetation (defaults to any value > 0)
+  leaf_on_transmissivity?: number; // Light kept per metre of canopy with leaves (defaults to 0.8)
+  leaf_off_transmissivity?: number; // Light kept per metre of bare canopy (defaults to 0.95)
+  leaf_on_date?: string; // "MM-DD" when leaves come out (defaults to 05-01)
+  leaf_off_date?: string; // "MM-DD" when leaves drop (defaults to 10-31)
+  facade_min_wall_height?: number; // Height jump treated as a wall in facade analysis (defaults to 2.5 m)
+  facade_level_spacing?: number; // Vertical spacing of facade samples (defaults to 1.0 m)
+  sky_view_factor?: boolean; // Add a sky view factor summary layer (defaults to off)
+  sky_view_directions?: number; // Azimuths traced for the sky view factor (defaults by quality)
+  irradiance?: boolean; // Add clear-sky direct, diffuse and global irradiation layers (defaults to off)
+  slope_source?: SlopeSource; // Raster slope and aspect are derived from (defaults to DSM)
+  slope_aspect?: boolean; // Add slope and aspect layers (defaults to off)
+  self_shading?: boolean; // Shade cells whose surface faces away from the sun (defaults to off)
+  incidence_weighting?: boolean; // Direct irradiation on the sloped surface instead of horizontal (defaults to off)
+  roof_min_height?: number; // Height above terrain counted as building roof (defaults to 2.5 m)
+  roof_angle_tolerance?: number; // Normal deviation allowed within one roof face in degrees (defaults to 10)
+  roof_min_area?: number; // Smallest roof face footprint kept (defaults to 5 m²)
+  roof_usable_sun_fraction?: number; // Share of analysis hours a usable roof cell is sunlit (defaults to 0.8)
+  pv_array?: PvArray; // PV system for yield estimates
+  weather_path?: string; // EPW or TMY CSV weighting timestamps by irradiance (defaults to none, always sunny)
+  point_cloud_resolution?: number; // Cell size in metres when gridding LAS/LAZ inputs (defaults to 1.0)
+  proposed_buildings?: BuildingFootprint[]; // Footprints burned into the DSM for what-if runs (defaults to none)
+}
+
+export interface BuildingFootprint {
+  polygon: number[][]; // Outline in DSM coordinates
+  holes?: number[][][]; // Interior rings such as courtyards, left as surveyed (defaults to none)
+  height: number; // Eaves height above the mean terrain under the footprint
+  roof_shape?: RoofShape; // Defaults to Flat
+  roof_height?: number; // Ridge rise above the eaves for pitched roofs (defaults to 3.0 m)
+}
+
+export interface PvArray {
+  polygon?: number[][]; // Array outline (defaults to the AOI)
+  tilt: number; // Module tilt from horizontal in degrees
+  azimuth: number; // Direction the modules face, degrees clockwise from north
+  module_efficiency: number; // Fraction, e.g. 0.20
+  temperature_coefficient: number; // %/°C, e.g. -0.35
+  inverter_losses: number; // Fraction, e.g. 0.04
+  noct?: number; // Nominal operating cell temperature (defaults to 45 °C)
+  ambient_temperature?: number; // Air temperature for the cell model (defaults to 20 °C)
+  albedo?: number; // Ground reflectance (defaults to 0.2)
+}
+
+export interface PvMonthlyYield {
+  year: number;
+  month: number;
+  ac_energy: number;
+  unshaded_ac_energy: number;
+  shading_loss_percent: number;
+}
+
+export interface PvYieldExport {
+  monthly_path: string;
+  hourly_path: string;
+  module_area: number;
+  peak_power: number;
+  total_ac_energy: number;
+  unshaded_ac_energy: number;
+  shading_loss_percent: number;
+  specific_yield: number;
+  monthly: PvMonthlyYield[];
+}
+
+export interface ComparisonSummary {
+  sun_hours_threshold: number; // Mean daily sun hours a cell needs to pass
+  cells: number;
+  area: number; // m²
+  mean_added_shadow_hours: number;
+  max_added_shadow_hours: number;
+  cells_more_shadow: number;
+  cells_less_shadow: number;
+  mean_lost_solar_efficiency: number; // Fraction, 0-1
+  baseline_failing_cells: number;
+  scenario_failing_cells: number;
+  newly_failing_cells: number;
+  newly_failing_area: number; // m²
+  newly_passing_cells: number;
 }
 
+export interface ComparisonExport extends ComparisonSummary {
+  path: string;
+}
+
+export interface TimelapseExport {
+  frames: number;
+  frame_directory: string; // Numbered PNG frames
+  gif_path: string;
+}
+
+// Error of a command that can be cancelled (calculation, facades, time-lapse)
+export type CommandError =
+  | { kind: 'cancelled' }
+  | { kind: 'failed'; message: string };
+
 export interface TimeConfig {
   start_date: string;
   end_date: string;